@@ -2,13 +2,21 @@ use std::env::current_dir;
 use std::fs::create_dir_all;
 
 use cosmwasm_schema::{export_schema, remove_schemas, schema_for};
+use cw20::{
+    AllAccountsResponse, AllAllowancesResponse, AllowanceResponse, BalanceResponse,
+    TokenInfoResponse,
+};
 
+use terra_deposit_withdraw::fees::AccruedFees;
 use terra_deposit_withdraw::msg::{
-    ActivatableResponse, BalanceResponse, ClaimableResponse, ConfigResponse, ExecuteMsg,
-    InfoResponse, InstantiateMsg, PermissionResponse, QueryMsg, TimestampResponse,
-    TotalCapResponse, UnlockableResponse,
+    ActivatableResponse, ClaimableResponse, ConfigResponse, ExecuteMsg, HighWaterMarkResponse,
+    InfoResponse, InstantiateMsg, MigrateMsg, NextSwapResponse, PendingConfigChangeResponse,
+    PendingOwnerResponse, PermissionResponse, PermissionsResponse, QueryMsg, ReceiveMsg,
+    ShutdownBasketResponse, SimulateSwapResponse, StrategyPresetsResponse, TimestampResponse,
+    TotalCapResponse, UnlockableResponse, WithdrawalRequestsResponse,
 };
-use terra_deposit_withdraw::state::State;
+use terra_deposit_withdraw::nav::Nav;
+use terra_deposit_withdraw::state::{PauseFlags, SettlementStats, State};
 
 fn main() {
     let mut out_dir = current_dir().unwrap();
@@ -19,14 +27,33 @@ fn main() {
     export_schema(&schema_for!(InstantiateMsg), &out_dir);
     export_schema(&schema_for!(ExecuteMsg), &out_dir);
     export_schema(&schema_for!(QueryMsg), &out_dir);
+    export_schema(&schema_for!(MigrateMsg), &out_dir);
+    export_schema(&schema_for!(ReceiveMsg), &out_dir);
     export_schema(&schema_for!(State), &out_dir);
     export_schema(&schema_for!(InfoResponse), &out_dir);
     export_schema(&schema_for!(ConfigResponse), &out_dir);
     export_schema(&schema_for!(BalanceResponse), &out_dir);
     export_schema(&schema_for!(TotalCapResponse), &out_dir);
+    export_schema(&schema_for!(Nav), &out_dir);
     export_schema(&schema_for!(ActivatableResponse), &out_dir);
     export_schema(&schema_for!(ClaimableResponse), &out_dir);
+    export_schema(&schema_for!(PauseFlags), &out_dir);
+    export_schema(&schema_for!(SimulateSwapResponse), &out_dir);
+    export_schema(&schema_for!(NextSwapResponse), &out_dir);
+    export_schema(&schema_for!(ShutdownBasketResponse), &out_dir);
+    export_schema(&schema_for!(SettlementStats), &out_dir);
+    export_schema(&schema_for!(PendingOwnerResponse), &out_dir);
+    export_schema(&schema_for!(PendingConfigChangeResponse), &out_dir);
     export_schema(&schema_for!(PermissionResponse), &out_dir);
+    export_schema(&schema_for!(PermissionsResponse), &out_dir);
     export_schema(&schema_for!(UnlockableResponse), &out_dir);
     export_schema(&schema_for!(TimestampResponse), &out_dir);
+    export_schema(&schema_for!(WithdrawalRequestsResponse), &out_dir);
+    export_schema(&schema_for!(StrategyPresetsResponse), &out_dir);
+    export_schema(&schema_for!(AccruedFees), &out_dir);
+    export_schema(&schema_for!(HighWaterMarkResponse), &out_dir);
+    export_schema(&schema_for!(TokenInfoResponse), &out_dir);
+    export_schema(&schema_for!(AllowanceResponse), &out_dir);
+    export_schema(&schema_for!(AllAllowancesResponse), &out_dir);
+    export_schema(&schema_for!(AllAccountsResponse), &out_dir);
 }
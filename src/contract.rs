@@ -1,53 +1,113 @@
 use crate::ContractError::{
-    DivideByZeroError, Insufficient, Invalidate, Locked, Paused, Unauthorized,
+    AllocationExceedsMax, AlreadyBalanced, BidNotFound, BpsAboveMax, DivideByZeroError, Downgrade,
+    DuplicatePremiumSlot, EmptyPresetName, InsufficientUnlockedCollateral, InvalidBps,
+    InvalidContract, InvalidFunds, InvalidPremiumSlot, InvalidPriceBounds, InvalidRetractAmount,
+    InvalidSwapRoute, MissingRole, NoAstroportPair, NoConfigChange, NoIdleUst,
+    NoPendingConfigChange, NoPendingOwner, NoTargetAllocation, NotPendingOwner, NotRequester,
+    NotShutDown, NothingToClaim, NothingToSwap, NothingToUnlock, OwnerAdminRequired, OwnerRequired,
+    OwnerUnchanged, OwnershipProposalExpired, Paused, PermissionUnchanged, PriceOutOfBounds,
+    ShutDown, ShutdownBidsRemaining, SlippageExceeded, StalePrice, StrategyAndPreset,
+    SwapAmountExceedsMax, SwapCooldown, TimelockNotExpired, UnknownPreset, UnknownReply,
+    UnsupportedToken, WithdrawLocked, WithdrawalFilled, WithdrawalNotFilled, WithdrawalNotFound,
+    ZeroAllocation, ZeroAmount, ZeroShare, ZeroWithdrawAmount,
 };
 
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
 use cosmwasm_std::Order::Ascending;
 use cosmwasm_std::{
-    attr, to_binary, Addr, BankMsg, Binary, Coin, CosmosMsg, Decimal, Deps, DepsMut, Env, Fraction,
-    MessageInfo, Order, Response, StdResult, Timestamp, Uint128, Uint256, WasmMsg,
+    attr, from_binary, to_binary, Addr, BankMsg, Binary, Coin, ContractResult, CosmosMsg, Decimal,
+    Decimal256, Deps, DepsMut, Env, Event, MessageInfo, Order, Reply, Response, StdError,
+    StdResult, Storage, SubMsg, Timestamp, Uint128, Uint256, WasmMsg,
 };
-use cw2::set_contract_version;
-use cw_storage_plus::U32Key;
+use cw2::{get_contract_version, set_contract_version};
+use cw20::{Cw20ReceiveMsg, Expiration};
+use cw20_base::allowances::{
+    execute_burn_from, execute_decrease_allowance, execute_increase_allowance, execute_send_from,
+    execute_transfer_from, query_allowance,
+};
+use cw20_base::contract::{
+    execute_burn, execute_send, execute_transfer, query_balance, query_token_info,
+};
+use cw20_base::enumerable::{query_all_accounts, query_all_allowances};
+use cw20_base::state::{TokenInfo, TOKEN_INFO};
+use cw_storage_plus::{Bound, U32Key, U64Key};
+use semver::Version;
 use std::convert::{TryFrom, TryInto};
-use std::ops::Mul;
-use std::str::FromStr;
 
 use crate::error::ContractError;
+use crate::fees::{
+    collect_fees, compute_fees, AccruedFees, MAX_MANAGEMENT_FEE_BPS, MAX_PERFORMANCE_FEE_BPS,
+};
+use crate::migration::{
+    default_swap_route, migrate_v0_1_0, DEFAULT_MAX_PREMIUM_SLOT, DEFAULT_MAX_PRICE_AGE,
+    DEFAULT_SHARE_NAME, DEFAULT_SHARE_SYMBOL, DEFAULT_SWAP_TOLERANCE_BPS, DEFAULT_TIMELOCK_DELAY,
+};
 use crate::msg::AssetInfo::{NativeToken, Token};
-use crate::msg::SwapOperation::{AstroSwap, NativeSwap};
 use crate::msg::{
-    BalanceResponse, BidStrategy, ClaimableResponse, ConfigResponse, CumulativeLoanAmount,
-    Cw20BalanceResponse, EpochStateResponse, ExecuteMsg, ExternalMsg, ExternalQueryMsg,
-    InfoResponse, InstantiateMsg, KujiraBidsResponse, PermissionResponse, PriceResponse, QueryMsg,
-    TimestampResponse, TotalCapResponse, UnlockableResponse,
+    AddressPermission, Asset, BidStrategy, ClaimableResponse, ConfigResponse, CumulativeLoanAmount,
+    Cw20BalanceResponse, ExecuteMsg, ExternalMsg, ExternalQueryMsg, HighWaterMarkResponse,
+    InfoResponse, InstantiateMsg, MigrateMsg, NextSwapResponse, PendingConfigChangeResponse,
+    PendingOwnerResponse, PermissionResponse, PermissionsResponse, QueryMsg, ReceiveMsg,
+    ShutdownBasketResponse, SimulateSwapOperationsResponse, SimulateSwapResponse,
+    SimulationResponse, SlotAllocation, StrategyPreset, StrategyPresetsResponse, SwapOperation,
+    SwapVenue, TimestampResponse, TotalCapResponse, UnlockableResponse, WithdrawalRequestsResponse,
+};
+use crate::nav::{query_bids, query_bids_page, query_nav, Nav};
+use crate::rebalance::plan_rebalance;
+use crate::share::{
+    burn_share, check_transfer_lock, escrow_share, mint_share, outstanding_share, total_share,
 };
 use crate::state::{
-    Permission, State, TokenRecord, BALANCES, CLAIM_LIST, LAST_DEPOSIT, PERMISSIONS, STATE,
+    PauseFlags, PendingConfigChange, PendingOwner, PendingSettlement, Permission, Role,
+    ShutdownBasket, State, TokenRecord, WithdrawalRequest, CLAIM_LIST, LAST_DEPOSIT,
+    PENDING_CONFIG_CHANGE, PENDING_OWNER, PENDING_SETTLEMENTS, PERMISSIONS, SETTLEMENT_STATS,
+    SHUTDOWN_BASKET, SHUTDOWN_BID_CURSOR, STATE, STRATEGY_PRESETS, WITHDRAWAL_REQUESTS,
 };
 
 // version info for migration info
 const CONTRACT_NAME: &str = "crates.io:terra-deposit-withdraw";
 const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
 
+const MAX_BPS: u16 = 10_000;
+// Widest shortfall below the oracle value a swap may accept
+const MAX_SWAP_TOLERANCE_BPS: u16 = 1_000;
+
+// Pagination of withdrawal request queries
+const DEFAULT_LIMIT: u32 = 10;
+const MAX_LIMIT: u32 = 30;
+
+// Replies of the external calls whose proceeds are reconciled
+const SWAP_REPLY_ID: u64 = 1;
+const REDEEM_REPLY_ID: u64 = 2;
+const CLAIM_REPLY_ID: u64 = 3;
+// Failed retractions during a shutdown, reported without blocking the batch
+const RETRACT_REPLY_ID: u64 = 4;
+
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
     deps: DepsMut,
-    _env: Env,
+    env: Env,
     _info: MessageInfo,
     msg: InstantiateMsg,
 ) -> Result<Response, ContractError> {
+    let fee_collector = msg
+        .fee_collector
+        .clone()
+        .unwrap_or_else(|| msg.owner.clone());
+    let collateral_token = msg
+        .collateral_token
+        .unwrap_or_else(|| Addr::unchecked("terra1kc87mu460fwkqte29rquh4hc20m54fxwtsx7gp"));
+    let swap_route = msg
+        .swap_route
+        .unwrap_or_else(|| default_swap_route(&collateral_token));
+    validate_swap_route(&swap_route, &collateral_token)?;
     let state = State {
         owner: msg.owner.clone(),
-        total_supply: Uint128::zero(),
         locked_b_luna: Uint128::zero(),
         swap_wallet: msg.swap_wallet.clone(),
-        paused: false,
-        collateral_token: msg
-            .collateral_token
-            .unwrap_or_else(|| Addr::unchecked("terra1kc87mu460fwkqte29rquh4hc20m54fxwtsx7gp")),
+        paused: PauseFlags::default(),
+        collateral_token,
         price_oracle: msg
             .price_oracle
             .unwrap_or_else(|| Addr::unchecked("terra1cgg6yef7qcdm070qftghfulaxmllgmvk77nc7t")),
@@ -75,15 +135,74 @@ pub fn instantiate(
                 cumulative_value: Uint256::from(100_000_000_000u128),
             },
         }),
+        b_luna_discount_bps: msg.b_luna_discount_bps.unwrap_or(0),
+        max_price_age: msg.max_price_age.unwrap_or(DEFAULT_MAX_PRICE_AGE),
+        min_b_luna_price: msg.min_b_luna_price,
+        max_b_luna_price: msg.max_b_luna_price,
+        queued_ust: Uint128::zero(),
+        reserved_ust: Uint128::zero(),
+        escrowed_share: Uint128::zero(),
+        next_withdrawal_id: 1,
+        next_fill_id: 1,
+        fee_collector,
+        management_fee_bps: msg.management_fee_bps.unwrap_or(0),
+        performance_fee_bps: msg.performance_fee_bps.unwrap_or(0),
+        high_water_mark: Decimal256::one(),
+        last_fee_collection: env.block.time.seconds(),
+        target_allocation: msg.target_allocation.unwrap_or_default(),
+        rebalance_tolerance_bps: msg.rebalance_tolerance_bps.unwrap_or(0),
+        max_premium_slot: msg.max_premium_slot.unwrap_or(DEFAULT_MAX_PREMIUM_SLOT),
+        timelock_delay: msg.timelock_delay.unwrap_or(DEFAULT_TIMELOCK_DELAY),
+        swap_tolerance_bps: msg.swap_tolerance_bps.unwrap_or(DEFAULT_SWAP_TOLERANCE_BPS),
+        swap_route,
+        astroport_pair: msg.astroport_pair,
+        max_swap_amount: msg.max_swap_amount.filter(|amount| !amount.is_zero()),
+        swap_cooldown: msg.swap_cooldown.unwrap_or(0),
+        last_swap: 0,
+        shutdown: false,
     };
+    check_bps("b_luna_discount_bps", state.b_luna_discount_bps)?;
+    check_max_bps(
+        "management_fee_bps",
+        state.management_fee_bps,
+        MAX_MANAGEMENT_FEE_BPS,
+    )?;
+    check_max_bps(
+        "performance_fee_bps",
+        state.performance_fee_bps,
+        MAX_PERFORMANCE_FEE_BPS,
+    )?;
+    check_bps("rebalance_tolerance_bps", state.rebalance_tolerance_bps)?;
+    check_max_bps(
+        "swap_tolerance_bps",
+        state.swap_tolerance_bps,
+        MAX_SWAP_TOLERANCE_BPS,
+    )?;
+    validate_allocation(&state.target_allocation, state.max_premium_slot)?;
+    state.bid_strategy.validate()?;
+    check_price_bounds(&state)?;
     set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
     STATE.save(deps.storage, &state)?;
+    TOKEN_INFO.save(
+        deps.storage,
+        &TokenInfo {
+            name: msg
+                .share_name
+                .unwrap_or_else(|| DEFAULT_SHARE_NAME.to_string()),
+            symbol: msg
+                .share_symbol
+                .unwrap_or_else(|| DEFAULT_SHARE_SYMBOL.to_string()),
+            decimals: 6,
+            total_supply: Uint128::zero(),
+            mint: None,
+        },
+    )?;
     PERMISSIONS.save(
         deps.storage,
-        deps.api
-            .addr_canonicalize(&msg.owner.to_string())?
-            .as_slice(),
-        &Permission { submit_bid: true },
+        deps.api.addr_canonicalize(msg.owner.as_ref())?.as_slice(),
+        &Permission {
+            roles: vec![Role::Admin],
+        },
     )?;
     Ok(Response::new()
         .add_attribute("method", "instantiate")
@@ -98,59 +217,220 @@ pub fn execute(
     info: MessageInfo,
     msg: ExecuteMsg,
 ) -> Result<Response, ContractError> {
+    if STATE.load(deps.storage)?.shutdown && !allowed_after_shutdown(&msg) {
+        return Err(ShutDown {});
+    }
     match msg {
         // Deposit UST to vault
-        ExecuteMsg::Deposit {} => deposit(deps, env, info),
+        ExecuteMsg::Deposit { min_shares_out } => deposit(deps, env, info, min_shares_out),
+        // Deposit aUST to vault
+        ExecuteMsg::Receive(cw20_msg) => receive(deps, env, info, cw20_msg),
         // Withdraw UST from vault
-        ExecuteMsg::WithdrawUst { share } => withdraw_ust(deps, env, info, share),
+        ExecuteMsg::WithdrawUst {
+            share,
+            min_amount_out,
+        } => withdraw_ust(deps, env, info, share, min_amount_out),
         // Withdraw bLuna from Vault
-        ExecuteMsg::WithdrawBLuna { share } => withdraw_b_luna(deps, env, info, share),
+        ExecuteMsg::WithdrawBLuna {
+            share,
+            min_amount_out,
+        } => withdraw_b_luna(deps, env, info, share, min_amount_out),
         // Submit bid with amount and premium slot from service
-        // Only owner can execute
+        // Only bidders can execute
         ExecuteMsg::SubmitBid {
             amount,
             premium_slot,
-        } => submit_bid(deps, info, amount, premium_slot),
+            strategy,
+            preset,
+        } => submit_bid(deps, info, amount, premium_slot, strategy, preset),
+        // Submit bids on several premium slots at once
+        ExecuteMsg::SubmitBids { allocations } => submit_bids(deps, info, allocations),
+        // Retract a bid fully or partially
+        ExecuteMsg::RetractBid { bid_idx, amount } => retract_bid(deps, env, info, bid_idx, amount),
+        // Move bids towards the target allocation
+        ExecuteMsg::Rebalance {} => rebalance(deps, env),
+        // Queue a UST withdrawal at the current share price
+        ExecuteMsg::RequestWithdrawal { share } => request_withdrawal(deps, env, info, share),
+        // Fill queued withdrawals from idle UST
+        ExecuteMsg::ProcessWithdrawals { limit } => process_withdrawals(deps, env, limit),
+        ExecuteMsg::ClaimWithdrawal { id } => claim_withdrawal(deps, info, id),
+        ExecuteMsg::CancelWithdrawal { id } => cancel_withdrawal(deps, env, info, id),
+        ExecuteMsg::CollectFees {} => execute_collect_fees(deps, env),
         // Withdraw all liquidated bLuna from Anchor
         ExecuteMsg::ClaimLiquidation {} => claim_liquidation(deps, env, info),
         ExecuteMsg::Unlock {} => unlock(deps, env, info),
-        ExecuteMsg::Swap {} => swap(deps, env, info),
-        ExecuteMsg::SetPermission {
-            address,
-            new_permission,
-        } => set_permission(deps, info, address, new_permission),
-        ExecuteMsg::UpdateConfig {
-            owner,
-            paused,
+        ExecuteMsg::Swap {
+            amount,
+            minimum_receive,
+            venue,
+        } => swap(deps, env, info, amount, minimum_receive, venue),
+        ExecuteMsg::SetStrategyPreset { name, strategy } => {
+            set_strategy_preset(deps, info, name, strategy)
+        }
+        ExecuteMsg::RemoveStrategyPreset { name } => remove_strategy_preset(deps, info, name),
+        ExecuteMsg::GrantRole { address, role } => grant_role(deps, info, address, role),
+        ExecuteMsg::RevokeRole { address, role } => revoke_role(deps, info, address, role),
+        ExecuteMsg::ProposeOwner { new_owner, expiry } => {
+            propose_owner(deps, env, info, new_owner, expiry)
+        }
+        ExecuteMsg::AcceptOwnership {} => accept_ownership(deps, env, info),
+        ExecuteMsg::CancelOwnershipProposal {} => cancel_ownership_proposal(deps, info),
+        ExecuteMsg::QueueConfigChange {
             swap_wallet,
             lock_period,
             withdraw_lock,
             bid_strategy,
-        } => update_config(
+            timelock_delay,
+            swap_route,
+            astroport_pair,
+        } => queue_config_change(
             deps,
+            env,
             info,
-            owner,
-            paused,
             swap_wallet,
             lock_period,
             withdraw_lock,
             bid_strategy,
+            timelock_delay,
+            swap_route,
+            astroport_pair,
+        ),
+        ExecuteMsg::ExecuteConfigChange {} => execute_config_change(deps, env, info),
+        ExecuteMsg::CancelConfigChange {} => cancel_config_change(deps, info),
+        ExecuteMsg::EmergencyShutdown {} => emergency_shutdown(deps, info),
+        ExecuteMsg::RetractShutdownBids { limit } => retract_shutdown_bids(deps, env, limit),
+        ExecuteMsg::FinalizeShutdown {} => finalize_shutdown(deps, env),
+        ExecuteMsg::RedeemShutdownShare { share } => redeem_shutdown_share(deps, info, share),
+        ExecuteMsg::UpdateConfig {
+            paused,
+            b_luna_discount_bps,
+            max_price_age,
+            min_b_luna_price,
+            max_b_luna_price,
+            fee_collector,
+            management_fee_bps,
+            performance_fee_bps,
+            target_allocation,
+            rebalance_tolerance_bps,
+            max_premium_slot,
+            swap_tolerance_bps,
+            max_swap_amount,
+            swap_cooldown,
+        } => update_config(
+            deps,
+            env,
+            info,
+            paused,
+            b_luna_discount_bps,
+            max_price_age,
+            min_b_luna_price,
+            max_b_luna_price,
+            fee_collector,
+            management_fee_bps,
+            performance_fee_bps,
+            target_allocation,
+            rebalance_tolerance_bps,
+            max_premium_slot,
+            swap_tolerance_bps,
+            max_swap_amount,
+            swap_cooldown,
         ),
+        ExecuteMsg::Transfer { recipient, amount } => {
+            check_transfer_lock(deps.storage, deps.api, &env, info.sender.as_str())?;
+            Ok(execute_transfer(deps, env, info, recipient, amount)?)
+        }
+        ExecuteMsg::Burn { amount } => Ok(execute_burn(deps, env, info, amount)?),
+        ExecuteMsg::Send {
+            contract,
+            amount,
+            msg,
+        } => {
+            check_transfer_lock(deps.storage, deps.api, &env, info.sender.as_str())?;
+            Ok(execute_send(deps, env, info, contract, amount, msg)?)
+        }
+        ExecuteMsg::IncreaseAllowance {
+            spender,
+            amount,
+            expires,
+        } => Ok(execute_increase_allowance(
+            deps, env, info, spender, amount, expires,
+        )?),
+        ExecuteMsg::DecreaseAllowance {
+            spender,
+            amount,
+            expires,
+        } => Ok(execute_decrease_allowance(
+            deps, env, info, spender, amount, expires,
+        )?),
+        ExecuteMsg::TransferFrom {
+            owner,
+            recipient,
+            amount,
+        } => {
+            check_transfer_lock(deps.storage, deps.api, &env, &owner)?;
+            Ok(execute_transfer_from(
+                deps, env, info, owner, recipient, amount,
+            )?)
+        }
+        ExecuteMsg::SendFrom {
+            owner,
+            contract,
+            amount,
+            msg,
+        } => {
+            check_transfer_lock(deps.storage, deps.api, &env, &owner)?;
+            Ok(execute_send_from(
+                deps, env, info, owner, contract, amount, msg,
+            )?)
+        }
+        ExecuteMsg::BurnFrom { owner, amount } => {
+            Ok(execute_burn_from(deps, env, info, owner, amount)?)
+        }
     }
 }
 
-fn deposit(deps: DepsMut, env: Env, info: MessageInfo) -> Result<Response, ContractError> {
-    let mut state = STATE.load(deps.storage)?;
-    if state.paused {
-        return Err(Paused {});
-    }
+// Winding down bids, redemptions, filled withdrawal claims, share transfers and admin messages
+// outlive a shutdown
+fn allowed_after_shutdown(msg: &ExecuteMsg) -> bool {
+    matches!(
+        msg,
+        ExecuteMsg::RetractShutdownBids { .. }
+            | ExecuteMsg::FinalizeShutdown {}
+            | ExecuteMsg::RedeemShutdownShare { .. }
+            | ExecuteMsg::ClaimWithdrawal { .. }
+            | ExecuteMsg::GrantRole { .. }
+            | ExecuteMsg::RevokeRole { .. }
+            | ExecuteMsg::ProposeOwner { .. }
+            | ExecuteMsg::AcceptOwnership {}
+            | ExecuteMsg::CancelOwnershipProposal {}
+            | ExecuteMsg::Transfer { .. }
+            | ExecuteMsg::Burn { .. }
+            | ExecuteMsg::Send { .. }
+            | ExecuteMsg::IncreaseAllowance { .. }
+            | ExecuteMsg::DecreaseAllowance { .. }
+            | ExecuteMsg::TransferFrom { .. }
+            | ExecuteMsg::SendFrom { .. }
+            | ExecuteMsg::BurnFrom { .. }
+    )
+}
+
+fn deposit(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    min_shares_out: Option<Uint128>,
+) -> Result<Response, ContractError> {
+    let state = STATE.load(deps.storage)?;
+    check_not_paused(state.paused.deposit, "deposit")?;
     if info.funds.is_empty() {
+        // Leave UST reserved or owed to the withdrawal queue in the vault
         let uusd_balance = deps
             .querier
             .query_balance(&env.contract.address, "uusd")?
-            .amount;
+            .amount
+            .saturating_sub(state.reserved_ust + state.queued_ust);
         return if uusd_balance.is_zero() {
-            Err(Insufficient {})
+            Err(NoIdleUst {})
         } else {
             Ok(Response::new()
                 .add_attributes(vec![
@@ -168,266 +448,563 @@ fn deposit(deps: DepsMut, env: Env, info: MessageInfo) -> Result<Response, Contr
                 })))
         };
     }
-    let mut share: Uint128 = info.funds[0].amount;
+    let amount = info.funds[0].amount;
     // Only UST and non-zero amount
-    if info.funds.len() != 1 || info.funds[0].denom != "uusd" || share.is_zero() {
-        return Err(Invalidate {});
+    if info.funds.len() != 1 || info.funds[0].denom != "uusd" || amount.is_zero() {
+        return Err(InvalidFunds {
+            expected_denom: "uusd".to_string(),
+        });
     }
-    let msg_sender = info.sender.to_string().to_lowercase();
-    LAST_DEPOSIT.save(
-        deps.storage,
-        deps.api.addr_canonicalize(&msg_sender)?.as_slice(),
-        &env.block.time,
-    )?;
+    let mut nav = query_nav(deps.as_ref(), &env, &state)?;
+    check_price(&state, &env, &nav)?;
     // UST in vault
-    let uusd_balance = deps
-        .querier
-        .query_balance(&env.contract.address, "uusd")?
-        .amount;
-    let mut usd_balance = uusd_balance - share;
-    let a_ust_balance_response: Cw20BalanceResponse = deps.querier.query_wasm_smart(
-        state.a_ust.to_string(),
-        &ExternalQueryMsg::Balance {
-            address: env.contract.address.to_string(),
-        },
-    )?;
-    let mut a_ust_balance = a_ust_balance_response.balance;
-    // bLuna in vault
-    let b_luna_balance_response: Cw20BalanceResponse = deps.querier.query_wasm_smart(
-        state.collateral_token.to_string(),
-        &ExternalQueryMsg::Balance {
-            address: env.contract.address.to_string(),
-        },
-    )?;
-    let mut b_luna_balance = b_luna_balance_response.balance;
-    let mut start_after: Option<u64> = Some(0u64);
-    // Iterate all valid bids
-    loop {
-        let res: KujiraBidsResponse = deps.querier.query_wasm_smart(
-            state.kujira_a_ust_vault.to_string(),
-            &ExternalQueryMsg::BidsByUser {
-                collateral_token: state.collateral_token.to_string(),
-                bidder: env.contract.address.to_string(),
-                start_after,
-                limit: Some(31),
-            },
-        )?;
-        for item in &res.bids {
-            a_ust_balance += item.amount;
-            if let Some(proxied_bid) = item.proxied_bid.as_ref() {
-                // Waiting UST for liquidation
-                usd_balance += Uint128::try_from(proxied_bid.amount)?;
-                // Pending bLuna in Anchor
-                b_luna_balance += Uint128::try_from(proxied_bid.pending_liquidated_collateral)?;
+    let uusd_balance = nav.idle_ust;
+    nav.idle_ust = uusd_balance - amount;
+    let total_cap = nav.total_cap()?;
+    let share = issue_share(deps, &env, &info.sender, amount, total_cap, min_shares_out)?;
+    let mut response = Response::new().add_attributes(vec![
+        attr("action", "deposit"),
+        attr("from", info.sender),
+        attr("amount", amount),
+        attr("share", share),
+    ]);
+    // Keep UST owed to the withdrawal queue in the vault
+    let stable_amount = uusd_balance.saturating_sub(nav.queued_ust);
+    if !stable_amount.is_zero() {
+        response = response.add_message(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: state.anchor_market.to_string(),
+            msg: to_binary(&ExternalMsg::DepositStable {})?,
+            funds: vec![Coin {
+                denom: "uusd".to_string(),
+                amount: stable_amount,
+            }],
+        }));
+    }
+    Ok(response)
+}
+
+fn receive(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    cw20_msg: Cw20ReceiveMsg,
+) -> Result<Response, ContractError> {
+    match from_binary(&cw20_msg.msg)? {
+        ReceiveMsg::Deposit { min_shares_out } => {
+            let state = STATE.load(deps.storage)?;
+            let depositor = deps.api.addr_validate(&cw20_msg.sender)?;
+            if info.sender == state.a_ust {
+                deposit_a_ust(deps, env, state, depositor, cw20_msg.amount, min_shares_out)
+            } else if info.sender == state.collateral_token {
+                deposit_b_luna(deps, env, state, depositor, cw20_msg.amount, min_shares_out)
+            } else {
+                Err(UnsupportedToken {
+                    token: info.sender.to_string(),
+                })
             }
         }
-        if res.bids.len() < 31 {
-            break;
-        }
-        start_after = Some(res.bids.last().unwrap().idx);
     }
+}
 
-    let epoch_state_response: EpochStateResponse = deps.querier.query_wasm_smart(
-        state.anchor_market.to_string(),
-        &ExternalQueryMsg::EpochState {
-            block_height: Some(env.block.height),
-            distributed_interest: None,
-        },
-    )?;
+fn deposit_a_ust(
+    deps: DepsMut,
+    env: Env,
+    state: State,
+    depositor: Addr,
+    amount: Uint128,
+    min_shares_out: Option<Uint128>,
+) -> Result<Response, ContractError> {
+    check_not_paused(state.paused.deposit, "deposit")?;
+    if amount.is_zero() {
+        return Err(ZeroAmount {});
+    }
+    // aUST is already in vault
+    let mut nav = query_nav(deps.as_ref(), &env, &state)?;
+    check_price(&state, &env, &nav)?;
+    nav.a_ust -= amount;
+    let value = nav.a_ust_value(amount)?;
+    let total_cap = nav.total_cap()?;
+    let share = issue_share(deps, &env, &depositor, value, total_cap, min_shares_out)?;
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "deposit_a_ust"),
+        attr("from", depositor),
+        attr("amount", amount),
+        attr("value", value),
+        attr("share", share),
+    ]))
+}
 
-    let a_terra_exchange_rate = epoch_state_response.exchange_rate;
+fn deposit_b_luna(
+    deps: DepsMut,
+    env: Env,
+    state: State,
+    depositor: Addr,
+    amount: Uint128,
+    min_shares_out: Option<Uint128>,
+) -> Result<Response, ContractError> {
+    check_not_paused(state.paused.deposit, "deposit")?;
+    if amount.is_zero() {
+        return Err(ZeroAmount {});
+    }
+    // bLuna is already in vault
+    let mut nav = query_nav(deps.as_ref(), &env, &state)?;
+    check_price(&state, &env, &nav)?;
+    nav.unlocked_b_luna -= amount;
+    let value = nav
+        .b_luna_value(amount)?
+        .multiply_ratio(MAX_BPS - state.b_luna_discount_bps, MAX_BPS);
+    let total_cap = nav.total_cap()?;
+    let share = issue_share(deps, &env, &depositor, value, total_cap, min_shares_out)?;
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "deposit_b_luna"),
+        attr("from", depositor),
+        attr("amount", amount),
+        attr("value", value),
+        attr("share", share),
+    ]))
+}
 
-    // Fetch bLuna price from oracle
-    let price_response: PriceResponse = deps.querier.query_wasm_smart(
-        state.price_oracle.to_string(),
-        &ExternalQueryMsg::Price {
-            base: state.collateral_token.to_string(),
-            quote: "uusd".to_string(),
-        },
+// Mint shares worth value in UST after collecting fees, total cap excludes the deposit
+fn issue_share(
+    deps: DepsMut,
+    env: &Env,
+    depositor: &Addr,
+    value: Uint128,
+    total_cap: Uint128,
+    min_shares_out: Option<Uint128>,
+) -> Result<Uint128, ContractError> {
+    LAST_DEPOSIT.save(
+        deps.storage,
+        deps.api
+            .addr_canonicalize(&depositor.to_string().to_lowercase())?
+            .as_slice(),
+        &env.block.time,
     )?;
-    let price = price_response.rate;
-    let total_cap = Uint128::try_from(Uint256::from(b_luna_balance).mul(price))?
-        + Uint128::try_from(Uint256::from(a_ust_balance).mul(a_terra_exchange_rate))?
-        + usd_balance;
-    if !state.total_supply.is_zero() {
+    let mut state = STATE.load(deps.storage)?;
+    collect_fees(deps.storage, env, &mut state, total_cap)?;
+    let mut share = value;
+    let total_supply = outstanding_share(deps.storage)?;
+    if !total_supply.is_zero() {
         if total_cap.is_zero() {
             return Err(DivideByZeroError {});
         }
-        share = share.checked_mul(state.total_supply)? / total_cap;
+        share = share.checked_mul(total_supply)? / total_cap;
     }
-    state.total_supply += share;
-    STATE.save(deps.storage, &state)?;
-    BALANCES.update(
-        deps.storage,
-        deps.api.addr_canonicalize(&msg_sender)?.as_slice(),
-        |balance| -> StdResult<_> { Ok(balance.unwrap_or_default() + share) },
-    )?;
-    Ok(Response::new()
-        .add_attributes(vec![
-            attr("action", "deposit"),
-            attr("from", info.sender),
-            attr("amount", info.funds[0].amount),
-            attr("share", share),
-        ])
-        .add_message(CosmosMsg::Wasm(WasmMsg::Execute {
-            contract_addr: state.anchor_market.to_string(),
-            msg: to_binary(&ExternalMsg::DepositStable {})?,
-            funds: vec![Coin {
-                denom: "uusd".to_string(),
-                amount: uusd_balance,
-            }],
-        })))
+    check_slippage(min_shares_out, share)?;
+    mint_share(deps.storage, depositor, share)?;
+    Ok(share)
 }
 
-fn submit_bid(
-    deps: DepsMut,
-    info: MessageInfo,
-    amount: Uint128,
-    premium_slot: u8,
-) -> Result<Response, ContractError> {
-    let permission = PERMISSIONS
+// Reject share pricing on a stale or implausible bLuna price
+fn check_price(state: &State, env: &Env, nav: &Nav) -> Result<(), ContractError> {
+    if nav
+        .b_luna_price_updated_at
+        .saturating_add(state.max_price_age)
+        < env.block.time.seconds()
+    {
+        return Err(StalePrice {
+            updated_at: nav.b_luna_price_updated_at,
+            max_age: state.max_price_age,
+        });
+    }
+    let price = nav.b_luna_price;
+    if state.min_b_luna_price.map_or(false, |min| price < min)
+        || state.max_b_luna_price.map_or(false, |max| price > max)
+    {
+        return Err(PriceOutOfBounds { price });
+    }
+    Ok(())
+}
+
+fn check_slippage(minimum: Option<Uint128>, actual: Uint128) -> Result<(), ContractError> {
+    match minimum {
+        Some(minimum) if actual < minimum => Err(SlippageExceeded { minimum, actual }),
+        _ => Ok(()),
+    }
+}
+
+fn check_not_paused(paused: bool, operation: &str) -> Result<(), ContractError> {
+    if paused {
+        return Err(Paused {
+            operation: operation.to_string(),
+        });
+    }
+    Ok(())
+}
+
+fn check_bps(name: &str, bps: u16) -> Result<(), ContractError> {
+    if bps > MAX_BPS {
+        return Err(InvalidBps {
+            name: name.to_string(),
+            bps,
+        });
+    }
+    Ok(())
+}
+
+fn check_max_bps(name: &str, bps: u16, max: u16) -> Result<(), ContractError> {
+    if bps > max {
+        return Err(BpsAboveMax {
+            name: name.to_string(),
+            bps,
+            max,
+        });
+    }
+    Ok(())
+}
+
+fn check_price_bounds(state: &State) -> Result<(), ContractError> {
+    if let (Some(min), Some(max)) = (state.min_b_luna_price, state.max_b_luna_price) {
+        if min > max {
+            return Err(InvalidPriceBounds { min, max });
+        }
+    }
+    Ok(())
+}
+
+fn load_permission(deps: Deps, address: &Addr) -> StdResult<Permission> {
+    Ok(PERMISSIONS
         .may_load(
             deps.storage,
             deps.api
-                .addr_canonicalize(info.sender.to_string().to_lowercase().as_str())?
+                .addr_canonicalize(address.to_string().to_lowercase().as_str())?
                 .as_slice(),
         )?
-        .unwrap_or(Permission { submit_bid: false });
-    if !permission.submit_bid {
-        return Err(Unauthorized {});
+        .unwrap_or_default())
+}
+
+fn check_role(deps: Deps, sender: &Addr, role: Role) -> Result<(), ContractError> {
+    if !load_permission(deps, sender)?.has_role(role) {
+        return Err(MissingRole {
+            address: sender.to_string(),
+            role,
+        });
+    }
+    Ok(())
+}
+
+// Ownership transfer and shutdown are not delegated through roles, even to admins
+fn check_owner(state: &State, sender: &Addr) -> Result<(), ContractError> {
+    if state.owner.to_string().to_lowercase() != sender.to_string().to_lowercase() {
+        return Err(OwnerRequired {
+            address: sender.to_string(),
+        });
     }
+    Ok(())
+}
+
+// Send aUST to Kujira as a bid on premium_slot
+fn submit_bid_msg(
+    state: &State,
+    amount: Uint128,
+    premium_slot: u8,
+    strategy: BidStrategy,
+) -> Result<CosmosMsg, ContractError> {
+    if premium_slot > state.max_premium_slot {
+        return Err(InvalidPremiumSlot {
+            premium_slot,
+            max_premium_slot: state.max_premium_slot,
+        });
+    }
+    Ok(CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: state.a_ust.to_string(),
+        msg: to_binary(&ExternalMsg::Send {
+            contract: state.kujira_a_ust_vault.to_string(),
+            amount,
+            msg: to_binary(&ExternalMsg::SubmitBid {
+                collateral_token: state.collateral_token.to_string(),
+                premium_slot,
+                strategy,
+            })?,
+        })?,
+        funds: vec![],
+    }))
+}
+
+fn submit_bid(
+    deps: DepsMut,
+    info: MessageInfo,
+    amount: Uint128,
+    premium_slot: u8,
+    strategy: Option<BidStrategy>,
+    preset: Option<String>,
+) -> Result<Response, ContractError> {
+    check_role(deps.as_ref(), &info.sender, Role::Bidder)?;
     if !amount.is_zero() {
         let state = STATE.load(deps.storage)?;
+        check_not_paused(state.paused.submit_bid, "submit_bid")?;
+        let mut attributes = vec![
+            attr("action", "submit_bid"),
+            attr("from", info.sender),
+            attr("amount", amount),
+            attr("premium_slot", premium_slot.to_string()),
+        ];
+        let strategy = match (strategy, preset) {
+            (Some(_), Some(_)) => return Err(StrategyAndPreset {}),
+            (Some(strategy), None) => {
+                strategy.validate()?;
+                strategy
+            }
+            (None, Some(preset)) => {
+                let strategy = STRATEGY_PRESETS
+                    .may_load(deps.storage, &preset)?
+                    .ok_or_else(|| UnknownPreset {
+                        name: preset.clone(),
+                    })?;
+                attributes.push(attr("preset", preset));
+                strategy
+            }
+            (None, None) => state.bid_strategy.clone(),
+        };
         Ok(Response::new()
-            .add_attributes(vec![
-                attr("action", "submit_bid"),
-                attr("from", info.sender),
-                attr("amount", amount),
-                attr("premium_slot", premium_slot.to_string()),
-            ])
-            .add_message(CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: state.a_ust.to_string(),
-                msg: to_binary(&ExternalMsg::Send {
-                    contract: state.kujira_a_ust_vault.to_string(),
-                    amount,
-                    msg: to_binary(&ExternalMsg::SubmitBid {
-                        collateral_token: state.collateral_token.to_string(),
-                        premium_slot,
-                        strategy: state.bid_strategy,
-                    })?,
-                })?,
-                funds: vec![],
-            })))
+            .add_attributes(attributes)
+            .add_message(submit_bid_msg(&state, amount, premium_slot, strategy)?))
     } else {
-        Err(Insufficient {})
+        Err(ZeroAmount {})
     }
 }
 
-fn withdraw_ust(
+fn submit_bids(
     deps: DepsMut,
-    env: Env,
     info: MessageInfo,
-    share: Uint128,
+    allocations: Vec<(u8, Uint128)>,
 ) -> Result<Response, ContractError> {
-    if share.is_zero() {
-        return Err(Invalidate {});
+    check_role(deps.as_ref(), &info.sender, Role::Bidder)?;
+    if allocations.is_empty() || allocations.iter().any(|(_, amount)| amount.is_zero()) {
+        return Err(ZeroAmount {});
     }
-    let msg_sender = info.sender.to_string().to_lowercase();
-    let last_timestamp = LAST_DEPOSIT.may_load(
-        deps.storage,
-        deps.api.addr_canonicalize(&msg_sender)?.as_slice(),
-    )?;
-    let mut state = STATE.load(deps.storage)?;
-    if let Some(timestamp) = last_timestamp {
-        if timestamp.plus_seconds(state.withdraw_lock) >= env.block.time {
-            return Err(Locked {});
+    let state = STATE.load(deps.storage)?;
+    check_not_paused(state.paused.submit_bid, "submit_bid")?;
+    let mut messages = vec![];
+    let mut attributes = vec![attr("action", "submit_bids"), attr("from", info.sender)];
+    for (premium_slot, amount) in allocations {
+        messages.push(submit_bid_msg(
+            &state,
+            amount,
+            premium_slot,
+            state.bid_strategy.clone(),
+        )?);
+        attributes.push(attr("premium_slot", premium_slot.to_string()));
+        attributes.push(attr("amount", amount));
+    }
+    Ok(Response::new()
+        .add_messages(messages)
+        .add_attributes(attributes))
+}
+
+fn set_strategy_preset(
+    deps: DepsMut,
+    info: MessageInfo,
+    name: String,
+    strategy: BidStrategy,
+) -> Result<Response, ContractError> {
+    check_role(deps.as_ref(), &info.sender, Role::ConfigManager)?;
+    if name.is_empty() {
+        return Err(EmptyPresetName {});
+    }
+    strategy.validate()?;
+    STRATEGY_PRESETS.save(deps.storage, &name, &strategy)?;
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "set_strategy_preset"),
+        attr("name", name),
+        attr("activate_at_ltv", strategy.activate_at.ltv.to_string()),
+        attr(
+            "activate_at_cumulative_value",
+            strategy.activate_at.cumulative_value.to_string(),
+        ),
+        attr("deactivate_at_ltv", strategy.deactivate_at.ltv.to_string()),
+        attr(
+            "deactivate_at_cumulative_value",
+            strategy.deactivate_at.cumulative_value.to_string(),
+        ),
+    ]))
+}
+
+fn remove_strategy_preset(
+    deps: DepsMut,
+    info: MessageInfo,
+    name: String,
+) -> Result<Response, ContractError> {
+    check_role(deps.as_ref(), &info.sender, Role::ConfigManager)?;
+    if !STRATEGY_PRESETS.has(deps.storage, &name) {
+        return Err(UnknownPreset { name });
+    }
+    STRATEGY_PRESETS.remove(deps.storage, &name);
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "remove_strategy_preset"),
+        attr("name", name),
+    ]))
+}
+
+// Slots must be unique, biddable and share at most the whole bidding aUST
+fn validate_allocation(
+    target_allocation: &[SlotAllocation],
+    max_premium_slot: u8,
+) -> Result<(), ContractError> {
+    let mut total_bps = 0u32;
+    for (i, allocation) in target_allocation.iter().enumerate() {
+        if allocation.premium_slot > max_premium_slot {
+            return Err(InvalidPremiumSlot {
+                premium_slot: allocation.premium_slot,
+                max_premium_slot,
+            });
+        }
+        if allocation.bps == 0 {
+            return Err(ZeroAllocation {
+                premium_slot: allocation.premium_slot,
+            });
         }
+        if target_allocation[..i]
+            .iter()
+            .any(|other| other.premium_slot == allocation.premium_slot)
+        {
+            return Err(DuplicatePremiumSlot {
+                premium_slot: allocation.premium_slot,
+            });
+        }
+        total_bps += allocation.bps as u32;
     }
-    BALANCES.update(
-        deps.storage,
-        deps.api.addr_canonicalize(&msg_sender)?.as_slice(),
-        |balance| -> StdResult<_> { Ok(balance.unwrap_or_default().checked_sub(share)?) },
-    )?;
-    let mut uusd_balance = deps
-        .querier
-        .query_balance(&env.contract.address, "uusd")?
-        .amount;
-    let mut usd_balance = uusd_balance;
-    let a_ust_balance_response: Cw20BalanceResponse = deps.querier.query_wasm_smart(
+    if total_bps > MAX_BPS as u32 {
+        return Err(AllocationExceedsMax { total_bps });
+    }
+    Ok(())
+}
+
+fn rebalance(deps: DepsMut, env: Env) -> Result<Response, ContractError> {
+    let state = STATE.load(deps.storage)?;
+    check_not_paused(state.paused.submit_bid, "submit_bid")?;
+    if state.target_allocation.is_empty() {
+        return Err(NoTargetAllocation {});
+    }
+    let bids = query_bids(deps.as_ref(), &env, &state)?;
+    let a_ust_balance: Cw20BalanceResponse = deps.querier.query_wasm_smart(
         state.a_ust.to_string(),
         &ExternalQueryMsg::Balance {
             address: env.contract.address.to_string(),
         },
     )?;
-    let mut a_ust_balance = a_ust_balance_response.balance;
-    let b_luna_balance_response: Cw20BalanceResponse = deps.querier.query_wasm_smart(
-        state.collateral_token.to_string(),
-        &ExternalQueryMsg::Balance {
-            address: env.contract.address.to_string(),
-        },
-    )?;
-    let mut b_luna_balance = b_luna_balance_response.balance;
-    let mut start_after: Option<u64> = Some(0u64);
-    loop {
-        let res: KujiraBidsResponse = deps.querier.query_wasm_smart(
-            state.kujira_a_ust_vault.to_string(),
-            &ExternalQueryMsg::BidsByUser {
-                collateral_token: state.collateral_token.to_string(),
-                bidder: env.contract.address.to_string(),
-                start_after,
-                limit: Some(31),
-            },
-        )?;
-        for item in &res.bids {
-            a_ust_balance += item.amount;
-            if let Some(proxied_bid) = item.proxied_bid.as_ref() {
-                usd_balance += Uint128::try_from(proxied_bid.amount)?;
-                b_luna_balance += Uint128::try_from(proxied_bid.pending_liquidated_collateral)?;
-            }
-        }
-        if res.bids.len() < 31 {
-            break;
-        }
-        start_after = Some(res.bids.last().unwrap().idx);
+    let plan = plan_rebalance(
+        &bids,
+        a_ust_balance.balance,
+        &state.target_allocation,
+        state.rebalance_tolerance_bps,
+    );
+    // Within the tolerance band
+    if plan.is_empty() {
+        return Err(AlreadyBalanced {});
     }
+    let mut messages = vec![];
+    let mut attributes = vec![attr("action", "rebalance")];
+    for retraction in plan.retractions {
+        messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: state.kujira_a_ust_vault.to_string(),
+            msg: to_binary(&ExternalMsg::RetractBid {
+                bid_idx: retraction.bid_idx,
+                amount: retraction.amount,
+            })?,
+            funds: vec![],
+        }));
+        attributes.push(attr("retract_bid_idx", retraction.bid_idx.to_string()));
+        attributes.push(attr(
+            "retract_premium_slot",
+            retraction.premium_slot.to_string(),
+        ));
+        attributes.push(attr("retract_amount", retraction.retracted));
+    }
+    for (premium_slot, amount) in plan.submissions {
+        messages.push(submit_bid_msg(
+            &state,
+            amount,
+            premium_slot,
+            state.bid_strategy.clone(),
+        )?);
+        attributes.push(attr("submit_premium_slot", premium_slot.to_string()));
+        attributes.push(attr("submit_amount", amount));
+    }
+    Ok(Response::new()
+        .add_messages(messages)
+        .add_attributes(attributes))
+}
 
-    let epoch_state_response: EpochStateResponse = deps.querier.query_wasm_smart(
-        state.anchor_market.to_string(),
-        &ExternalQueryMsg::EpochState {
-            block_height: Some(env.block.height),
-            distributed_interest: None,
-        },
-    )?;
-
-    let a_terra_exchange_rate = epoch_state_response.exchange_rate;
-    let a_terra_exchange_rate = Decimal::from_ratio(
-        Uint128::try_from(a_terra_exchange_rate.numerator())?,
-        Uint128::try_from(a_terra_exchange_rate.denominator())?,
-    );
+fn retract_bid(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    bid_idx: u64,
+    amount: Option<Uint128>,
+) -> Result<Response, ContractError> {
+    check_role(deps.as_ref(), &info.sender, Role::Bidder)?;
+    let state = STATE.load(deps.storage)?;
+    check_not_paused(state.paused.submit_bid, "submit_bid")?;
+    // Only bids of the vault
+    let bid = query_bids(deps.as_ref(), &env, &state)?
+        .into_iter()
+        .find(|bid| bid.idx == bid_idx)
+        .ok_or(BidNotFound { bid_idx })?;
+    if let Some(amount) = amount {
+        if amount.is_zero() || amount > bid.amount {
+            return Err(InvalidRetractAmount {
+                amount,
+                bid_amount: bid.amount,
+            });
+        }
+    }
+    Ok(Response::new()
+        .add_message(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: state.kujira_a_ust_vault.to_string(),
+            msg: to_binary(&ExternalMsg::RetractBid { bid_idx, amount })?,
+            funds: vec![],
+        }))
+        .add_attributes(vec![
+            attr("action", "retract_bid"),
+            attr("from", info.sender),
+            attr("bid_idx", bid_idx.to_string()),
+            attr("premium_slot", bid.premium_slot.to_string()),
+            attr("amount", amount.unwrap_or(bid.amount)),
+        ]))
+}
 
-    let price_response: PriceResponse = deps.querier.query_wasm_smart(
-        state.price_oracle.to_string(),
-        &ExternalQueryMsg::Price {
-            base: state.collateral_token.to_string(),
-            quote: "uusd".to_string(),
-        },
+fn withdraw_ust(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    share: Uint128,
+    min_amount_out: Option<Uint128>,
+) -> Result<Response, ContractError> {
+    if share.is_zero() {
+        return Err(ZeroShare {});
+    }
+    let msg_sender = info.sender.to_string().to_lowercase();
+    let last_timestamp = LAST_DEPOSIT.may_load(
+        deps.storage,
+        deps.api.addr_canonicalize(&msg_sender)?.as_slice(),
     )?;
-    let price = price_response.rate;
+    let mut state = STATE.load(deps.storage)?;
+    check_not_paused(state.paused.withdraw_ust, "withdraw_ust")?;
+    if let Some(timestamp) = last_timestamp {
+        let unlock_at = timestamp.plus_seconds(state.withdraw_lock);
+        if unlock_at >= env.block.time {
+            return Err(WithdrawLocked { unlock_at });
+        }
+    }
+    let nav = query_nav(deps.as_ref(), &env, &state)?;
+    check_price(&state, &env, &nav)?;
+    let mut uusd_balance = nav.free_ust();
 
     // Calculate total cap
-    let total_cap = Uint128::try_from(Uint256::from(b_luna_balance).mul(price))?
-        + a_ust_balance.mul(a_terra_exchange_rate)
-        + usd_balance;
+    let total_cap = nav.total_cap()?;
+    collect_fees(deps.storage, &env, &mut state, total_cap)?;
+    let total_supply = outstanding_share(deps.storage)?;
 
     // Calculate exact amount from share and total cap
-    let withdraw_cap = total_cap * share / state.total_supply;
+    let withdraw_cap = total_cap.multiply_ratio(share, total_supply);
     if withdraw_cap.is_zero() {
-        return Err(Insufficient {});
+        return Err(ZeroWithdrawAmount {});
     }
     // Withdraw if UST in vault is enough
     if uusd_balance >= withdraw_cap {
-        state.total_supply -= share;
-        STATE.save(deps.storage, &state)?;
+        check_slippage(min_amount_out, withdraw_cap)?;
+        burn_share(deps.storage, &info.sender, share)?;
         Ok(Response::new()
             .add_message(CosmosMsg::Bank(BankMsg::Send {
                 to_address: msg_sender,
@@ -446,162 +1023,114 @@ fn withdraw_ust(
         // Retract bids for insufficient UST in vault
         let mut messages = vec![];
         let mut remaining_usd_balance = withdraw_cap - uusd_balance;
-        a_ust_balance = a_ust_balance_response.balance;
-        let uusd_in_a_ust = a_ust_balance.mul(a_terra_exchange_rate);
+        let mut a_ust_balance = nav.a_ust;
+        let uusd_in_a_ust = nav.a_ust_value(a_ust_balance)?;
         if remaining_usd_balance > uusd_in_a_ust {
             remaining_usd_balance -= uusd_in_a_ust;
         } else {
             remaining_usd_balance = Uint128::zero();
         }
         if !remaining_usd_balance.is_zero() {
-            start_after = Some(0u64);
-            loop {
-                let res: KujiraBidsResponse = deps.querier.query_wasm_smart(
-                    state.kujira_a_ust_vault.to_string(),
-                    &ExternalQueryMsg::BidsByUser {
-                        collateral_token: state.collateral_token.to_string(),
-                        bidder: env.contract.address.to_string(),
-                        start_after,
-                        limit: Some(31),
-                    },
-                )?;
-                for item in &res.bids {
-                    if let Some(proxied_bid) = item.proxied_bid.as_ref() {
-                        if !proxied_bid.amount.is_zero() {
-                            messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
-                                contract_addr: state.kujira_a_ust_vault.to_string(),
-                                msg: to_binary(&ExternalMsg::RetractBid { bid_idx: item.idx })?,
-                                funds: vec![],
-                            }));
-                            a_ust_balance += Uint128::try_from(proxied_bid.amount)?
-                                .mul(a_terra_exchange_rate.inv().unwrap());
-                            if proxied_bid.amount < remaining_usd_balance.into() {
-                                remaining_usd_balance -= Uint128::try_from(proxied_bid.amount)?;
-                            } else {
-                                remaining_usd_balance = Uint128::zero();
-                                break;
-                            }
-                        }
-                    } else {
-                        messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+            for item in query_bids(deps.as_ref(), &env, &state)? {
+                if let Some(proxied_bid) = item.proxied_bid.as_ref() {
+                    if !proxied_bid.amount.is_zero() {
+                        messages.push(SubMsg::new(WasmMsg::Execute {
                             contract_addr: state.kujira_a_ust_vault.to_string(),
-                            msg: to_binary(&ExternalMsg::RetractBid { bid_idx: item.idx })?,
+                            msg: to_binary(&ExternalMsg::RetractBid {
+                                bid_idx: item.idx,
+                                amount: None,
+                            })?,
                             funds: vec![],
                         }));
-                        let worth = item.amount.mul(a_terra_exchange_rate);
-                        a_ust_balance += item.amount;
-                        if worth < remaining_usd_balance {
-                            remaining_usd_balance -= worth;
+                        a_ust_balance +=
+                            nav.ust_to_a_ust(Uint128::try_from(proxied_bid.amount)?)?;
+                        if proxied_bid.amount < remaining_usd_balance.into() {
+                            remaining_usd_balance -= Uint128::try_from(proxied_bid.amount)?;
                         } else {
                             remaining_usd_balance = Uint128::zero();
-                            break;
                         }
                     }
+                } else {
+                    messages.push(SubMsg::new(WasmMsg::Execute {
+                        contract_addr: state.kujira_a_ust_vault.to_string(),
+                        msg: to_binary(&ExternalMsg::RetractBid {
+                            bid_idx: item.idx,
+                            amount: None,
+                        })?,
+                        funds: vec![],
+                    }));
+                    let worth = nav.a_ust_value(item.amount)?;
+                    a_ust_balance += item.amount;
+                    if worth < remaining_usd_balance {
+                        remaining_usd_balance -= worth;
+                    } else {
+                        remaining_usd_balance = Uint128::zero();
+                    }
                 }
-                if remaining_usd_balance.is_zero() || res.bids.len() < 31 {
+                if remaining_usd_balance.is_zero() {
                     break;
                 }
-                start_after = Some(res.bids.last().unwrap().idx);
             }
         }
         // Redeem aUST
         if !a_ust_balance.is_zero() {
-            messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: state.a_ust.to_string(),
-                msg: to_binary(&ExternalMsg::Send {
-                    contract: state.anchor_market.to_string(),
-                    amount: a_ust_balance,
-                    msg: to_binary(&ExternalMsg::RedeemStable {})?,
-                })?,
-                funds: vec![],
-            }));
-            uusd_balance += a_ust_balance.mul(a_terra_exchange_rate);
+            let settlement = PendingSettlement {
+                amount: a_ust_balance,
+                balance_before: ust_balance(deps.as_ref(), &env)?,
+                minimum_receive: Uint128::zero(),
+            };
+            PENDING_SETTLEMENTS.save(deps.storage, U64Key::new(REDEEM_REPLY_ID), &settlement)?;
+            messages.push(SubMsg::reply_on_success(
+                WasmMsg::Execute {
+                    contract_addr: state.a_ust.to_string(),
+                    msg: to_binary(&ExternalMsg::Send {
+                        contract: state.anchor_market.to_string(),
+                        amount: a_ust_balance,
+                        msg: to_binary(&ExternalMsg::RedeemStable {})?,
+                    })?,
+                    funds: vec![],
+                },
+                REDEEM_REPLY_ID,
+            ));
+            uusd_balance += nav.a_ust_value(a_ust_balance)?;
         }
-        if uusd_balance >= withdraw_cap {
-            messages.push(CosmosMsg::Bank(BankMsg::Send {
-                to_address: msg_sender.clone(),
-                amount: vec![Coin {
-                    denom: "uusd".to_string(),
-                    amount: withdraw_cap,
-                }],
-            }));
-        } else if !uusd_balance.is_zero() {
-            messages.push(CosmosMsg::Bank(BankMsg::Send {
-                to_address: msg_sender.clone(),
+        // Only UST paid out counts towards min_amount_out
+        let amount = uusd_balance.min(withdraw_cap);
+        check_slippage(min_amount_out, amount)?;
+        if !amount.is_zero() {
+            messages.push(SubMsg::new(BankMsg::Send {
+                to_address: msg_sender,
                 amount: vec![Coin {
                     denom: "uusd".to_string(),
-                    amount: uusd_balance,
+                    amount,
                 }],
             }));
         }
-        let mut unlocked_b_luna = Uint128::zero();
-        if !remaining_usd_balance.is_zero() {
-            let mut b_luna_withdraw = b_luna_balance * share * remaining_usd_balance
-                / withdraw_cap
-                / (state.total_supply
-                    - share * (withdraw_cap - remaining_usd_balance) / withdraw_cap);
-            if !b_luna_withdraw.is_zero() {
-                // swap on wallet
-                messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
-                    contract_addr: state.collateral_token.to_string(),
-                    msg: to_binary(&ExternalMsg::Send {
-                        contract: state.swap_wallet.to_string(),
-                        amount: b_luna_withdraw,
-                        msg: to_binary(&deps.api.addr_validate(&msg_sender)?)?,
-                    })?,
-                    funds: vec![],
-                }));
-                // unlock
-                if b_luna_balance_response.balance - state.locked_b_luna < b_luna_withdraw {
-                    b_luna_withdraw -= b_luna_balance_response.balance - state.locked_b_luna;
-                    let keys = CLAIM_LIST.keys(deps.storage, None, None, Order::Ascending);
-                    let mut remove_keys = Vec::new();
-                    let mut last_key = None;
-                    let mut new_claim = TokenRecord {
-                        amount: Uint128::zero(),
-                        timestamp: Timestamp::default(),
-                    };
-                    for key in keys {
-                        let claim = CLAIM_LIST.load(deps.storage, U32Key::from(key.clone()))?;
-                        if b_luna_withdraw >= claim.amount {
-                            b_luna_withdraw -= claim.amount;
-                            unlocked_b_luna += claim.amount;
-                            remove_keys.push(U32Key::from(key));
-                        } else {
-                            unlocked_b_luna += b_luna_withdraw;
-                            new_claim = claim.clone();
-                            new_claim.amount -= b_luna_withdraw;
-                            b_luna_withdraw = Uint128::zero();
-                            last_key = Some(key);
-                        }
-                        if b_luna_withdraw.is_zero() {
-                            break;
-                        }
-                    }
-                    for key in remove_keys {
-                        CLAIM_LIST.remove(deps.storage, key);
-                    }
-                    if let Some(key) = last_key {
-                        CLAIM_LIST.save(deps.storage, U32Key::from(key), &new_claim)?;
-                    }
-                    if !unlocked_b_luna.is_zero() {
-                        state.locked_b_luna -= unlocked_b_luna;
-                    }
-                }
-            }
-        }
-        state.total_supply -= share;
-        STATE.save(deps.storage, &state)?;
+        // Queue the rest until liquidated bLuna is swapped back to UST
+        let queued_share = share.multiply_ratio(withdraw_cap - amount, withdraw_cap);
+        burn_share(deps.storage, &info.sender, share - queued_share)?;
         let mut attrs = vec![
             attr("action", "withdraw"),
-            attr("to", info.sender),
+            attr("to", info.sender.clone()),
             attr("share", share),
-            attr("amount", withdraw_cap),
+            attr("amount", amount),
         ];
-        if !unlocked_b_luna.is_zero() {
-            attrs.push(attr("unlocked", unlocked_b_luna.to_string()));
+        if !queued_share.is_zero() {
+            let request = queue_withdrawal(
+                deps.storage,
+                &env,
+                &mut state,
+                info.sender,
+                queued_share,
+                withdraw_cap - amount,
+            )?;
+            attrs.push(attr("request_id", request.id.to_string()));
+            attrs.push(attr("queued", request.amount));
         }
-        Ok(Response::new().add_messages(messages).add_attributes(attrs))
+        STATE.save(deps.storage, &state)?;
+        Ok(Response::new()
+            .add_submessages(messages)
+            .add_attributes(attrs))
     }
 }
 
@@ -610,9 +1139,10 @@ fn withdraw_b_luna(
     env: Env,
     info: MessageInfo,
     share: Uint128,
+    min_amount_out: Option<Uint128>,
 ) -> Result<Response, ContractError> {
     if share.is_zero() {
-        return Err(Invalidate {});
+        return Err(ZeroShare {});
     }
     let msg_sender = info.sender.to_string().to_lowercase();
     let last_timestamp = LAST_DEPOSIT.may_load(
@@ -620,91 +1150,26 @@ fn withdraw_b_luna(
         deps.api.addr_canonicalize(&msg_sender)?.as_slice(),
     )?;
     let mut state = STATE.load(deps.storage)?;
+    check_not_paused(state.paused.withdraw_b_luna, "withdraw_b_luna")?;
     if let Some(timestamp) = last_timestamp {
-        if timestamp.plus_seconds(state.withdraw_lock) >= env.block.time {
-            return Err(Locked {});
-        }
-    }
-    BALANCES.update(
-        deps.storage,
-        deps.api.addr_canonicalize(&msg_sender)?.as_slice(),
-        |balance| -> StdResult<_> { Ok(balance.unwrap_or_default().checked_sub(share)?) },
-    )?;
-    let uusd_balance = deps
-        .querier
-        .query_balance(&env.contract.address, "uusd")?
-        .amount;
-    let mut usd_balance = uusd_balance;
-    let a_ust_balance_response: Cw20BalanceResponse = deps.querier.query_wasm_smart(
-        state.a_ust.to_string(),
-        &ExternalQueryMsg::Balance {
-            address: env.contract.address.to_string(),
-        },
-    )?;
-    let mut a_ust_balance = a_ust_balance_response.balance;
-    let b_luna_balance_response: Cw20BalanceResponse = deps.querier.query_wasm_smart(
-        state.collateral_token.to_string(),
-        &ExternalQueryMsg::Balance {
-            address: env.contract.address.to_string(),
-        },
-    )?;
-
-    let mut b_luna_balance = b_luna_balance_response.balance;
-    let mut start_after: Option<u64> = Some(0u64);
-    loop {
-        let res: KujiraBidsResponse = deps.querier.query_wasm_smart(
-            state.kujira_a_ust_vault.to_string(),
-            &ExternalQueryMsg::BidsByUser {
-                collateral_token: state.collateral_token.to_string(),
-                bidder: env.contract.address.to_string(),
-                start_after,
-                limit: Some(31),
-            },
-        )?;
-        for item in &res.bids {
-            a_ust_balance += item.amount;
-            if let Some(proxied_bid) = item.proxied_bid.as_ref() {
-                usd_balance += Uint128::try_from(proxied_bid.amount)?;
-                b_luna_balance += Uint128::try_from(proxied_bid.pending_liquidated_collateral)?;
-            }
+        let unlock_at = timestamp.plus_seconds(state.withdraw_lock);
+        if unlock_at >= env.block.time {
+            return Err(WithdrawLocked { unlock_at });
         }
-        if res.bids.len() < 31 {
-            break;
-        }
-        start_after = Some(res.bids.last().unwrap().idx);
     }
-    let epoch_state_response: EpochStateResponse = deps.querier.query_wasm_smart(
-        state.anchor_market.to_string(),
-        &ExternalQueryMsg::EpochState {
-            block_height: Some(env.block.height),
-            distributed_interest: None,
-        },
-    )?;
-    let a_terra_exchange_rate = epoch_state_response.exchange_rate;
-    let price_response: PriceResponse = deps.querier.query_wasm_smart(
-        state.price_oracle.to_string(),
-        &ExternalQueryMsg::Price {
-            base: state.collateral_token.to_string(),
-            quote: "uusd".to_string(),
-        },
-    )?;
-    let price = price_response.rate;
+    let nav = query_nav(deps.as_ref(), &env, &state)?;
+    check_price(&state, &env, &nav)?;
+    collect_fees(deps.storage, &env, &mut state, nav.total_cap()?)?;
+    let total_supply = outstanding_share(deps.storage)?;
+    burn_share(deps.storage, &info.sender, share)?;
     // Calculate total cap
-    let total_cap = b_luna_balance
-        + Uint128::try_from(
-            Uint256::from(
-                usd_balance
-                    + Uint128::try_from(Uint256::from(a_ust_balance).mul(a_terra_exchange_rate))?,
-            )
-            .mul(price.inv().unwrap()),
-        )?;
+    let total_cap = nav.total_cap_in_b_luna()?;
     // Calculate exact amount from share and total cap
-    let withdraw_cap = total_cap * share / state.total_supply;
+    let withdraw_cap = total_cap.multiply_ratio(share, total_supply);
+    check_slippage(min_amount_out, withdraw_cap)?;
 
-    state.total_supply -= share;
-    STATE.save(deps.storage, &state)?;
     // Withdraw if bLuna in vault is enough
-    if b_luna_balance_response.balance - state.locked_b_luna >= withdraw_cap {
+    if nav.unlocked_b_luna >= withdraw_cap {
         Ok(Response::new()
             .add_message(CosmosMsg::Wasm(WasmMsg::Execute {
                 contract_addr: state.collateral_token.to_string(),
@@ -721,71 +1186,268 @@ fn withdraw_b_luna(
                 attr("amount", withdraw_cap),
             ]))
     } else {
-        Err(Locked {})
+        Err(InsufficientUnlockedCollateral {
+            available: nav.unlocked_b_luna,
+            requested: withdraw_cap,
+        })
     }
 }
 
-fn claim_liquidation(
+fn request_withdrawal(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
+    share: Uint128,
 ) -> Result<Response, ContractError> {
-    let mut b_luna_balance = Uint128::zero();
-    let mut start_after = Some(0u64);
+    if share.is_zero() {
+        return Err(ZeroShare {});
+    }
+    let last_timestamp = LAST_DEPOSIT.may_load(
+        deps.storage,
+        deps.api
+            .addr_canonicalize(&info.sender.to_string().to_lowercase())?
+            .as_slice(),
+    )?;
     let mut state = STATE.load(deps.storage)?;
-    let mut bids_idx = vec![];
-    loop {
-        let res: KujiraBidsResponse = deps.querier.query_wasm_smart(
-            state.kujira_a_ust_vault.to_string(),
-            &ExternalQueryMsg::BidsByUser {
-                collateral_token: state.collateral_token.to_string(),
-                bidder: env.contract.address.to_string(),
-                start_after,
-                limit: Some(31),
-            },
-        )?;
-        for item in &res.bids {
-            if let Some(proxied_bid) = item.proxied_bid.as_ref() {
-                if !proxied_bid.pending_liquidated_collateral.is_zero() {
-                    b_luna_balance += Uint128::try_from(proxied_bid.pending_liquidated_collateral)?;
-                    bids_idx.push(item.idx);
-                }
+    check_not_paused(state.paused.withdraw_ust, "withdraw_ust")?;
+    if let Some(timestamp) = last_timestamp {
+        let unlock_at = timestamp.plus_seconds(state.withdraw_lock);
+        if unlock_at >= env.block.time {
+            return Err(WithdrawLocked { unlock_at });
+        }
+    }
+    let nav = query_nav(deps.as_ref(), &env, &state)?;
+    check_price(&state, &env, &nav)?;
+    let total_cap = nav.total_cap()?;
+    collect_fees(deps.storage, &env, &mut state, total_cap)?;
+    let total_supply = outstanding_share(deps.storage)?;
+    // Snapshot the UST value of the share
+    let amount = total_cap.multiply_ratio(share, total_supply);
+    if amount.is_zero() {
+        return Err(ZeroWithdrawAmount {});
+    }
+    let request = queue_withdrawal(deps.storage, &env, &mut state, info.sender, share, amount)?;
+    STATE.save(deps.storage, &state)?;
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "request_withdrawal"),
+        attr("id", request.id.to_string()),
+        attr("from", request.owner),
+        attr("share", share),
+        attr("amount", amount),
+    ]))
+}
+
+// Escrow share in the vault against amount of UST paid out once idle UST covers it
+fn queue_withdrawal(
+    storage: &mut dyn Storage,
+    env: &Env,
+    state: &mut State,
+    owner: Addr,
+    share: Uint128,
+    amount: Uint128,
+) -> StdResult<WithdrawalRequest> {
+    escrow_share(storage, &owner, &env.contract.address, share)?;
+    let request = WithdrawalRequest {
+        id: state.next_withdrawal_id,
+        owner,
+        share,
+        amount,
+        requested_at: env.block.time,
+        filled: false,
+    };
+    WITHDRAWAL_REQUESTS.save(storage, U64Key::from(request.id), &request)?;
+    state.next_withdrawal_id += 1;
+    state.queued_ust += amount;
+    state.escrowed_share += share;
+    Ok(request)
+}
+
+fn process_withdrawals(
+    deps: DepsMut,
+    env: Env,
+    limit: Option<u32>,
+) -> Result<Response, ContractError> {
+    let mut state = STATE.load(deps.storage)?;
+    check_not_paused(state.paused.withdraw_ust, "withdraw_ust")?;
+    let nav = query_nav(deps.as_ref(), &env, &state)?;
+    if nav.gross_cap()? < nav.queued_ust {
+        // The writedown is priced with bLuna
+        check_price(&state, &env, &nav)?;
+    }
+    let mut available = nav.idle_ust;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = Some(Bound::inclusive_int(state.next_fill_id));
+    let requests = WITHDRAWAL_REQUESTS
+        .range(deps.storage, start, None, Ascending)
+        .map(|item| item.map(|(_, request)| request))
+        .take(limit)
+        .collect::<StdResult<Vec<_>>>()?;
+    let mut filled = vec![];
+    // Fill in request order, a request that doesn't fit is skipped until UST covers it
+    let mut skipped = false;
+    for mut request in requests {
+        if request.filled {
+            if !skipped {
+                state.next_fill_id = request.id + 1;
             }
+            continue;
         }
-        if res.bids.len() < 31 {
-            break;
+        let amount = nav.queued_payout(request.amount)?;
+        if available < amount {
+            skipped = true;
+            continue;
+        }
+        available -= amount;
+        state.queued_ust -= request.amount;
+        state.reserved_ust += amount;
+        state.escrowed_share -= request.share;
+        burn_share(deps.storage, &env.contract.address, request.share)?;
+        request.amount = amount;
+        request.filled = true;
+        WITHDRAWAL_REQUESTS.save(deps.storage, U64Key::from(request.id), &request)?;
+        // Later passes start at the first request still waiting
+        if !skipped {
+            state.next_fill_id = request.id + 1;
         }
-        start_after = Some(res.bids.last().unwrap().idx);
+        filled.push(request.id.to_string());
     }
-    if b_luna_balance.is_zero() {
-        return Err(Insufficient {});
+    STATE.save(deps.storage, &state)?;
+    let mut response = Response::new().add_attribute("action", "process_withdrawals");
+    // Chains reject empty attribute values
+    if !filled.is_empty() {
+        response = response.add_attribute("filled", filled.join(","));
     }
-    let last_key = CLAIM_LIST.keys(deps.storage, None, None, Ascending).last();
-    let new_key = if let Some(value) = last_key {
-        u32::from_be_bytes(value.as_slice().try_into().unwrap()) + 1
-    } else {
-        0
-    };
-    CLAIM_LIST.save(
+    Ok(response)
+}
+
+fn cancel_withdrawal(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: u64,
+) -> Result<Response, ContractError> {
+    let mut state = STATE.load(deps.storage)?;
+    let request = WITHDRAWAL_REQUESTS
+        .may_load(deps.storage, U64Key::from(id))?
+        .ok_or(WithdrawalNotFound { id })?;
+    if request.owner != info.sender {
+        return Err(NotRequester {
+            id,
+            address: info.sender.to_string(),
+        });
+    }
+    if request.filled {
+        return Err(WithdrawalFilled { id });
+    }
+    WITHDRAWAL_REQUESTS.remove(deps.storage, U64Key::from(id));
+    // Give the escrowed shares back, they are priced against the NAV again
+    escrow_share(
         deps.storage,
-        U32Key::from(new_key),
-        &TokenRecord {
-            amount: b_luna_balance,
-            timestamp: env.block.time,
-        },
+        &env.contract.address,
+        &request.owner,
+        request.share,
     )?;
+    state.queued_ust -= request.amount;
+    state.escrowed_share -= request.share;
+    STATE.save(deps.storage, &state)?;
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "cancel_withdrawal"),
+        attr("id", id.to_string()),
+        attr("to", request.owner),
+        attr("share", request.share),
+        attr("amount", request.amount),
+    ]))
+}
 
-    state.locked_b_luna += b_luna_balance;
+fn claim_withdrawal(deps: DepsMut, info: MessageInfo, id: u64) -> Result<Response, ContractError> {
+    let mut state = STATE.load(deps.storage)?;
+    check_not_paused(state.paused.withdraw_ust, "withdraw_ust")?;
+    let request = WITHDRAWAL_REQUESTS
+        .may_load(deps.storage, U64Key::from(id))?
+        .ok_or(WithdrawalNotFound { id })?;
+    if request.owner != info.sender {
+        return Err(NotRequester {
+            id,
+            address: info.sender.to_string(),
+        });
+    }
+    if !request.filled {
+        return Err(WithdrawalNotFilled { id });
+    }
+    WITHDRAWAL_REQUESTS.remove(deps.storage, U64Key::from(id));
+    state.reserved_ust = state.reserved_ust.checked_sub(request.amount)?;
     STATE.save(deps.storage, &state)?;
     Ok(Response::new()
-        .add_message(CosmosMsg::Wasm(WasmMsg::Execute {
-            contract_addr: state.kujira_a_ust_vault.to_string(),
-            msg: to_binary(&ExternalMsg::ClaimLiquidations {
-                collateral_token: state.collateral_token,
-                bids_idx,
-            })?,
-            funds: vec![],
+        .add_message(CosmosMsg::Bank(BankMsg::Send {
+            to_address: request.owner.to_string(),
+            amount: vec![Coin {
+                denom: "uusd".to_string(),
+                amount: request.amount,
+            }],
         }))
+        .add_attributes(vec![
+            attr("action", "claim_withdrawal"),
+            attr("id", id.to_string()),
+            attr("to", request.owner),
+            attr("amount", request.amount),
+        ]))
+}
+
+fn execute_collect_fees(deps: DepsMut, env: Env) -> Result<Response, ContractError> {
+    let mut state = STATE.load(deps.storage)?;
+    let nav = query_nav(deps.as_ref(), &env, &state)?;
+    check_price(&state, &env, &nav)?;
+    let fees = collect_fees(deps.storage, &env, &mut state, nav.total_cap()?)?;
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "collect_fees"),
+        attr("to", state.fee_collector),
+        attr("management_fee", fees.management_fee),
+        attr("performance_fee", fees.performance_fee),
+        attr("share", fees.share),
+        attr("high_water_mark", fees.high_water_mark.to_string()),
+    ]))
+}
+
+fn claim_liquidation(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    check_role(deps.as_ref(), &info.sender, Role::Keeper)?;
+    let mut b_luna_balance = Uint128::zero();
+    let state = STATE.load(deps.storage)?;
+    check_not_paused(state.paused.claim, "claim")?;
+    let mut bids_idx = vec![];
+    for item in query_bids(deps.as_ref(), &env, &state)? {
+        if let Some(proxied_bid) = item.proxied_bid.as_ref() {
+            if !proxied_bid.pending_liquidated_collateral.is_zero() {
+                b_luna_balance += Uint128::try_from(proxied_bid.pending_liquidated_collateral)?;
+                bids_idx.push(item.idx);
+            }
+        }
+    }
+    if b_luna_balance.is_zero() {
+        return Err(NothingToClaim {});
+    }
+    // The reply locks the bLuna that actually arrives
+    let settlement = PendingSettlement {
+        amount: b_luna_balance,
+        balance_before: b_luna_balance_of(deps.as_ref(), &env, &state)?,
+        minimum_receive: Uint128::zero(),
+    };
+    PENDING_SETTLEMENTS.save(deps.storage, U64Key::new(CLAIM_REPLY_ID), &settlement)?;
+    Ok(Response::new()
+        .add_submessage(SubMsg::reply_on_success(
+            WasmMsg::Execute {
+                contract_addr: state.kujira_a_ust_vault.to_string(),
+                msg: to_binary(&ExternalMsg::ClaimLiquidations {
+                    collateral_token: state.collateral_token,
+                    bids_idx,
+                })?,
+                funds: vec![],
+            },
+            CLAIM_REPLY_ID,
+        ))
         .add_attributes(vec![
             attr("action", "liquidate"),
             attr("from", &info.sender),
@@ -794,10 +1456,12 @@ fn claim_liquidation(
 }
 
 fn unlock(deps: DepsMut, env: Env, info: MessageInfo) -> Result<Response, ContractError> {
+    check_role(deps.as_ref(), &info.sender, Role::Keeper)?;
     let keys = CLAIM_LIST.keys(deps.storage, None, None, Order::Ascending);
     let mut remove_keys = Vec::new();
     let mut unlocked_b_luna = Uint128::zero();
     let state = STATE.load(deps.storage)?;
+    check_not_paused(state.paused.unlock, "unlock")?;
     for key in keys {
         let claim = CLAIM_LIST.load(deps.storage, U32Key::from(key.clone()))?;
         if claim.timestamp.plus_seconds(state.lock_period) <= env.block.time {
@@ -811,7 +1475,7 @@ fn unlock(deps: DepsMut, env: Env, info: MessageInfo) -> Result<Response, Contra
         CLAIM_LIST.remove(deps.storage, key);
     }
     if unlocked_b_luna.is_zero() {
-        return Err(Insufficient {});
+        return Err(NothingToUnlock {});
     }
     let mut state = STATE.load(deps.storage)?;
     state.locked_b_luna -= unlocked_b_luna;
@@ -823,162 +1487,456 @@ fn unlock(deps: DepsMut, env: Env, info: MessageInfo) -> Result<Response, Contra
     ]))
 }
 
-fn set_permission(
+fn grant_role(
+    deps: DepsMut,
+    info: MessageInfo,
+    address: Addr,
+    role: Role,
+) -> Result<Response, ContractError> {
+    check_role(deps.as_ref(), &info.sender, Role::Admin)?;
+    let address = deps
+        .api
+        .addr_validate(address.to_string().to_lowercase().as_str())?;
+    let mut permission = load_permission(deps.as_ref(), &address)?;
+    if permission.roles.contains(&role) {
+        return Err(PermissionUnchanged {});
+    }
+    permission.roles.push(role);
+    PERMISSIONS.save(
+        deps.storage,
+        deps.api.addr_canonicalize(address.as_str())?.as_slice(),
+        &permission,
+    )?;
+    Ok(Response::new()
+        .add_attributes(vec![
+            attr("action", "grant_role"),
+            attr("from", info.sender),
+        ])
+        .add_event(
+            Event::new("grant_role")
+                .add_attribute("address", address)
+                .add_attribute("role", role.to_string()),
+        ))
+}
+
+fn revoke_role(
     deps: DepsMut,
     info: MessageInfo,
     address: Addr,
-    new_permission: Permission,
+    role: Role,
 ) -> Result<Response, ContractError> {
+    check_role(deps.as_ref(), &info.sender, Role::Admin)?;
+    let address = deps
+        .api
+        .addr_validate(address.to_string().to_lowercase().as_str())?;
     let state = STATE.load(deps.storage)?;
-    if state.owner.to_string().to_lowercase() != info.sender.to_string().to_lowercase() {
-        return Err(Unauthorized {});
+    if role == Role::Admin && address == state.owner {
+        return Err(OwnerAdminRequired {});
     }
-    let permission = PERMISSIONS
-        .may_load(
-            deps.storage,
-            deps.api
-                .addr_canonicalize(&address.to_string().to_lowercase())?
-                .as_slice(),
-        )?
-        .unwrap_or(Permission { submit_bid: false });
-    if permission == new_permission {
-        return Err(Invalidate {});
+    let mut permission = load_permission(deps.as_ref(), &address)?;
+    if !permission.roles.contains(&role) {
+        return Err(PermissionUnchanged {});
     }
-    if permission == (Permission { submit_bid: false }) {
-        PERMISSIONS.remove(
-            deps.storage,
-            deps.api
-                .addr_canonicalize(&address.to_string().to_lowercase())?
-                .as_slice(),
-        );
+    permission.roles.retain(|other| *other != role);
+    let key = deps.api.addr_canonicalize(address.as_str())?;
+    if permission.roles.is_empty() {
+        PERMISSIONS.remove(deps.storage, key.as_slice());
     } else {
-        PERMISSIONS.save(
-            deps.storage,
-            deps.api
-                .addr_canonicalize(&address.to_string().to_lowercase())?
-                .as_slice(),
-            &permission,
-        )?;
+        PERMISSIONS.save(deps.storage, key.as_slice(), &permission)?;
     }
-    Ok(Response::new().add_attributes(vec![
-        attr("action", "set_permission"),
-        attr("from", info.sender),
-        attr("to", address.to_string().to_lowercase()),
-        attr("submit_bid", new_permission.submit_bid.to_string()),
-    ]))
+    Ok(Response::new()
+        .add_attributes(vec![
+            attr("action", "revoke_role"),
+            attr("from", info.sender),
+        ])
+        .add_event(
+            Event::new("revoke_role")
+                .add_attribute("address", address)
+                .add_attribute("role", role.to_string()),
+        ))
 }
 
-fn swap(deps: DepsMut, env: Env, info: MessageInfo) -> Result<Response, ContractError> {
-    let state = STATE.load(deps.storage)?;
-    let b_luna_balance_response: Cw20BalanceResponse = deps.querier.query_wasm_smart(
-        state.collateral_token.to_string(),
-        &ExternalQueryMsg::Balance {
-            address: env.contract.address.to_string(),
-        },
-    )?;
-    let swap_amount = b_luna_balance_response.balance - state.locked_b_luna;
+fn swap(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    amount: Option<Uint128>,
+    minimum_receive: Option<Uint128>,
+    venue: Option<SwapVenue>,
+) -> Result<Response, ContractError> {
+    check_role(deps.as_ref(), &info.sender, Role::Keeper)?;
+    let mut state = STATE.load(deps.storage)?;
+    check_not_paused(state.paused.swap, "swap")?;
+    let next_swap_at = next_swap_at(&state);
+    if env.block.time < next_swap_at {
+        return Err(SwapCooldown { next_swap_at });
+    }
+    let nav = query_nav(deps.as_ref(), &env, &state)?;
+    check_price(&state, &env, &nav)?;
+    let swap_amount = match (amount, state.max_swap_amount) {
+        (Some(amount), _) if amount.is_zero() => return Err(ZeroAmount {}),
+        (Some(amount), Some(max)) if amount > max => {
+            return Err(SwapAmountExceedsMax { amount, max })
+        }
+        (Some(amount), _) if amount > nav.unlocked_b_luna => {
+            return Err(InsufficientUnlockedCollateral {
+                available: nav.unlocked_b_luna,
+                requested: amount,
+            })
+        }
+        (Some(amount), _) => amount,
+        (None, _) => max_swap_amount(&state, nav.unlocked_b_luna),
+    };
     if swap_amount.is_zero() {
-        return Err(Insufficient {});
+        return Err(NothingToSwap {});
     }
+    state.last_swap = env.block.time.seconds();
+    STATE.save(deps.storage, &state)?;
+    // The oracle floor can only be tightened by the caller
+    let minimum_receive =
+        minimum_receive
+            .unwrap_or_default()
+            .max(swap_minimum(&state, &nav, swap_amount)?);
+    let venue = venue.unwrap_or(SwapVenue::Router);
+    let (contract, msg) = match venue {
+        SwapVenue::Router => (
+            state.astroport_router.clone(),
+            to_binary(&ExternalMsg::ExecuteSwapOperations {
+                operations: state.swap_route.clone(),
+                minimum_receive: Some(minimum_receive),
+                to: None,
+                max_spread: Some(Decimal::from_ratio(state.swap_tolerance_bps, MAX_BPS)),
+            })?,
+        ),
+        // Pairs take no minimum receive, a zero spread against this belief price enforces it
+        SwapVenue::AstroportPair => (
+            state.astroport_pair.clone().ok_or(NoAstroportPair {})?,
+            to_binary(&ExternalMsg::Swap {
+                belief_price: (!minimum_receive.is_zero())
+                    .then(|| Decimal::from_ratio(swap_amount, minimum_receive)),
+                max_spread: Some(Decimal::zero()),
+                to: None,
+            })?,
+        ),
+    };
+    // The reply reverts the swap if the venue returned less than the minimum
+    let settlement = PendingSettlement {
+        amount: swap_amount,
+        balance_before: ust_balance(deps.as_ref(), &env)?,
+        minimum_receive,
+    };
+    PENDING_SETTLEMENTS.save(deps.storage, U64Key::new(SWAP_REPLY_ID), &settlement)?;
     Ok(Response::new()
-        .add_message(CosmosMsg::Wasm(WasmMsg::Execute {
-            contract_addr: state.collateral_token.to_string(),
-            msg: to_binary(&ExternalMsg::Send {
-                contract: state.astroport_router.to_string(),
-                amount: swap_amount,
-                msg: to_binary(&ExternalMsg::ExecuteSwapOperations {
-                    operations: vec![
-                        AstroSwap {
-                            offer_asset_info: Token {
-                                contract_addr: state.collateral_token,
-                            },
-                            ask_asset_info: NativeToken {
-                                denom: "uluna".to_string(),
-                            },
-                        },
-                        NativeSwap {
-                            offer_denom: "uluna".to_string(),
-                            ask_denom: "uusd".to_string(),
-                        },
-                    ],
-                    minimum_receive: None,
-                    to: None,
-                    max_spread: Some(Decimal::from_str("0.5")?),
+        .add_submessage(SubMsg::reply_on_success(
+            WasmMsg::Execute {
+                contract_addr: state.collateral_token.to_string(),
+                msg: to_binary(&ExternalMsg::Send {
+                    contract: contract.to_string(),
+                    amount: swap_amount,
+                    msg,
                 })?,
-            })?,
-            funds: vec![],
-        }))
+                funds: vec![],
+            },
+            SWAP_REPLY_ID,
+        ))
         .add_attributes(vec![
             attr("action", "swap"),
             attr("from", info.sender),
             attr("amount", swap_amount.to_string()),
+            attr("minimum_receive", minimum_receive),
+            attr("venue", venue.to_string()),
         ]))
 }
 
+fn next_swap_at(state: &State) -> Timestamp {
+    Timestamp::from_seconds(state.last_swap + state.swap_cooldown)
+}
+
+// Unlocked bLuna capped by the per-swap maximum
+fn max_swap_amount(state: &State, unlocked_b_luna: Uint128) -> Uint128 {
+    state
+        .max_swap_amount
+        .map_or(unlocked_b_luna, |max| max.min(unlocked_b_luna))
+}
+
+// Oracle value of the bLuna less the swap tolerance
+fn swap_minimum(state: &State, nav: &Nav, amount: Uint128) -> StdResult<Uint128> {
+    Ok(
+        nav.b_luna_value(amount)?
+            * Decimal::from_ratio(MAX_BPS - state.swap_tolerance_bps, MAX_BPS),
+    )
+}
+
+fn validate_swap_route(
+    route: &[SwapOperation],
+    collateral_token: &Addr,
+) -> Result<(), ContractError> {
+    let invalid = |reason: String| InvalidSwapRoute { reason };
+    let first = route
+        .first()
+        .ok_or_else(|| invalid("route is empty".to_string()))?;
+    let collateral = Token {
+        contract_addr: collateral_token.clone(),
+    };
+    if first.offer_asset_info() != collateral {
+        return Err(invalid(format!("route must start at {}", collateral)));
+    }
+    for (prev, next) in route.iter().zip(route.iter().skip(1)) {
+        if prev.ask_asset_info() != next.offer_asset_info() {
+            return Err(invalid(format!(
+                "{} does not follow {}",
+                next.offer_asset_info(),
+                prev.ask_asset_info()
+            )));
+        }
+    }
+    let uusd = NativeToken {
+        denom: "uusd".to_string(),
+    };
+    if route.last().map(SwapOperation::ask_asset_info) != Some(uusd) {
+        return Err(invalid("route must end at uusd".to_string()));
+    }
+    Ok(())
+}
+
+// Assets visited by a route, e.g. "b_luna>uluna>uusd"
+fn route_attr(route: &[SwapOperation]) -> String {
+    route
+        .first()
+        .map(SwapOperation::offer_asset_info)
+        .into_iter()
+        .chain(route.iter().map(SwapOperation::ask_asset_info))
+        .map(|asset| asset.to_string())
+        .collect::<Vec<_>>()
+        .join(">")
+}
+
+fn propose_owner(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    new_owner: Addr,
+    expiry: Option<Expiration>,
+) -> Result<Response, ContractError> {
+    let state = STATE.load(deps.storage)?;
+    check_owner(&state, &info.sender)?;
+    let new_owner = deps
+        .api
+        .addr_validate(new_owner.to_string().to_lowercase().as_str())?;
+    if new_owner == state.owner {
+        return Err(OwnerUnchanged {});
+    }
+    if let Some(expiry) = expiry {
+        if expiry.is_expired(&env.block) {
+            return Err(OwnershipProposalExpired { expiry });
+        }
+    }
+    PENDING_OWNER.save(
+        deps.storage,
+        &PendingOwner {
+            new_owner: new_owner.clone(),
+            expiry,
+        },
+    )?;
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "propose_owner"),
+        attr("from", info.sender),
+        attr("new_owner", new_owner),
+        attr(
+            "expiry",
+            expiry.map(|expiry| expiry.to_string()).unwrap_or_default(),
+        ),
+    ]))
+}
+
+fn accept_ownership(deps: DepsMut, env: Env, info: MessageInfo) -> Result<Response, ContractError> {
+    let pending = PENDING_OWNER
+        .may_load(deps.storage)?
+        .ok_or(NoPendingOwner {})?;
+    if pending.new_owner != info.sender.to_string().to_lowercase() {
+        return Err(NotPendingOwner {
+            address: info.sender.to_string(),
+        });
+    }
+    if let Some(expiry) = pending.expiry {
+        if expiry.is_expired(&env.block) {
+            return Err(OwnershipProposalExpired { expiry });
+        }
+    }
+    let mut state = STATE.load(deps.storage)?;
+    // The admin role follows the owner
+    let mut permission = load_permission(deps.as_ref(), &state.owner)?;
+    permission.roles.retain(|role| *role != Role::Admin);
+    let key = deps.api.addr_canonicalize(state.owner.as_str())?;
+    if permission.roles.is_empty() {
+        PERMISSIONS.remove(deps.storage, key.as_slice());
+    } else {
+        PERMISSIONS.save(deps.storage, key.as_slice(), &permission)?;
+    }
+    let mut permission = load_permission(deps.as_ref(), &pending.new_owner)?;
+    if !permission.roles.contains(&Role::Admin) {
+        permission.roles.push(Role::Admin);
+    }
+    PERMISSIONS.save(
+        deps.storage,
+        deps.api
+            .addr_canonicalize(pending.new_owner.as_str())?
+            .as_slice(),
+        &permission,
+    )?;
+    let previous_owner = state.owner;
+    state.owner = pending.new_owner;
+    STATE.save(deps.storage, &state)?;
+    PENDING_OWNER.remove(deps.storage);
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "accept_ownership"),
+        attr("previous_owner", previous_owner),
+        attr("owner", state.owner),
+    ]))
+}
+
+fn cancel_ownership_proposal(deps: DepsMut, info: MessageInfo) -> Result<Response, ContractError> {
+    let state = STATE.load(deps.storage)?;
+    check_owner(&state, &info.sender)?;
+    let pending = PENDING_OWNER
+        .may_load(deps.storage)?
+        .ok_or(NoPendingOwner {})?;
+    PENDING_OWNER.remove(deps.storage);
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "cancel_ownership_proposal"),
+        attr("from", info.sender),
+        attr("new_owner", pending.new_owner),
+    ]))
+}
+
 #[allow(clippy::too_many_arguments)]
-fn update_config(
+fn queue_config_change(
     deps: DepsMut,
+    env: Env,
     info: MessageInfo,
-    owner: Option<Addr>,
-    paused: Option<bool>,
     swap_wallet: Option<Addr>,
     lock_period: Option<u64>,
     withdraw_lock: Option<u64>,
     bid_strategy: Option<BidStrategy>,
+    timelock_delay: Option<u64>,
+    swap_route: Option<Vec<SwapOperation>>,
+    astroport_pair: Option<Addr>,
 ) -> Result<Response, ContractError> {
-    let mut state = STATE.load(deps.storage)?;
-    if state.owner.to_string().to_lowercase() != info.sender.to_string().to_lowercase() {
-        return Err(Unauthorized {});
-    }
-    let mut attributes = vec![attr("action", "update_config"), attr("from", info.sender)];
-    if let Some(owner) = owner {
-        if owner.to_string().to_lowercase() != state.owner {
-            PERMISSIONS.remove(
-                deps.storage,
+    check_role(deps.as_ref(), &info.sender, Role::ConfigManager)?;
+    let state = STATE.load(deps.storage)?;
+    let change = PendingConfigChange {
+        swap_wallet: swap_wallet
+            .map(|swap_wallet| {
                 deps.api
-                    .addr_canonicalize(state.owner.to_string().as_str())?
-                    .as_slice(),
-            );
-            PERMISSIONS.save(
-                deps.storage,
+                    .addr_validate(swap_wallet.to_string().to_lowercase().as_str())
+            })
+            .transpose()?,
+        lock_period,
+        withdraw_lock,
+        bid_strategy,
+        timelock_delay,
+        swap_route,
+        astroport_pair: astroport_pair
+            .map(|astroport_pair| {
                 deps.api
-                    .addr_canonicalize(owner.to_string().to_lowercase().as_str())?
-                    .as_slice(),
-                &Permission { submit_bid: true },
-            )?;
-            state.owner = deps
-                .api
-                .addr_validate(owner.to_string().to_lowercase().as_str())?;
-            attributes.push(attr("owner", state.owner.to_string()));
-        }
+                    .addr_validate(astroport_pair.to_string().to_lowercase().as_str())
+            })
+            .transpose()?,
+        executable_at: env.block.time.plus_seconds(state.timelock_delay),
+    };
+    if let Some(bid_strategy) = change.bid_strategy.as_ref() {
+        bid_strategy.validate()?;
     }
-    if let Some(paused) = paused {
-        if paused != state.paused {
-            state.paused = paused;
-            attributes.push(attr("paused", paused.to_string()));
-        }
+    if let Some(swap_route) = change.swap_route.as_ref() {
+        validate_swap_route(swap_route, &state.collateral_token)?;
     }
-    if let Some(swap_wallet) = swap_wallet {
-        if swap_wallet.to_string().to_lowercase() != state.swap_wallet {
-            state.swap_wallet = deps
-                .api
-                .addr_validate(swap_wallet.to_string().to_lowercase().as_str())?;
-            attributes.push(attr("swap_wallet", state.swap_wallet.to_string()));
+    let mut attributes = vec![
+        attr("action", "queue_config_change"),
+        attr("from", info.sender),
+        attr("executable_at", change.executable_at.seconds().to_string()),
+    ];
+    if let Some(swap_wallet) = change.swap_wallet.as_ref() {
+        attributes.push(attr("swap_wallet", swap_wallet));
+    }
+    if let Some(lock_period) = change.lock_period {
+        attributes.push(attr("lock_period", lock_period.to_string()));
+    }
+    if let Some(withdraw_lock) = change.withdraw_lock {
+        attributes.push(attr("withdraw_lock", withdraw_lock.to_string()));
+    }
+    if let Some(bid_strategy) = change.bid_strategy.as_ref() {
+        attributes.push(attr(
+            "activate_at_ltv",
+            bid_strategy.activate_at.ltv.to_string(),
+        ));
+        attributes.push(attr(
+            "activate_at_cumulative_value",
+            bid_strategy.activate_at.cumulative_value.to_string(),
+        ));
+        attributes.push(attr(
+            "deactivate_at_ltv",
+            bid_strategy.deactivate_at.ltv.to_string(),
+        ));
+        attributes.push(attr(
+            "deactivate_at_cumulative_value",
+            bid_strategy.deactivate_at.cumulative_value.to_string(),
+        ));
+    }
+    if let Some(timelock_delay) = change.timelock_delay {
+        attributes.push(attr("timelock_delay", timelock_delay.to_string()));
+    }
+    if let Some(swap_route) = change.swap_route.as_ref() {
+        attributes.push(attr("swap_route", route_attr(swap_route)));
+    }
+    if let Some(astroport_pair) = change.astroport_pair.as_ref() {
+        attributes.push(attr("astroport_pair", astroport_pair));
+    }
+    if attributes.len() <= 3 {
+        return Err(NoConfigChange {});
+    }
+    // A new change replaces the pending one and restarts the delay
+    PENDING_CONFIG_CHANGE.save(deps.storage, &change)?;
+    Ok(Response::new().add_attributes(attributes))
+}
+
+fn execute_config_change(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    check_role(deps.as_ref(), &info.sender, Role::ConfigManager)?;
+    let change = PENDING_CONFIG_CHANGE
+        .may_load(deps.storage)?
+        .ok_or(NoPendingConfigChange {})?;
+    if env.block.time < change.executable_at {
+        return Err(TimelockNotExpired {
+            executable_at: change.executable_at,
+        });
+    }
+    let mut state = STATE.load(deps.storage)?;
+    let mut attributes = vec![
+        attr("action", "execute_config_change"),
+        attr("from", info.sender),
+    ];
+    if let Some(swap_wallet) = change.swap_wallet {
+        if swap_wallet != state.swap_wallet {
+            attributes.push(attr("swap_wallet", swap_wallet.to_string()));
+            state.swap_wallet = swap_wallet;
         }
     }
-    if let Some(lock_period) = lock_period {
+    if let Some(lock_period) = change.lock_period {
         if lock_period != state.lock_period {
             state.lock_period = lock_period;
             attributes.push(attr("lock_period", lock_period.to_string()));
         }
     }
-    if let Some(withdraw_lock) = withdraw_lock {
+    if let Some(withdraw_lock) = change.withdraw_lock {
         if withdraw_lock != state.withdraw_lock {
             state.withdraw_lock = withdraw_lock;
             attributes.push(attr("withdraw_lock", withdraw_lock.to_string()));
         }
     }
-    if let Some(bid_strategy) = bid_strategy {
+    if let Some(bid_strategy) = change.bid_strategy {
         if bid_strategy != state.bid_strategy {
             state.bid_strategy = bid_strategy.clone();
             attributes.push(attr(
@@ -999,13 +1957,593 @@ fn update_config(
             ));
         }
     }
+    if let Some(timelock_delay) = change.timelock_delay {
+        if timelock_delay != state.timelock_delay {
+            state.timelock_delay = timelock_delay;
+            attributes.push(attr("timelock_delay", timelock_delay.to_string()));
+        }
+    }
+    if let Some(swap_route) = change.swap_route {
+        if swap_route != state.swap_route {
+            attributes.push(attr("swap_route", route_attr(&swap_route)));
+            state.swap_route = swap_route;
+        }
+    }
+    if let Some(astroport_pair) = change.astroport_pair {
+        if Some(&astroport_pair) != state.astroport_pair.as_ref() {
+            attributes.push(attr("astroport_pair", astroport_pair.to_string()));
+            state.astroport_pair = Some(astroport_pair);
+        }
+    }
+    PENDING_CONFIG_CHANGE.remove(deps.storage);
+    STATE.save(deps.storage, &state)?;
+    Ok(Response::new().add_attributes(attributes))
+}
+
+fn cancel_config_change(deps: DepsMut, info: MessageInfo) -> Result<Response, ContractError> {
+    check_role(deps.as_ref(), &info.sender, Role::ConfigManager)?;
+    PENDING_CONFIG_CHANGE
+        .may_load(deps.storage)?
+        .ok_or(NoPendingConfigChange {})?;
+    PENDING_CONFIG_CHANGE.remove(deps.storage);
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "cancel_config_change"),
+        attr("from", info.sender),
+    ]))
+}
+
+fn emergency_shutdown(deps: DepsMut, info: MessageInfo) -> Result<Response, ContractError> {
+    let mut state = STATE.load(deps.storage)?;
+    check_owner(&state, &info.sender)?;
+    state.shutdown = true;
+    // Only exits remain and UpdateConfig is closed, a leftover pause would trap them
+    state.paused = PauseFlags::default();
+    STATE.save(deps.storage, &state)?;
+    SHUTDOWN_BID_CURSOR.save(deps.storage, &0)?;
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "emergency_shutdown"),
+        attr("from", info.sender),
+    ]))
+}
+
+fn retract_shutdown_bids(
+    deps: DepsMut,
+    env: Env,
+    limit: Option<u32>,
+) -> Result<Response, ContractError> {
+    let state = STATE.load(deps.storage)?;
+    if !state.shutdown {
+        return Err(NotShutDown {});
+    }
+    if SHUTDOWN_BASKET.may_load(deps.storage)?.is_some() {
+        return Err(ShutDown {});
+    }
+    let cursor = SHUTDOWN_BID_CURSOR.load(deps.storage)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+    let bids = query_bids_page(deps.as_ref(), &env, &state, cursor, limit as u8)?;
+    if let Some(bid) = bids.last() {
+        SHUTDOWN_BID_CURSOR.save(deps.storage, &bid.idx)?;
+    }
+
+    let mut messages = vec![];
+    // Claim before retracting so no liquidated collateral is left behind
+    let mut claimed = Uint128::zero();
+    let mut bids_idx = vec![];
+    for item in bids.iter() {
+        if let Some(proxied_bid) = item.proxied_bid.as_ref() {
+            if !proxied_bid.pending_liquidated_collateral.is_zero() {
+                claimed += Uint128::try_from(proxied_bid.pending_liquidated_collateral)?;
+                bids_idx.push(item.idx);
+            }
+        }
+    }
+    if !bids_idx.is_empty() {
+        messages.push(SubMsg::new(WasmMsg::Execute {
+            contract_addr: state.kujira_a_ust_vault.to_string(),
+            msg: to_binary(&ExternalMsg::ClaimLiquidations {
+                collateral_token: state.collateral_token.clone(),
+                bids_idx,
+            })?,
+            funds: vec![],
+        }));
+    }
+    // Fully consumed bids have nothing to return and retracting them fails
+    let retractable: Vec<_> = bids
+        .iter()
+        .filter(|item| {
+            !item.amount.is_zero()
+                || item
+                    .proxied_bid
+                    .as_ref()
+                    .map_or(false, |proxied_bid| !proxied_bid.amount.is_zero())
+        })
+        .collect();
+    for item in retractable.iter() {
+        // A bid Kujira refuses to return stays behind instead of blocking the others
+        messages.push(SubMsg::reply_on_error(
+            WasmMsg::Execute {
+                contract_addr: state.kujira_a_ust_vault.to_string(),
+                msg: to_binary(&ExternalMsg::RetractBid {
+                    bid_idx: item.idx,
+                    amount: None,
+                })?,
+                funds: vec![],
+            },
+            RETRACT_REPLY_ID,
+        ));
+    }
+    // The last page freezes the basket once its claims and retractions ran
+    let done = bids.len() < limit as usize;
+    if done {
+        messages.push(SubMsg::new(WasmMsg::Execute {
+            contract_addr: env.contract.address.to_string(),
+            msg: to_binary(&ExecuteMsg::FinalizeShutdown {})?,
+            funds: vec![],
+        }));
+    }
+    Ok(Response::new()
+        .add_submessages(messages)
+        .add_attributes(vec![
+            attr("action", "retract_shutdown_bids"),
+            attr("retracted_bids", retractable.len().to_string()),
+            attr("claimed", claimed),
+            attr("done", done.to_string()),
+        ]))
+}
+
+fn finalize_shutdown(deps: DepsMut, env: Env) -> Result<Response, ContractError> {
+    let mut state = STATE.load(deps.storage)?;
+    if !state.shutdown {
+        return Err(NotShutDown {});
+    }
+    if SHUTDOWN_BASKET.may_load(deps.storage)?.is_some() {
+        return Err(ShutDown {});
+    }
+    let cursor = SHUTDOWN_BID_CURSOR.load(deps.storage)?;
+    if !query_bids_page(deps.as_ref(), &env, &state, cursor, 1)?.is_empty() {
+        return Err(ShutdownBidsRemaining { cursor });
+    }
+    // Unfilled withdrawal requests get their shares back and redeem like everyone else
+    let start = Some(Bound::inclusive_int(state.next_fill_id));
+    let requests = WITHDRAWAL_REQUESTS
+        .range(deps.storage, start, None, Ascending)
+        .map(|item| item.map(|(_, request)| request))
+        .collect::<StdResult<Vec<_>>>()?;
+    let mut released = vec![];
+    for request in requests {
+        escrow_share(
+            deps.storage,
+            &env.contract.address,
+            &request.owner,
+            request.share,
+        )?;
+        WITHDRAWAL_REQUESTS.remove(deps.storage, U64Key::from(request.id));
+        released.push(request.id.to_string());
+    }
+    state.queued_ust = Uint128::zero();
+    state.escrowed_share = Uint128::zero();
+    // Lock rules no longer apply
+    let keys = CLAIM_LIST
+        .keys(deps.storage, None, None, Ascending)
+        .collect::<Vec<_>>();
+    for key in keys {
+        CLAIM_LIST.remove(deps.storage, U32Key::from(key));
+    }
+    state.locked_b_luna = Uint128::zero();
+    STATE.save(deps.storage, &state)?;
+
+    // UST reserved for filled requests stays claimable
+    let ust = deps
+        .querier
+        .query_balance(&env.contract.address, "uusd")?
+        .amount
+        .checked_sub(state.reserved_ust)?;
+    let a_ust: Cw20BalanceResponse = deps.querier.query_wasm_smart(
+        state.a_ust.to_string(),
+        &ExternalQueryMsg::Balance {
+            address: env.contract.address.to_string(),
+        },
+    )?;
+    let b_luna: Cw20BalanceResponse = deps.querier.query_wasm_smart(
+        state.collateral_token.to_string(),
+        &ExternalQueryMsg::Balance {
+            address: env.contract.address.to_string(),
+        },
+    )?;
+    let basket = ShutdownBasket {
+        ust,
+        a_ust: a_ust.balance,
+        b_luna: b_luna.balance,
+    };
+    SHUTDOWN_BASKET.save(deps.storage, &basket)?;
+    let mut response = Response::new().add_attributes(vec![
+        attr("action", "finalize_shutdown"),
+        attr("ust", basket.ust),
+        attr("a_ust", basket.a_ust),
+        attr("b_luna", basket.b_luna),
+        attr("share", total_share(deps.storage)?),
+    ]);
+    // Chains reject empty attribute values
+    if !released.is_empty() {
+        response = response.add_attribute("released_requests", released.join(","));
+    }
+    Ok(response)
+}
+
+fn redeem_shutdown_share(
+    deps: DepsMut,
+    info: MessageInfo,
+    share: Uint128,
+) -> Result<Response, ContractError> {
+    if share.is_zero() {
+        return Err(ZeroShare {});
+    }
+    let mut basket = SHUTDOWN_BASKET
+        .may_load(deps.storage)?
+        .ok_or(NotShutDown {})?;
+    let state = STATE.load(deps.storage)?;
+    // Pro-rata of what is left, no oracle price involved
+    let total_supply = total_share(deps.storage)?;
+    let ust = basket.ust.multiply_ratio(share, total_supply);
+    let a_ust = basket.a_ust.multiply_ratio(share, total_supply);
+    let b_luna = basket.b_luna.multiply_ratio(share, total_supply);
+    if ust.is_zero() && a_ust.is_zero() && b_luna.is_zero() {
+        return Err(ZeroWithdrawAmount {});
+    }
+    burn_share(deps.storage, &info.sender, share)?;
+    basket.ust -= ust;
+    basket.a_ust -= a_ust;
+    basket.b_luna -= b_luna;
+    SHUTDOWN_BASKET.save(deps.storage, &basket)?;
+
+    let mut messages = vec![];
+    if !ust.is_zero() {
+        messages.push(CosmosMsg::Bank(BankMsg::Send {
+            to_address: info.sender.to_string(),
+            amount: vec![Coin {
+                denom: "uusd".to_string(),
+                amount: ust,
+            }],
+        }));
+    }
+    for (token, amount) in [(&state.a_ust, a_ust), (&state.collateral_token, b_luna)] {
+        if !amount.is_zero() {
+            messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: token.to_string(),
+                msg: to_binary(&ExternalMsg::Transfer {
+                    recipient: info.sender.to_string(),
+                    amount,
+                })?,
+                funds: vec![],
+            }));
+        }
+    }
+    Ok(Response::new().add_messages(messages).add_attributes(vec![
+        attr("action", "redeem_shutdown_share"),
+        attr("to", info.sender),
+        attr("share", share),
+        attr("ust", ust),
+        attr("a_ust", a_ust),
+        attr("b_luna", b_luna),
+    ]))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn update_config(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    paused: Option<PauseFlags>,
+    b_luna_discount_bps: Option<u16>,
+    max_price_age: Option<u64>,
+    min_b_luna_price: Option<Decimal256>,
+    max_b_luna_price: Option<Decimal256>,
+    fee_collector: Option<Addr>,
+    management_fee_bps: Option<u16>,
+    performance_fee_bps: Option<u16>,
+    target_allocation: Option<Vec<SlotAllocation>>,
+    rebalance_tolerance_bps: Option<u16>,
+    max_premium_slot: Option<u8>,
+    swap_tolerance_bps: Option<u16>,
+    max_swap_amount: Option<Uint128>,
+    swap_cooldown: Option<u64>,
+) -> Result<Response, ContractError> {
+    if paused.is_some() {
+        check_role(deps.as_ref(), &info.sender, Role::Pauser)?;
+    }
+    if b_luna_discount_bps.is_some()
+        || max_price_age.is_some()
+        || min_b_luna_price.is_some()
+        || max_b_luna_price.is_some()
+        || fee_collector.is_some()
+        || management_fee_bps.is_some()
+        || performance_fee_bps.is_some()
+        || target_allocation.is_some()
+        || rebalance_tolerance_bps.is_some()
+        || max_premium_slot.is_some()
+        || swap_tolerance_bps.is_some()
+        || max_swap_amount.is_some()
+        || swap_cooldown.is_some()
+    {
+        check_role(deps.as_ref(), &info.sender, Role::ConfigManager)?;
+    }
+    let mut state = STATE.load(deps.storage)?;
+    let mut attributes = vec![attr("action", "update_config"), attr("from", info.sender)];
+    if let Some(paused) = paused {
+        if paused != state.paused {
+            state.paused = paused;
+            // Chains reject empty attribute values
+            let operations = paused.operations();
+            attributes.push(attr(
+                "paused",
+                if operations.is_empty() {
+                    "none".to_string()
+                } else {
+                    operations.join(",")
+                },
+            ));
+        }
+    }
+    if let Some(b_luna_discount_bps) = b_luna_discount_bps {
+        check_bps("b_luna_discount_bps", b_luna_discount_bps)?;
+        if b_luna_discount_bps != state.b_luna_discount_bps {
+            state.b_luna_discount_bps = b_luna_discount_bps;
+            attributes.push(attr("b_luna_discount_bps", b_luna_discount_bps.to_string()));
+        }
+    }
+    if let Some(max_price_age) = max_price_age {
+        if max_price_age != state.max_price_age {
+            state.max_price_age = max_price_age;
+            attributes.push(attr("max_price_age", max_price_age.to_string()));
+        }
+    }
+    // A zero bound removes it
+    if let Some(min_b_luna_price) = min_b_luna_price {
+        let min_b_luna_price = Some(min_b_luna_price).filter(|price| !price.is_zero());
+        if min_b_luna_price != state.min_b_luna_price {
+            state.min_b_luna_price = min_b_luna_price;
+            attributes.push(attr(
+                "min_b_luna_price",
+                min_b_luna_price.unwrap_or_default().to_string(),
+            ));
+        }
+    }
+    if let Some(max_b_luna_price) = max_b_luna_price {
+        let max_b_luna_price = Some(max_b_luna_price).filter(|price| !price.is_zero());
+        if max_b_luna_price != state.max_b_luna_price {
+            state.max_b_luna_price = max_b_luna_price;
+            attributes.push(attr(
+                "max_b_luna_price",
+                max_b_luna_price.unwrap_or_default().to_string(),
+            ));
+        }
+    }
+    check_price_bounds(&state)?;
+    if let Some(fee_collector) = fee_collector {
+        if fee_collector.to_string().to_lowercase() != state.fee_collector {
+            state.fee_collector = deps
+                .api
+                .addr_validate(fee_collector.to_string().to_lowercase().as_str())?;
+            attributes.push(attr("fee_collector", state.fee_collector.to_string()));
+        }
+    }
+    let management_fee_bps = management_fee_bps.filter(|bps| *bps != state.management_fee_bps);
+    let performance_fee_bps = performance_fee_bps.filter(|bps| *bps != state.performance_fee_bps);
+    if management_fee_bps.is_some() || performance_fee_bps.is_some() {
+        // Settle fees at the old rates first
+        let nav = query_nav(deps.as_ref(), &env, &state)?;
+        check_price(&state, &env, &nav)?;
+        collect_fees(deps.storage, &env, &mut state, nav.total_cap()?)?;
+    }
+    if let Some(management_fee_bps) = management_fee_bps {
+        check_max_bps(
+            "management_fee_bps",
+            management_fee_bps,
+            MAX_MANAGEMENT_FEE_BPS,
+        )?;
+        state.management_fee_bps = management_fee_bps;
+        attributes.push(attr("management_fee_bps", management_fee_bps.to_string()));
+    }
+    if let Some(performance_fee_bps) = performance_fee_bps {
+        check_max_bps(
+            "performance_fee_bps",
+            performance_fee_bps,
+            MAX_PERFORMANCE_FEE_BPS,
+        )?;
+        state.performance_fee_bps = performance_fee_bps;
+        attributes.push(attr("performance_fee_bps", performance_fee_bps.to_string()));
+    }
+    if let Some(max_premium_slot) = max_premium_slot {
+        if max_premium_slot != state.max_premium_slot {
+            state.max_premium_slot = max_premium_slot;
+            attributes.push(attr("max_premium_slot", max_premium_slot.to_string()));
+        }
+    }
+    if let Some(target_allocation) = target_allocation {
+        if target_allocation != state.target_allocation {
+            for allocation in target_allocation.iter() {
+                attributes.push(attr(
+                    "target_allocation",
+                    format!("{}:{}", allocation.premium_slot, allocation.bps),
+                ));
+            }
+            if target_allocation.is_empty() {
+                attributes.push(attr("target_allocation", ""));
+            }
+            state.target_allocation = target_allocation;
+        }
+    }
+    if let Some(rebalance_tolerance_bps) = rebalance_tolerance_bps {
+        check_bps("rebalance_tolerance_bps", rebalance_tolerance_bps)?;
+        if rebalance_tolerance_bps != state.rebalance_tolerance_bps {
+            state.rebalance_tolerance_bps = rebalance_tolerance_bps;
+            attributes.push(attr(
+                "rebalance_tolerance_bps",
+                rebalance_tolerance_bps.to_string(),
+            ));
+        }
+    }
+    if let Some(swap_tolerance_bps) = swap_tolerance_bps {
+        check_max_bps(
+            "swap_tolerance_bps",
+            swap_tolerance_bps,
+            MAX_SWAP_TOLERANCE_BPS,
+        )?;
+        if swap_tolerance_bps != state.swap_tolerance_bps {
+            state.swap_tolerance_bps = swap_tolerance_bps;
+            attributes.push(attr("swap_tolerance_bps", swap_tolerance_bps.to_string()));
+        }
+    }
+    if let Some(max_swap_amount) = max_swap_amount {
+        let max_swap_amount = Some(max_swap_amount).filter(|amount| !amount.is_zero());
+        if max_swap_amount != state.max_swap_amount {
+            state.max_swap_amount = max_swap_amount;
+            attributes.push(attr("max_swap_amount", max_swap_amount.unwrap_or_default()));
+        }
+    }
+    if let Some(swap_cooldown) = swap_cooldown {
+        if swap_cooldown != state.swap_cooldown {
+            state.swap_cooldown = swap_cooldown;
+            attributes.push(attr("swap_cooldown", swap_cooldown.to_string()));
+        }
+    }
     if attributes.len() <= 2 {
-        return Err(Invalidate {});
+        return Err(NoConfigChange {});
     }
+    // A lower max slot must still cover the target allocation
+    validate_allocation(&state.target_allocation, state.max_premium_slot)?;
     STATE.save(deps.storage, &state)?;
     Ok(Response::new().add_attributes(attributes))
 }
 
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn migrate(mut deps: DepsMut, env: Env, _msg: MigrateMsg) -> Result<Response, ContractError> {
+    let stored = get_contract_version(deps.storage)?;
+    if stored.contract != CONTRACT_NAME {
+        return Err(InvalidContract {
+            contract: stored.contract,
+        });
+    }
+    let stored_version = Version::parse(&stored.version)?;
+    let current_version = Version::parse(CONTRACT_VERSION)?;
+    if stored_version > current_version {
+        return Err(Downgrade {
+            from: stored.version,
+            to: CONTRACT_VERSION.to_string(),
+        });
+    }
+    // Convert older layouts step by step
+    if stored_version < Version::new(0, 2, 0) {
+        migrate_v0_1_0(deps.branch(), &env)?;
+    }
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+    Ok(Response::new()
+        .add_attribute("method", "migrate")
+        .add_attribute("from", stored.version)
+        .add_attribute("to", CONTRACT_VERSION))
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn reply(deps: DepsMut, env: Env, msg: Reply) -> Result<Response, ContractError> {
+    if msg.id == RETRACT_REPLY_ID {
+        let error = match msg.result {
+            ContractResult::Err(error) => error,
+            ContractResult::Ok(_) => return Err(UnknownReply { id: msg.id }),
+        };
+        return Ok(Response::new()
+            .add_event(Event::new("shutdown_retract_failed").add_attribute("error", error)));
+    }
+    let key = U64Key::new(msg.id);
+    let pending = PENDING_SETTLEMENTS
+        .may_load(deps.storage, key.clone())?
+        .ok_or(UnknownReply { id: msg.id })?;
+    PENDING_SETTLEMENTS.remove(deps.storage, key);
+    let mut state = STATE.load(deps.storage)?;
+    let balance = match msg.id {
+        CLAIM_REPLY_ID => b_luna_balance_of(deps.as_ref(), &env, &state)?,
+        _ => ust_balance(deps.as_ref(), &env)?,
+    };
+    let received = balance.checked_sub(pending.balance_before)?;
+    if received < pending.minimum_receive {
+        return Err(SlippageExceeded {
+            minimum: pending.minimum_receive,
+            actual: received,
+        });
+    }
+    let mut stats = SETTLEMENT_STATS.may_load(deps.storage)?.unwrap_or_default();
+    let event = match msg.id {
+        SWAP_REPLY_ID => {
+            stats.swaps += 1;
+            stats.swapped_b_luna += pending.amount;
+            stats.swap_proceeds += received;
+            Event::new("swap_settled")
+                .add_attribute("amount", pending.amount)
+                .add_attribute("received", received)
+                .add_attribute("minimum_receive", pending.minimum_receive)
+                .add_attribute(
+                    "realized_price",
+                    Decimal::from_ratio(received, pending.amount).to_string(),
+                )
+        }
+        REDEEM_REPLY_ID => {
+            stats.redemptions += 1;
+            stats.redeemed_a_ust += pending.amount;
+            stats.redemption_proceeds += received;
+            Event::new("redeem_settled")
+                .add_attribute("amount", pending.amount)
+                .add_attribute("received", received)
+                .add_attribute(
+                    "realized_rate",
+                    Decimal::from_ratio(received, pending.amount).to_string(),
+                )
+        }
+        _ => {
+            if !received.is_zero() {
+                let last_key = CLAIM_LIST.keys(deps.storage, None, None, Ascending).last();
+                let new_key = if let Some(value) = last_key {
+                    u32::from_be_bytes(value.as_slice().try_into().unwrap()) + 1
+                } else {
+                    0
+                };
+                CLAIM_LIST.save(
+                    deps.storage,
+                    U32Key::from(new_key),
+                    &TokenRecord {
+                        amount: received,
+                        timestamp: env.block.time,
+                    },
+                )?;
+                state.locked_b_luna += received;
+                STATE.save(deps.storage, &state)?;
+            }
+            stats.claims += 1;
+            stats.claimed_b_luna += received;
+            Event::new("claim_settled")
+                .add_attribute("expected", pending.amount)
+                .add_attribute("received", received)
+        }
+    };
+    SETTLEMENT_STATS.save(deps.storage, &stats)?;
+    Ok(Response::new().add_event(event))
+}
+
+fn ust_balance(deps: Deps, env: &Env) -> StdResult<Uint128> {
+    Ok(deps
+        .querier
+        .query_balance(&env.contract.address, "uusd")?
+        .amount)
+}
+
+fn b_luna_balance_of(deps: Deps, env: &Env, state: &State) -> StdResult<Uint128> {
+    let response: Cw20BalanceResponse = deps.querier.query_wasm_smart(
+        state.collateral_token.to_string(),
+        &ExternalQueryMsg::Balance {
+            address: env.contract.address.to_string(),
+        },
+    )?;
+    Ok(response.balance)
+}
+
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
     match msg {
@@ -1015,20 +2553,185 @@ pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
         QueryMsg::Balance { address } => to_binary(&query_balance(deps, address)?),
         // Get total cap in vault and anchor
         QueryMsg::TotalCap {} => to_binary(&query_total_cap(deps, env)?),
+        // Get breakdown of total cap
+        QueryMsg::Nav {} => to_binary(&query_nav_breakdown(deps, env)?),
         // Return true if liquidate is needed
         QueryMsg::Claimable {} => to_binary(&query_claimable(deps, env)?),
+        QueryMsg::PauseStatus {} => to_binary(&STATE.load(deps.storage)?.paused),
+        QueryMsg::SimulateSwap { amount, venue } => {
+            to_binary(&query_simulate_swap(deps, env, amount, venue)?)
+        }
+        QueryMsg::NextSwap {} => to_binary(&query_next_swap(deps, env)?),
+        QueryMsg::SettlementStats {} => {
+            to_binary(&SETTLEMENT_STATS.may_load(deps.storage)?.unwrap_or_default())
+        }
+        QueryMsg::ShutdownBasket {} => to_binary(&ShutdownBasketResponse {
+            basket: SHUTDOWN_BASKET.may_load(deps.storage)?,
+            total_share: total_share(deps.storage)?,
+        }),
+        QueryMsg::PendingOwner {} => to_binary(&query_pending_owner(deps)?),
+        QueryMsg::PendingConfigChange {} => to_binary(&PendingConfigChangeResponse {
+            pending: PENDING_CONFIG_CHANGE.may_load(deps.storage)?,
+        }),
         QueryMsg::Permission { address } => to_binary(&query_permission(deps, address)?),
+        QueryMsg::ListPermissions { start_after, limit } => {
+            to_binary(&query_permissions(deps, start_after, limit)?)
+        }
         QueryMsg::Unlockable {} => to_binary(&query_unlockable(deps, env)?),
         QueryMsg::LastDepositTimestamp { address } => {
             to_binary(&query_last_deposit_timestamp(deps, address)?)
         }
+        QueryMsg::StrategyPresets { start_after, limit } => {
+            to_binary(&query_strategy_presets(deps, start_after, limit)?)
+        }
+        QueryMsg::AccruedFees {} => to_binary(&query_accrued_fees(deps, env)?),
+        QueryMsg::HighWaterMark {} => to_binary(&query_high_water_mark(deps)?),
+        QueryMsg::WithdrawalRequests { start_after, limit } => {
+            to_binary(&query_withdrawal_requests(deps, None, start_after, limit)?)
+        }
+        QueryMsg::WithdrawalRequestsByOwner {
+            owner,
+            start_after,
+            limit,
+        } => {
+            let owner = deps.api.addr_validate(&owner)?;
+            to_binary(&query_withdrawal_requests(
+                deps,
+                Some(owner),
+                start_after,
+                limit,
+            )?)
+        }
+        QueryMsg::TokenInfo {} => to_binary(&query_token_info(deps)?),
+        QueryMsg::Allowance { owner, spender } => {
+            to_binary(&query_allowance(deps, owner, spender)?)
+        }
+        QueryMsg::AllAllowances {
+            owner,
+            start_after,
+            limit,
+        } => to_binary(&query_all_allowances(deps, owner, start_after, limit)?),
+        QueryMsg::AllAccounts { start_after, limit } => {
+            to_binary(&query_all_accounts(deps, start_after, limit)?)
+        }
     }
 }
 
+fn query_simulate_swap(
+    deps: Deps,
+    env: Env,
+    amount: Option<Uint128>,
+    venue: Option<SwapVenue>,
+) -> StdResult<SimulateSwapResponse> {
+    let state = STATE.load(deps.storage)?;
+    let nav = query_nav(deps, &env, &state)?;
+    let amount = amount.unwrap_or(nav.unlocked_b_luna);
+    let return_amount = match venue.unwrap_or(SwapVenue::Router) {
+        SwapVenue::Router => {
+            let res: SimulateSwapOperationsResponse = deps.querier.query_wasm_smart(
+                state.astroport_router.to_string(),
+                &ExternalQueryMsg::SimulateSwapOperations {
+                    offer_amount: amount,
+                    operations: state.swap_route.clone(),
+                },
+            )?;
+            res.amount
+        }
+        SwapVenue::AstroportPair => {
+            let pair = state
+                .astroport_pair
+                .as_ref()
+                .ok_or_else(|| StdError::generic_err(NoAstroportPair {}.to_string()))?;
+            let res: SimulationResponse = deps.querier.query_wasm_smart(
+                pair.to_string(),
+                &ExternalQueryMsg::Simulation {
+                    offer_asset: Asset {
+                        info: Token {
+                            contract_addr: state.collateral_token.clone(),
+                        },
+                        amount,
+                    },
+                },
+            )?;
+            res.return_amount
+        }
+    };
+    Ok(SimulateSwapResponse {
+        amount,
+        return_amount,
+        minimum_receive: swap_minimum(&state, &nav, amount)?,
+    })
+}
+
+fn query_next_swap(deps: Deps, env: Env) -> StdResult<NextSwapResponse> {
+    let state = STATE.load(deps.storage)?;
+    let nav = query_nav(deps, &env, &state)?;
+    Ok(NextSwapResponse {
+        next_swap_at: next_swap_at(&state),
+        max_amount: max_swap_amount(&state, nav.unlocked_b_luna),
+    })
+}
+
+fn query_accrued_fees(deps: Deps, env: Env) -> StdResult<AccruedFees> {
+    let state = STATE.load(deps.storage)?;
+    let total_cap = query_nav(deps, &env, &state)?.total_cap()?;
+    compute_fees(&state, &env, total_cap, outstanding_share(deps.storage)?)
+}
+
+fn query_high_water_mark(deps: Deps) -> StdResult<HighWaterMarkResponse> {
+    let state = STATE.load(deps.storage)?;
+    Ok(HighWaterMarkResponse {
+        high_water_mark: state.high_water_mark,
+        last_fee_collection: state.last_fee_collection,
+    })
+}
+
+fn query_strategy_presets(
+    deps: Deps,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<StrategyPresetsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive);
+    let presets = STRATEGY_PRESETS
+        .range(deps.storage, start, None, Ascending)
+        .take(limit)
+        .map(|item| {
+            let (name, strategy) = item?;
+            Ok(StrategyPreset {
+                name: String::from_utf8(name)?,
+                strategy,
+            })
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+    Ok(StrategyPresetsResponse { presets })
+}
+
+fn query_withdrawal_requests(
+    deps: Deps,
+    owner: Option<Addr>,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<WithdrawalRequestsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive_int);
+    let requests = WITHDRAWAL_REQUESTS
+        .range(deps.storage, start, None, Ascending)
+        .map(|item| item.map(|(_, request)| request))
+        .filter(|item| {
+            item.as_ref().map_or(true, |request| {
+                owner.as_ref().map_or(true, |owner| request.owner == *owner)
+            })
+        })
+        .take(limit)
+        .collect::<StdResult<Vec<_>>>()?;
+    Ok(WithdrawalRequestsResponse { requests })
+}
+
 fn query_info(deps: Deps) -> StdResult<InfoResponse> {
     let state = STATE.load(deps.storage)?;
     Ok(InfoResponse {
-        total_supply: state.total_supply,
+        total_supply: total_share(deps.storage)?,
         locked_b_luna: state.locked_b_luna,
     })
 }
@@ -1038,128 +2741,98 @@ fn query_config(deps: Deps) -> StdResult<ConfigResponse> {
     Ok(ConfigResponse {
         owner: state.owner.to_string(),
         paused: state.paused,
+        shutdown: state.shutdown,
         swap_wallet: state.swap_wallet.to_string(),
         collateral_token: state.collateral_token.to_string(),
         price_oracle: state.price_oracle.to_string(),
         astroport_router: state.astroport_router.to_string(),
         lock_period: state.lock_period,
         withdraw_lock: state.withdraw_lock,
+        timelock_delay: state.timelock_delay,
         anchor_market: state.anchor_market.to_string(),
         a_ust: state.a_ust.to_string(),
         kujira_a_ust_vault: state.kujira_a_ust_vault.to_string(),
         bid_strategy: state.bid_strategy,
+        b_luna_discount_bps: state.b_luna_discount_bps,
+        max_price_age: state.max_price_age,
+        min_b_luna_price: state.min_b_luna_price,
+        max_b_luna_price: state.max_b_luna_price,
+        fee_collector: state.fee_collector.to_string(),
+        management_fee_bps: state.management_fee_bps,
+        performance_fee_bps: state.performance_fee_bps,
+        target_allocation: state.target_allocation,
+        rebalance_tolerance_bps: state.rebalance_tolerance_bps,
+        max_premium_slot: state.max_premium_slot,
+        swap_tolerance_bps: state.swap_tolerance_bps,
+        swap_route: state.swap_route,
+        astroport_pair: state.astroport_pair.map(|pair| pair.to_string()),
+        max_swap_amount: state.max_swap_amount,
+        swap_cooldown: state.swap_cooldown,
     })
 }
 
-fn query_balance(deps: Deps, address: String) -> StdResult<BalanceResponse> {
-    let address = deps.api.addr_canonicalize(&address)?;
-    let balance = BALANCES
-        .may_load(deps.storage, address.as_slice())?
-        .unwrap_or_default();
-    Ok(BalanceResponse { balance })
-}
-
 fn query_total_cap(deps: Deps, env: Env) -> StdResult<TotalCapResponse> {
-    let mut usd_balance = deps
-        .querier
-        .query_balance(&env.contract.address, "uusd")?
-        .amount;
     let state = STATE.load(deps.storage)?;
-    let a_ust_balance_response: Cw20BalanceResponse = deps.querier.query_wasm_smart(
-        state.a_ust.to_string(),
-        &ExternalQueryMsg::Balance {
-            address: env.contract.address.to_string(),
-        },
-    )?;
-    let mut a_ust_balance = a_ust_balance_response.balance;
-    let b_luna_balance_response: Cw20BalanceResponse = deps.querier.query_wasm_smart(
-        state.collateral_token.to_string(),
-        &ExternalQueryMsg::Balance {
-            address: env.contract.address.to_string(),
-        },
-    )?;
-    let mut b_luna_balance = b_luna_balance_response.balance;
-    let mut start_after: Option<u64> = Some(0u64);
-    loop {
-        let res: KujiraBidsResponse = deps.querier.query_wasm_smart(
-            state.kujira_a_ust_vault.to_string(),
-            &ExternalQueryMsg::BidsByUser {
-                collateral_token: state.collateral_token.to_string(),
-                bidder: env.contract.address.to_string(),
-                start_after,
-                limit: Some(31),
-            },
-        )?;
-        for item in &res.bids {
-            a_ust_balance += item.amount;
-            if let Some(proxied_bid) = item.proxied_bid.as_ref() {
-                usd_balance += Uint128::try_from(proxied_bid.amount)?;
-                b_luna_balance += Uint128::try_from(proxied_bid.pending_liquidated_collateral)?;
-            }
-        }
-        if res.bids.len() < 31 {
-            break;
-        }
-        start_after = Some(res.bids.last().unwrap().idx);
-    }
-    let epoch_state_response: EpochStateResponse = deps.querier.query_wasm_smart(
-        state.anchor_market,
-        &ExternalQueryMsg::EpochState {
-            block_height: Some(env.block.height),
-            distributed_interest: None,
-        },
-    )?;
-    let a_terra_exchange_rate = epoch_state_response.exchange_rate;
-    let price_response: PriceResponse = deps.querier.query_wasm_smart(
-        state.price_oracle.to_string(),
-        &ExternalQueryMsg::Price {
-            base: state.collateral_token.to_string(),
-            quote: "uusd".to_string(),
-        },
-    )?;
-    let price = price_response.rate;
-    let total_cap = Uint128::try_from(Uint256::from(b_luna_balance).mul(price))?
-        + Uint128::try_from(Uint256::from(a_ust_balance).mul(a_terra_exchange_rate))?
-        + usd_balance;
+    let total_cap = query_nav(deps, &env, &state)?.total_cap()?;
     Ok(TotalCapResponse { total_cap })
 }
 
+fn query_nav_breakdown(deps: Deps, env: Env) -> StdResult<Nav> {
+    let state = STATE.load(deps.storage)?;
+    query_nav(deps, &env, &state)
+}
+
 fn query_claimable(deps: Deps, env: Env) -> StdResult<ClaimableResponse> {
-    let mut start_after: Option<u64> = Some(0u64);
     let state = STATE.load(deps.storage)?;
-    loop {
-        let res: KujiraBidsResponse = deps.querier.query_wasm_smart(
-            state.kujira_a_ust_vault.to_string(),
-            &ExternalQueryMsg::BidsByUser {
-                collateral_token: state.collateral_token.to_string(),
-                bidder: env.contract.address.to_string(),
-                start_after,
-                limit: Some(31),
-            },
-        )?;
-        for item in &res.bids {
-            if let Some(proxied_bid) = item.proxied_bid.as_ref() {
-                if !proxied_bid.pending_liquidated_collateral.is_zero() {
-                    return Ok(ClaimableResponse { claimable: true });
-                }
-            }
-        }
-        if res.bids.len() < 31 {
-            break;
-        }
-        start_after = Some(res.bids.last().unwrap().idx);
-    }
-    Ok(ClaimableResponse { claimable: false })
+    let claimable = query_bids(deps, &env, &state)?
+        .iter()
+        .filter_map(|item| item.proxied_bid.as_ref())
+        .any(|proxied_bid| !proxied_bid.pending_liquidated_collateral.is_zero());
+    Ok(ClaimableResponse { claimable })
+}
+
+fn query_pending_owner(deps: Deps) -> StdResult<PendingOwnerResponse> {
+    let pending = PENDING_OWNER.may_load(deps.storage)?;
+    Ok(PendingOwnerResponse {
+        new_owner: pending
+            .as_ref()
+            .map(|pending| pending.new_owner.to_string()),
+        expiry: pending.and_then(|pending| pending.expiry),
+    })
 }
 
 fn query_permission(deps: Deps, address: String) -> StdResult<PermissionResponse> {
     let address = deps.api.addr_canonicalize(&address)?;
     let permission = PERMISSIONS
         .may_load(deps.storage, address.as_slice())?
-        .unwrap_or(Permission { submit_bid: false });
+        .unwrap_or_default();
     Ok(PermissionResponse { permission })
 }
 
+fn query_permissions(
+    deps: Deps,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<PermissionsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after
+        .map(|address| deps.api.addr_canonicalize(&address))
+        .transpose()?
+        .map(|address| Bound::exclusive(address.as_slice()));
+    let permissions = PERMISSIONS
+        .range(deps.storage, start, None, Ascending)
+        .take(limit)
+        .map(|item| {
+            let (address, permission) = item?;
+            Ok(AddressPermission {
+                address: deps.api.addr_humanize(&address.into())?.to_string(),
+                roles: permission.roles,
+            })
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+    Ok(PermissionsResponse { permissions })
+}
+
 fn query_unlockable(deps: Deps, env: Env) -> StdResult<UnlockableResponse> {
     let mut keys = CLAIM_LIST.keys(deps.storage, None, None, Order::Ascending);
     let state = STATE.load(deps.storage)?;
@@ -1,34 +1,215 @@
-use cosmwasm_std::{ConversionOverflowError, OverflowError, StdError};
+use cosmwasm_std::{
+    ConversionOverflowError, Decimal256, OverflowError, StdError, Timestamp, Uint128, Uint256,
+};
+use cw20::Expiration;
 use thiserror::Error;
 
+use crate::state::Role;
+
 #[derive(Error, Debug)]
 pub enum ContractError {
-    #[error("Standard Error")]
+    #[error("{0}")]
     Std(#[from] StdError),
 
-    #[error("Overflow")]
+    #[error("{0}")]
+    Cw20(#[from] cw20_base::ContractError),
+
+    #[error("Semver: {0}")]
+    Semver(#[from] semver::Error),
+
+    #[error("{0}")]
     OverflowError(#[from] OverflowError),
 
-    #[error("Conversion Overflow")]
+    #[error("{0}")]
     ConversionOverflowError(#[from] ConversionOverflowError),
 
     #[error("Divide By Zero")]
     DivideByZeroError {},
 
-    #[error("Unauthorized")]
-    Unauthorized {},
+    #[error("{address} does not have the {role} role")]
+    MissingRole { address: String, role: Role },
+
+    #[error("{address} is not the owner")]
+    OwnerRequired { address: String },
+
+    #[error("The owner cannot lose the admin role")]
+    OwnerAdminRequired {},
+
+    #[error("Proposed owner is already the owner")]
+    OwnerUnchanged {},
+
+    #[error("No ownership transfer is pending")]
+    NoPendingOwner {},
+
+    #[error("{address} is not the proposed owner")]
+    NotPendingOwner { address: String },
+
+    #[error("Ownership proposal expired at {expiry}")]
+    OwnershipProposalExpired { expiry: Expiration },
+
+    #[error("Paused: {operation}")]
+    Paused { operation: String },
+
+    #[error("Vault is shut down")]
+    ShutDown {},
+
+    #[error("Vault is not shut down")]
+    NotShutDown {},
+
+    #[error("Bids after {cursor} are not retracted yet")]
+    ShutdownBidsRemaining { cursor: u64 },
+
+    #[error("Expected a single non-zero {expected_denom} coin")]
+    InvalidFunds { expected_denom: String },
+
+    #[error("Amount must not be zero")]
+    ZeroAmount {},
+
+    #[error("Share must not be zero")]
+    ZeroShare {},
+
+    #[error("Share is worth nothing to withdraw")]
+    ZeroWithdrawAmount {},
+
+    #[error("No idle UST to deposit into Anchor")]
+    NoIdleUst {},
+
+    #[error("Token {token} cannot be deposited")]
+    UnsupportedToken { token: String },
+
+    #[error("Withdrawals are locked until {unlock_at}")]
+    WithdrawLocked { unlock_at: Timestamp },
+
+    #[error("Insufficient unlocked collateral: {available} available, {requested} requested")]
+    InsufficientUnlockedCollateral {
+        available: Uint128,
+        requested: Uint128,
+    },
+
+    #[error("Withdrawal request {id} does not exist")]
+    WithdrawalNotFound { id: u64 },
+
+    #[error("{address} did not request withdrawal {id}")]
+    NotRequester { id: u64, address: String },
+
+    #[error("Withdrawal request {id} is not filled yet")]
+    WithdrawalNotFilled { id: u64 },
+
+    #[error("Withdrawal request {id} is already filled")]
+    WithdrawalFilled { id: u64 },
+
+    #[error("No liquidated collateral to claim")]
+    NothingToClaim {},
+
+    #[error("No claimed collateral past the lock period")]
+    NothingToUnlock {},
+
+    #[error("No unlocked collateral to swap")]
+    NothingToSwap {},
+
+    #[error("Swap of {amount} exceeds the maximum of {max}")]
+    SwapAmountExceedsMax { amount: Uint128, max: Uint128 },
+
+    #[error("Next swap is allowed at {next_swap_at}")]
+    SwapCooldown { next_swap_at: Timestamp },
+
+    #[error("Invalid swap route: {reason}")]
+    InvalidSwapRoute { reason: String },
+
+    #[error("No Astroport pair is configured")]
+    NoAstroportPair {},
+
+    #[error("Bid {bid_idx} is not a bid of the vault")]
+    BidNotFound { bid_idx: u64 },
+
+    #[error("Cannot retract {amount} from a bid of {bid_amount}")]
+    InvalidRetractAmount {
+        amount: Uint128,
+        bid_amount: Uint128,
+    },
+
+    #[error("Strategy and preset are mutually exclusive")]
+    StrategyAndPreset {},
+
+    #[error("Strategy preset {name} does not exist")]
+    UnknownPreset { name: String },
+
+    #[error("Strategy preset name must not be empty")]
+    EmptyPresetName {},
+
+    #[error("Premium slot {premium_slot} appears more than once in the allocation")]
+    DuplicatePremiumSlot { premium_slot: u8 },
+
+    #[error("Premium slot {premium_slot} has a zero allocation")]
+    ZeroAllocation { premium_slot: u8 },
+
+    #[error("Allocation of {total_bps} bps exceeds 10000")]
+    AllocationExceedsMax { total_bps: u32 },
+
+    #[error("No target allocation to rebalance to")]
+    NoTargetAllocation {},
+
+    #[error("Bids are already within the rebalance tolerance")]
+    AlreadyBalanced {},
+
+    #[error("Role is unchanged")]
+    PermissionUnchanged {},
+
+    #[error("Config is unchanged")]
+    NoConfigChange {},
+
+    #[error("No config change is queued")]
+    NoPendingConfigChange {},
+
+    #[error("Config change cannot be executed before {executable_at}")]
+    TimelockNotExpired { executable_at: Timestamp },
+
+    #[error("{name} of {bps} bps exceeds 10000")]
+    InvalidBps { name: String, bps: u16 },
+
+    #[error("{name} of {bps} bps exceeds the maximum of {max}")]
+    BpsAboveMax { name: String, bps: u16, max: u16 },
+
+    #[error("Minimum bLuna price {min} is above maximum {max}")]
+    InvalidPriceBounds { min: Decimal256, max: Decimal256 },
+
+    #[error("Oracle price last updated at {updated_at} is older than {max_age} seconds")]
+    StalePrice { updated_at: u64, max_age: u64 },
+
+    #[error("Oracle price {price} is out of bounds")]
+    PriceOutOfBounds { price: Decimal256 },
+
+    #[error("No pending settlement for reply {id}")]
+    UnknownReply { id: u64 },
+
+    #[error("Slippage exceeded: {actual} is below minimum {minimum}")]
+    SlippageExceeded { minimum: Uint128, actual: Uint128 },
+
+    #[error("LTV {ltv} must be between 1 and 100")]
+    InvalidLtv { ltv: u8 },
+
+    #[error("Activate LTV {activate} is below deactivate LTV {deactivate}")]
+    ActivateLtvBelowDeactivate { activate: u8, deactivate: u8 },
 
-    #[error("Insufficient")]
-    Insufficient {},
+    #[error(
+        "Activate cumulative value {activate} is below deactivate cumulative value {deactivate}"
+    )]
+    ActivateValueBelowDeactivate {
+        activate: Uint256,
+        deactivate: Uint256,
+    },
 
-    #[error("Invalidate Input")]
-    Invalidate {},
+    #[error("Premium slot {premium_slot} is above the maximum {max_premium_slot}")]
+    InvalidPremiumSlot {
+        premium_slot: u8,
+        max_premium_slot: u8,
+    },
 
-    #[error("Locked")]
-    Locked {},
+    #[error("Cannot migrate from contract {contract}")]
+    InvalidContract { contract: String },
 
-    #[error("Paused")]
-    Paused {},
+    #[error("Cannot migrate from version {from} down to {to}")]
+    Downgrade { from: String, to: String },
     // Add any other custom errors you like here.
     // Look at https://docs.rs/thiserror/1.0.21/thiserror/ for details.
 }
@@ -0,0 +1,115 @@
+use cosmwasm_std::{Decimal256, Env, StdResult, Storage, Uint128, Uint256};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+
+use crate::share::{mint_share, outstanding_share};
+use crate::state::{State, STATE};
+
+const MAX_BPS: u128 = 10_000;
+const SECONDS_PER_YEAR: u64 = 365 * 24 * 60 * 60;
+
+/// Highest annual management fee
+pub const MAX_MANAGEMENT_FEE_BPS: u16 = 500;
+/// Highest share of gains taken as performance fee
+pub const MAX_PERFORMANCE_FEE_BPS: u16 = 3_000;
+/// Highest share of the total cap taken by a single collection
+pub const MAX_FEE_BPS: u16 = 5_000;
+
+/// Fees accrued since the last collection
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AccruedFees {
+    /// Management fee in uusd
+    pub management_fee: Uint128,
+    /// Performance fee in uusd
+    pub performance_fee: Uint128,
+    /// Shares minted to the fee collector for both fees
+    pub share: Uint128,
+    /// NAV per share before fees
+    pub nav_per_share: Decimal256,
+    /// High-water mark after collection
+    pub high_water_mark: Decimal256,
+}
+
+/// Fees owed at `env.block.time` on a vault worth total_cap uusd
+pub fn compute_fees(
+    state: &State,
+    env: &Env,
+    total_cap: Uint128,
+    total_supply: Uint128,
+) -> StdResult<AccruedFees> {
+    if total_supply.is_zero() || total_cap.is_zero() {
+        return Ok(AccruedFees {
+            management_fee: Uint128::zero(),
+            performance_fee: Uint128::zero(),
+            share: Uint128::zero(),
+            nav_per_share: Decimal256::one(),
+            // New shares start again from one uusd
+            high_water_mark: if total_supply.is_zero() {
+                Decimal256::one()
+            } else {
+                state.high_water_mark
+            },
+        });
+    }
+    let elapsed = env
+        .block
+        .time
+        .seconds()
+        .saturating_sub(state.last_fee_collection);
+    let management_fee_bps = state.management_fee_bps.min(MAX_MANAGEMENT_FEE_BPS);
+    let performance_fee_bps = state.performance_fee_bps.min(MAX_PERFORMANCE_FEE_BPS);
+    let mut management_fee = total_cap.multiply_ratio(
+        management_fee_bps as u128 * elapsed as u128,
+        MAX_BPS * SECONDS_PER_YEAR as u128,
+    );
+    let nav_per_share = Decimal256::from_ratio(total_cap, total_supply);
+    let mut performance_fee = Uint128::zero();
+    if nav_per_share > state.high_water_mark {
+        let gain = Uint128::try_from(
+            Uint256::from(total_supply) * (nav_per_share - state.high_water_mark),
+        )?;
+        performance_fee = gain.multiply_ratio(performance_fee_bps as u128, MAX_BPS);
+    }
+    // Long gaps accrue in full, only the collected fee is capped so shareholders keep the rest
+    let max_fee = total_cap.multiply_ratio(MAX_FEE_BPS as u128, MAX_BPS);
+    management_fee = management_fee.min(max_fee);
+    performance_fee = performance_fee.min(max_fee - management_fee);
+    let fee = management_fee + performance_fee;
+    // Shares worth fee after dilution
+    let remaining = total_cap.checked_sub(fee)?;
+    let share = if fee.is_zero() {
+        Uint128::zero()
+    } else {
+        fee.multiply_ratio(total_supply, remaining)
+    };
+    let high_water_mark = Decimal256::from_ratio(total_cap, total_supply + share);
+    Ok(AccruedFees {
+        management_fee,
+        performance_fee,
+        share,
+        nav_per_share,
+        high_water_mark: if high_water_mark > state.high_water_mark {
+            high_water_mark
+        } else {
+            state.high_water_mark
+        },
+    })
+}
+
+/// Mint accrued fees to the fee collector and save the state
+pub fn collect_fees(
+    storage: &mut dyn Storage,
+    env: &Env,
+    state: &mut State,
+    total_cap: Uint128,
+) -> StdResult<AccruedFees> {
+    let fees = compute_fees(state, env, total_cap, outstanding_share(storage)?)?;
+    if !fees.share.is_zero() {
+        mint_share(storage, &state.fee_collector, fees.share)?;
+    }
+    state.high_water_mark = fees.high_water_mark;
+    state.last_fee_collection = env.block.time.seconds();
+    STATE.save(storage, state)?;
+    Ok(fees)
+}
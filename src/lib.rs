@@ -1,6 +1,11 @@
 pub mod contract;
 mod error;
+pub mod fees;
+mod migration;
 pub mod msg;
+pub mod nav;
+mod rebalance;
+mod share;
 pub mod state;
 
 pub use crate::error::ContractError;
@@ -0,0 +1,163 @@
+use cosmwasm_std::{Addr, CanonicalAddr, Decimal256, DepsMut, Env, Order, StdResult, Uint128};
+use cw20_base::state::{TokenInfo, BALANCES, TOKEN_INFO};
+use cw_storage_plus::{Item, Map};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::msg::AssetInfo::{NativeToken, Token};
+use crate::msg::SwapOperation::{AstroSwap, NativeSwap};
+use crate::msg::{BidStrategy, SwapOperation};
+use crate::state::{PauseFlags, Permission, Role, State, PERMISSIONS, STATE};
+
+pub const DEFAULT_SHARE_NAME: &str = "Kallisto aUST Vault Share";
+pub const DEFAULT_SHARE_SYMBOL: &str = "kaUST";
+pub const DEFAULT_MAX_PRICE_AGE: u64 = 60 * 60;
+pub const DEFAULT_MAX_PREMIUM_SLOT: u8 = 30;
+pub const DEFAULT_TIMELOCK_DELAY: u64 = 2 * 24 * 60 * 60;
+pub const DEFAULT_SWAP_TOLERANCE_BPS: u16 = 100;
+
+// bLuna -> uluna on Astroport, then uluna -> uusd on the Terra market
+pub fn default_swap_route(collateral_token: &Addr) -> Vec<SwapOperation> {
+    vec![
+        AstroSwap {
+            offer_asset_info: Token {
+                contract_addr: collateral_token.clone(),
+            },
+            ask_asset_info: NativeToken {
+                denom: "uluna".to_string(),
+            },
+        },
+        NativeSwap {
+            offer_denom: "uluna".to_string(),
+            ask_denom: "uusd".to_string(),
+        },
+    ]
+}
+
+// State layout of v0.1.0, shares were tracked in State and BALANCES by canonical address
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+struct StateV0_1_0 {
+    pub owner: Addr,
+    pub total_supply: Uint128,
+    pub locked_b_luna: Uint128,
+    pub swap_wallet: Addr,
+    pub paused: bool,
+    pub collateral_token: Addr,
+    pub price_oracle: Addr,
+    pub astroport_router: Addr,
+    pub anchor_market: Addr,
+    pub a_ust: Addr,
+    pub kujira_a_ust_vault: Addr,
+    pub lock_period: u64,
+    pub withdraw_lock: u64,
+    pub bid_strategy: BidStrategy,
+}
+
+const STATE_V0_1_0: Item<StateV0_1_0> = Item::new("state");
+
+const BALANCES_V0_1_0: Map<&[u8], Uint128> = Map::new("balance");
+
+// v0.1.0 only knew bidders
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+struct PermissionV0_1_0 {
+    pub submit_bid: bool,
+}
+
+const PERMISSIONS_V0_1_0: Map<&[u8], PermissionV0_1_0> = Map::new("permission");
+
+pub fn migrate_v0_1_0(deps: DepsMut, env: &Env) -> StdResult<()> {
+    let old_state = STATE_V0_1_0.load(deps.storage)?;
+    let owner = deps.api.addr_canonicalize(old_state.owner.as_str())?;
+    let swap_route = default_swap_route(&old_state.collateral_token);
+    STATE.save(
+        deps.storage,
+        &State {
+            fee_collector: old_state.owner.clone(),
+            owner: old_state.owner,
+            locked_b_luna: old_state.locked_b_luna,
+            swap_wallet: old_state.swap_wallet,
+            // v0.1.0 only paused deposits
+            paused: PauseFlags {
+                deposit: old_state.paused,
+                ..PauseFlags::default()
+            },
+            collateral_token: old_state.collateral_token,
+            price_oracle: old_state.price_oracle,
+            astroport_router: old_state.astroport_router,
+            anchor_market: old_state.anchor_market,
+            a_ust: old_state.a_ust,
+            kujira_a_ust_vault: old_state.kujira_a_ust_vault,
+            lock_period: old_state.lock_period,
+            withdraw_lock: old_state.withdraw_lock,
+            bid_strategy: old_state.bid_strategy,
+            b_luna_discount_bps: 0,
+            max_price_age: DEFAULT_MAX_PRICE_AGE,
+            min_b_luna_price: None,
+            max_b_luna_price: None,
+            queued_ust: Uint128::zero(),
+            reserved_ust: Uint128::zero(),
+            escrowed_share: Uint128::zero(),
+            next_withdrawal_id: 1,
+            next_fill_id: 1,
+            management_fee_bps: 0,
+            performance_fee_bps: 0,
+            high_water_mark: Decimal256::one(),
+            last_fee_collection: env.block.time.seconds(),
+            target_allocation: vec![],
+            rebalance_tolerance_bps: 0,
+            max_premium_slot: DEFAULT_MAX_PREMIUM_SLOT,
+            timelock_delay: DEFAULT_TIMELOCK_DELAY,
+            swap_tolerance_bps: DEFAULT_SWAP_TOLERANCE_BPS,
+            swap_route,
+            astroport_pair: None,
+            max_swap_amount: None,
+            swap_cooldown: 0,
+            last_swap: 0,
+            shutdown: false,
+        },
+    )?;
+    TOKEN_INFO.save(
+        deps.storage,
+        &TokenInfo {
+            name: DEFAULT_SHARE_NAME.to_string(),
+            symbol: DEFAULT_SHARE_SYMBOL.to_string(),
+            decimals: 6,
+            total_supply: old_state.total_supply,
+            mint: None,
+        },
+    )?;
+    // Both maps share the "balance" namespace, so read everything before rewriting keys
+    let old_balances = BALANCES_V0_1_0
+        .range(deps.storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    for (key, balance) in old_balances {
+        BALANCES_V0_1_0.remove(deps.storage, &key);
+        let address = deps.api.addr_humanize(&CanonicalAddr::from(key))?;
+        BALANCES.save(deps.storage, &address, &balance)?;
+    }
+    // Bidders keep bidding, the owner becomes admin
+    let old_permissions = PERMISSIONS_V0_1_0
+        .range(deps.storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    for (key, permission) in old_permissions {
+        if key.as_slice() == owner.as_slice() || !permission.submit_bid {
+            PERMISSIONS_V0_1_0.remove(deps.storage, &key);
+        } else {
+            PERMISSIONS.save(
+                deps.storage,
+                &key,
+                &Permission {
+                    roles: vec![Role::Bidder],
+                },
+            )?;
+        }
+    }
+    PERMISSIONS.save(
+        deps.storage,
+        owner.as_slice(),
+        &Permission {
+            roles: vec![Role::Admin],
+        },
+    )?;
+    Ok(())
+}
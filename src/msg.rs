@@ -1,7 +1,14 @@
-use crate::state::Permission;
+use crate::state::{
+    PauseFlags, PendingConfigChange, Permission, Role, ShutdownBasket, WithdrawalRequest,
+};
+use crate::ContractError;
 use cosmwasm_std::{Addr, Binary, Decimal, Decimal256, Timestamp, Uint128, Uint256};
+use cw20::{Cw20ReceiveMsg, Expiration};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::fmt;
+
+pub const MAX_LTV: u8 = 100;
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct InstantiateMsg {
@@ -16,39 +23,194 @@ pub struct InstantiateMsg {
     pub lock_period: Option<u64>,
     pub withdraw_lock: Option<u64>,
     pub bid_strategy: Option<BidStrategy>,
+    pub b_luna_discount_bps: Option<u16>,
+    pub max_price_age: Option<u64>,
+    pub min_b_luna_price: Option<Decimal256>,
+    pub max_b_luna_price: Option<Decimal256>,
+    pub fee_collector: Option<Addr>,
+    pub management_fee_bps: Option<u16>,
+    pub performance_fee_bps: Option<u16>,
+    pub target_allocation: Option<Vec<SlotAllocation>>,
+    pub rebalance_tolerance_bps: Option<u16>,
+    pub max_premium_slot: Option<u8>,
+    pub timelock_delay: Option<u64>,
+    pub swap_tolerance_bps: Option<u16>,
+    // Router route for Swap, bLuna -> uluna -> uusd if not set
+    pub swap_route: Option<Vec<SwapOperation>>,
+    pub astroport_pair: Option<Addr>,
+    pub max_swap_amount: Option<Uint128>,
+    pub swap_cooldown: Option<u64>,
+    pub share_name: Option<String>,
+    pub share_symbol: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
+#[allow(clippy::large_enum_variant)]
 pub enum ExecuteMsg {
-    Deposit {},
+    Deposit {
+        min_shares_out: Option<Uint128>,
+    },
+    Receive(Cw20ReceiveMsg),
     WithdrawUst {
         share: Uint128,
+        min_amount_out: Option<Uint128>,
     },
     WithdrawBLuna {
         share: Uint128,
+        min_amount_out: Option<Uint128>,
     },
     SubmitBid {
         amount: Uint128,
         premium_slot: u8,
+        // Overrides the default bid strategy, exclusive with preset
+        strategy: Option<BidStrategy>,
+        preset: Option<String>,
+    },
+    SubmitBids {
+        // (premium_slot, amount) pairs
+        allocations: Vec<(u8, Uint128)>,
+    },
+    RetractBid {
+        bid_idx: u64,
+        // Retract the whole bid if not set
+        amount: Option<Uint128>,
+    },
+    Rebalance {},
+    RequestWithdrawal {
+        share: Uint128,
+    },
+    ProcessWithdrawals {
+        limit: Option<u32>,
     },
+    ClaimWithdrawal {
+        id: u64,
+    },
+    CancelWithdrawal {
+        id: u64,
+    },
+    CollectFees {},
     ClaimLiquidation {},
     Unlock {},
-    Swap {},
-    SetPermission {
+    Swap {
+        // All unlocked bLuna if not set
+        amount: Option<Uint128>,
+        // Raises the oracle-derived minimum, never lowers it
+        minimum_receive: Option<Uint128>,
+        // Router if not set
+        venue: Option<SwapVenue>,
+    },
+    GrantRole {
         address: Addr,
-        new_permission: Permission,
+        role: Role,
     },
-    UpdateConfig {
-        owner: Option<Addr>,
-        paused: Option<bool>,
+    RevokeRole {
+        address: Addr,
+        role: Role,
+    },
+    SetStrategyPreset {
+        name: String,
+        strategy: BidStrategy,
+    },
+    RemoveStrategyPreset {
+        name: String,
+    },
+    ProposeOwner {
+        new_owner: Addr,
+        // Proposal can be accepted forever if not set
+        expiry: Option<Expiration>,
+    },
+    AcceptOwnership {},
+    CancelOwnershipProposal {},
+    // Sensitive config, applied by ExecuteConfigChange after the timelock delay
+    QueueConfigChange {
         swap_wallet: Option<Addr>,
         lock_period: Option<u64>,
         withdraw_lock: Option<u64>,
         bid_strategy: Option<BidStrategy>,
+        timelock_delay: Option<u64>,
+        swap_route: Option<Vec<SwapOperation>>,
+        astroport_pair: Option<Addr>,
+    },
+    ExecuteConfigChange {},
+    CancelConfigChange {},
+    // Stops the vault, bids are then wound down by RetractShutdownBids
+    EmergencyShutdown {},
+    // Claims and retracts the next batch of bids after a shutdown
+    RetractShutdownBids {
+        limit: Option<u32>,
+    },
+    // Freezes the remaining assets once every bid is retracted
+    FinalizeShutdown {},
+    RedeemShutdownShare {
+        share: Uint128,
+    },
+    UpdateConfig {
+        paused: Option<PauseFlags>,
+        b_luna_discount_bps: Option<u16>,
+        max_price_age: Option<u64>,
+        min_b_luna_price: Option<Decimal256>,
+        max_b_luna_price: Option<Decimal256>,
+        fee_collector: Option<Addr>,
+        management_fee_bps: Option<u16>,
+        performance_fee_bps: Option<u16>,
+        target_allocation: Option<Vec<SlotAllocation>>,
+        rebalance_tolerance_bps: Option<u16>,
+        max_premium_slot: Option<u8>,
+        swap_tolerance_bps: Option<u16>,
+        // Zero removes the cap
+        max_swap_amount: Option<Uint128>,
+        swap_cooldown: Option<u64>,
+    },
+    // CW20 share token
+    Transfer {
+        recipient: String,
+        amount: Uint128,
+    },
+    Burn {
+        amount: Uint128,
+    },
+    Send {
+        contract: String,
+        amount: Uint128,
+        msg: Binary,
+    },
+    IncreaseAllowance {
+        spender: String,
+        amount: Uint128,
+        expires: Option<Expiration>,
+    },
+    DecreaseAllowance {
+        spender: String,
+        amount: Uint128,
+        expires: Option<Expiration>,
+    },
+    TransferFrom {
+        owner: String,
+        recipient: String,
+        amount: Uint128,
+    },
+    SendFrom {
+        owner: String,
+        contract: String,
+        amount: Uint128,
+        msg: Binary,
+    },
+    BurnFrom {
+        owner: String,
+        amount: Uint128,
     },
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MigrateMsg {}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiveMsg {
+    Deposit { min_shares_out: Option<Uint128> },
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum ExternalMsg {
@@ -59,6 +221,7 @@ pub enum ExternalMsg {
     },
     RetractBid {
         bid_idx: u64,
+        amount: Option<Uint128>,
     },
     ClaimLiquidations {
         collateral_token: Addr,
@@ -79,8 +242,11 @@ pub enum ExternalMsg {
         amount: Uint128,
         msg: Binary,
     },
+    // Astroport pair cw20 hook
     Swap {
-        to: Addr,
+        belief_price: Option<Decimal>,
+        max_spread: Option<Decimal>,
+        to: Option<String>,
     },
     DepositStable {},
     RedeemStable {},
@@ -92,12 +258,64 @@ pub enum QueryMsg {
     // GetCount returns the current count as a json-encoded number
     GetInfo {},
     Config {},
-    Balance { address: String },
+    Balance {
+        address: String,
+    },
     TotalCap {},
+    Nav {},
     Claimable {},
-    Permission { address: String },
+    PauseStatus {},
+    ShutdownBasket {},
+    SimulateSwap {
+        // All unlocked bLuna if not set
+        amount: Option<Uint128>,
+        venue: Option<SwapVenue>,
+    },
+    NextSwap {},
+    SettlementStats {},
+    PendingOwner {},
+    PendingConfigChange {},
+    Permission {
+        address: String,
+    },
+    ListPermissions {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
     Unlockable {},
-    LastDepositTimestamp { address: String },
+    LastDepositTimestamp {
+        address: String,
+    },
+    AccruedFees {},
+    HighWaterMark {},
+    StrategyPresets {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    WithdrawalRequests {
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    WithdrawalRequestsByOwner {
+        owner: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    // CW20 share token
+    TokenInfo {},
+    Allowance {
+        owner: String,
+        spender: String,
+    },
+    AllAllowances {
+        owner: String,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    AllAccounts {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
 }
 
 // We define a custom struct for each query response
@@ -110,22 +328,56 @@ pub struct InfoResponse {
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct ConfigResponse {
     pub owner: String,
-    pub paused: bool,
+    pub paused: PauseFlags,
+    pub shutdown: bool,
     pub swap_wallet: String,
     pub collateral_token: String,
     pub price_oracle: String,
     pub astroport_router: String,
     pub lock_period: u64,
     pub withdraw_lock: u64,
+    pub timelock_delay: u64,
     pub anchor_market: String,
     pub a_ust: String,
     pub kujira_a_ust_vault: String,
     pub bid_strategy: BidStrategy,
+    pub b_luna_discount_bps: u16,
+    pub max_price_age: u64,
+    pub min_b_luna_price: Option<Decimal256>,
+    pub max_b_luna_price: Option<Decimal256>,
+    pub fee_collector: String,
+    pub management_fee_bps: u16,
+    pub performance_fee_bps: u16,
+    pub target_allocation: Vec<SlotAllocation>,
+    pub rebalance_tolerance_bps: u16,
+    pub max_premium_slot: u8,
+    pub swap_tolerance_bps: u16,
+    pub swap_route: Vec<SwapOperation>,
+    pub astroport_pair: Option<String>,
+    pub max_swap_amount: Option<Uint128>,
+    pub swap_cooldown: u64,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
-pub struct BalanceResponse {
-    pub balance: Uint128,
+pub struct HighWaterMarkResponse {
+    pub high_water_mark: Decimal256,
+    pub last_fee_collection: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct StrategyPreset {
+    pub name: String,
+    pub strategy: BidStrategy,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct StrategyPresetsResponse {
+    pub presets: Vec<StrategyPreset>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct WithdrawalRequestsResponse {
+    pub requests: Vec<WithdrawalRequest>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -143,11 +395,58 @@ pub struct ClaimableResponse {
     pub claimable: bool,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SimulateSwapResponse {
+    pub amount: Uint128,
+    // UST the venue would return
+    pub return_amount: Uint128,
+    // Oracle-derived minimum Swap would accept
+    pub minimum_receive: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct NextSwapResponse {
+    // Swaps fail with SwapCooldown before this time
+    pub next_swap_at: Timestamp,
+    // Unlocked bLuna a swap may sell, capped by max_swap_amount
+    pub max_amount: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ShutdownBasketResponse {
+    // Not set until the shutdown is finalized
+    pub basket: Option<ShutdownBasket>,
+    // Shares redeemable against the basket
+    pub total_share: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingOwnerResponse {
+    pub new_owner: Option<String>,
+    pub expiry: Option<Expiration>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingConfigChangeResponse {
+    pub pending: Option<PendingConfigChange>,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct PermissionResponse {
     pub permission: Permission,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AddressPermission {
+    pub address: String,
+    pub roles: Vec<Role>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PermissionsResponse {
+    pub permissions: Vec<AddressPermission>,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct UnlockableResponse {
     pub unlockable: bool,
@@ -174,6 +473,13 @@ pub enum ExternalQueryMsg {
         block_height: Option<u64>,
         distributed_interest: Option<Uint256>,
     },
+    SimulateSwapOperations {
+        offer_amount: Uint128,
+        operations: Vec<SwapOperation>,
+    },
+    Simulation {
+        offer_asset: Asset,
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -208,6 +514,25 @@ pub struct TimestampResponse {
     pub timestamp: Timestamp,
 }
 
+// Where Swap sells unlocked bLuna
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SwapVenue {
+    // Astroport router along the configured swap route
+    Router,
+    // Configured Astroport bLuna-UST pair
+    AstroportPair,
+}
+
+impl fmt::Display for SwapVenue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SwapVenue::Router => "router",
+            SwapVenue::AstroportPair => "astroport_pair",
+        })
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum SwapOperation {
@@ -221,6 +546,28 @@ pub enum SwapOperation {
     },
 }
 
+impl SwapOperation {
+    pub fn offer_asset_info(&self) -> AssetInfo {
+        match self {
+            SwapOperation::NativeSwap { offer_denom, .. } => AssetInfo::NativeToken {
+                denom: offer_denom.clone(),
+            },
+            SwapOperation::AstroSwap {
+                offer_asset_info, ..
+            } => offer_asset_info.clone(),
+        }
+    }
+
+    pub fn ask_asset_info(&self) -> AssetInfo {
+        match self {
+            SwapOperation::NativeSwap { ask_denom, .. } => AssetInfo::NativeToken {
+                denom: ask_denom.clone(),
+            },
+            SwapOperation::AstroSwap { ask_asset_info, .. } => ask_asset_info.clone(),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum AssetInfo {
@@ -228,6 +575,33 @@ pub enum AssetInfo {
     NativeToken { denom: String },
 }
 
+impl fmt::Display for AssetInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AssetInfo::Token { contract_addr } => write!(f, "{}", contract_addr),
+            AssetInfo::NativeToken { denom } => write!(f, "{}", denom),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Asset {
+    pub info: AssetInfo,
+    pub amount: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SimulateSwapOperationsResponse {
+    pub amount: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SimulationResponse {
+    pub return_amount: Uint128,
+    pub spread_amount: Uint128,
+    pub commission_amount: Uint128,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct EpochStateResponse {
     pub exchange_rate: Decimal256,
@@ -258,8 +632,46 @@ pub struct BidStrategy {
     pub deactivate_at: CumulativeLoanAmount,
 }
 
+// Share of the bidding aUST to keep at a premium slot
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SlotAllocation {
+    pub premium_slot: u8,
+    pub bps: u16,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct CumulativeLoanAmount {
     pub ltv: u8,
     pub cumulative_value: Uint256,
 }
+
+impl CumulativeLoanAmount {
+    /// LTV is a percentage of the collateral value
+    pub fn validate(&self) -> Result<(), ContractError> {
+        if self.ltv == 0 || self.ltv > MAX_LTV {
+            return Err(ContractError::InvalidLtv { ltv: self.ltv });
+        }
+        Ok(())
+    }
+}
+
+impl BidStrategy {
+    /// A bid deactivates no later than it activates
+    pub fn validate(&self) -> Result<(), ContractError> {
+        self.activate_at.validate()?;
+        self.deactivate_at.validate()?;
+        if self.activate_at.ltv < self.deactivate_at.ltv {
+            return Err(ContractError::ActivateLtvBelowDeactivate {
+                activate: self.activate_at.ltv,
+                deactivate: self.deactivate_at.ltv,
+            });
+        }
+        if self.activate_at.cumulative_value < self.deactivate_at.cumulative_value {
+            return Err(ContractError::ActivateValueBelowDeactivate {
+                activate: self.activate_at.cumulative_value,
+                deactivate: self.deactivate_at.cumulative_value,
+            });
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,235 @@
+use cosmwasm_std::{Decimal256, Deps, Env, Fraction, StdError, StdResult, Uint128, Uint256};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::ops::Mul;
+
+use crate::msg::{
+    Cw20BalanceResponse, EpochStateResponse, ExternalQueryMsg, KujiraBidResponse,
+    KujiraBidsResponse, PriceResponse,
+};
+use crate::state::State;
+
+// Kujira returns at most 31 bids per page
+const BIDS_PAGE_LIMIT: u8 = 31;
+
+/// Breakdown of the vault's net asset value
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Nav {
+    /// UST held by the vault
+    pub idle_ust: Uint128,
+    /// aUST held by the vault
+    pub a_ust: Uint128,
+    /// aUST placed in Kujira bids
+    pub a_ust_in_bids: Uint128,
+    /// UST waiting for liquidation in activated (proxied) bids
+    pub ust_in_active_bids: Uint128,
+    /// Liquidated bLuna not yet claimed from Anchor
+    pub pending_b_luna: Uint128,
+    /// Claimed bLuna still inside the lock period
+    pub locked_b_luna: Uint128,
+    /// Claimed bLuna free to be swapped or withdrawn
+    pub unlocked_b_luna: Uint128,
+    /// aUST to UST exchange rate from Anchor
+    pub a_ust_exchange_rate: Decimal256,
+    /// bLuna price in uusd from the price oracle
+    pub b_luna_price: Decimal256,
+    /// Oldest update time of the oracle price, in seconds
+    pub b_luna_price_updated_at: u64,
+    /// UST set aside for filled withdrawal requests, excluded from idle_ust
+    pub reserved_ust: Uint128,
+    /// UST owed to unfilled withdrawal requests
+    pub queued_ust: Uint128,
+}
+
+impl Nav {
+    pub fn total_ust(&self) -> Uint128 {
+        self.idle_ust + self.ust_in_active_bids
+    }
+
+    /// Idle UST not needed by the withdrawal queue
+    pub fn free_ust(&self) -> Uint128 {
+        self.idle_ust.saturating_sub(self.queued_ust)
+    }
+
+    pub fn total_a_ust(&self) -> Uint128 {
+        self.a_ust + self.a_ust_in_bids
+    }
+
+    pub fn total_b_luna(&self) -> Uint128 {
+        self.pending_b_luna + self.locked_b_luna + self.unlocked_b_luna
+    }
+
+    /// UST value of an aUST amount
+    pub fn a_ust_value(&self, amount: Uint128) -> StdResult<Uint128> {
+        Ok(Uint128::try_from(
+            Uint256::from(amount).mul(self.a_ust_exchange_rate),
+        )?)
+    }
+
+    /// aUST amount worth the given UST
+    pub fn ust_to_a_ust(&self, amount: Uint128) -> StdResult<Uint128> {
+        let rate = self
+            .a_ust_exchange_rate
+            .inv()
+            .ok_or_else(|| StdError::generic_err("aUST exchange rate is zero"))?;
+        Ok(Uint128::try_from(Uint256::from(amount).mul(rate))?)
+    }
+
+    /// UST value of a bLuna amount
+    pub fn b_luna_value(&self, amount: Uint128) -> StdResult<Uint128> {
+        Ok(Uint128::try_from(
+            Uint256::from(amount).mul(self.b_luna_price),
+        )?)
+    }
+
+    /// bLuna amount worth the given UST
+    pub fn ust_to_b_luna(&self, amount: Uint128) -> StdResult<Uint128> {
+        let price = self
+            .b_luna_price
+            .inv()
+            .ok_or_else(|| StdError::generic_err("bLuna price is zero"))?;
+        Ok(Uint128::try_from(Uint256::from(amount).mul(price))?)
+    }
+
+    /// Total value in uusd, queued withdrawals included
+    pub fn gross_cap(&self) -> StdResult<Uint128> {
+        Ok(self.b_luna_value(self.total_b_luna())?
+            + self.a_ust_value(self.total_a_ust())?
+            + self.total_ust())
+    }
+
+    /// Total value in uusd, net of queued withdrawals. Zero once losses leave nothing beyond the queue
+    pub fn total_cap(&self) -> StdResult<Uint128> {
+        Ok(self.gross_cap()?.saturating_sub(self.queued_ust))
+    }
+
+    /// Total value in bLuna, net of queued withdrawals
+    pub fn total_cap_in_b_luna(&self) -> StdResult<Uint128> {
+        let ust = self.a_ust_value(self.total_a_ust())? + self.total_ust();
+        if ust >= self.queued_ust {
+            Ok(self.total_b_luna() + self.ust_to_b_luna(ust - self.queued_ust)?)
+        } else {
+            Ok(self
+                .total_b_luna()
+                .saturating_sub(self.ust_to_b_luna(self.queued_ust - ust)?))
+        }
+    }
+
+    /// UST paid for a queued amount, written down pro-rata when the vault is worth less than the queue
+    pub fn queued_payout(&self, amount: Uint128) -> StdResult<Uint128> {
+        let gross_cap = self.gross_cap()?;
+        if gross_cap >= self.queued_ust {
+            Ok(amount)
+        } else {
+            Ok(amount.multiply_ratio(gross_cap, self.queued_ust))
+        }
+    }
+}
+
+/// One page of the vault's bids in Kujira, ordered by idx
+pub fn query_bids_page(
+    deps: Deps,
+    env: &Env,
+    state: &State,
+    start_after: u64,
+    limit: u8,
+) -> StdResult<Vec<KujiraBidResponse>> {
+    let res: KujiraBidsResponse = deps.querier.query_wasm_smart(
+        state.kujira_a_ust_vault.to_string(),
+        &ExternalQueryMsg::BidsByUser {
+            collateral_token: state.collateral_token.to_string(),
+            bidder: env.contract.address.to_string(),
+            start_after: Some(start_after),
+            limit: Some(limit.min(BIDS_PAGE_LIMIT)),
+        },
+    )?;
+    Ok(res.bids)
+}
+
+/// Iterate all bids of the vault in Kujira
+pub fn query_bids(deps: Deps, env: &Env, state: &State) -> StdResult<Vec<KujiraBidResponse>> {
+    let mut bids = vec![];
+    let mut start_after = 0u64;
+    loop {
+        let page = query_bids_page(deps, env, state, start_after, BIDS_PAGE_LIMIT)?;
+        let len = page.len();
+        if let Some(bid) = page.last() {
+            start_after = bid.idx;
+        }
+        bids.extend(page);
+        if len < BIDS_PAGE_LIMIT as usize {
+            break;
+        }
+    }
+    Ok(bids)
+}
+
+pub fn query_nav(deps: Deps, env: &Env, state: &State) -> StdResult<Nav> {
+    let idle_ust = deps
+        .querier
+        .query_balance(&env.contract.address, "uusd")?
+        .amount
+        .checked_sub(state.reserved_ust)?;
+    let a_ust_balance_response: Cw20BalanceResponse = deps.querier.query_wasm_smart(
+        state.a_ust.to_string(),
+        &ExternalQueryMsg::Balance {
+            address: env.contract.address.to_string(),
+        },
+    )?;
+    let b_luna_balance_response: Cw20BalanceResponse = deps.querier.query_wasm_smart(
+        state.collateral_token.to_string(),
+        &ExternalQueryMsg::Balance {
+            address: env.contract.address.to_string(),
+        },
+    )?;
+    let unlocked_b_luna = b_luna_balance_response
+        .balance
+        .checked_sub(state.locked_b_luna)?;
+
+    let mut a_ust_in_bids = Uint128::zero();
+    let mut ust_in_active_bids = Uint128::zero();
+    let mut pending_b_luna = Uint128::zero();
+    for item in query_bids(deps, env, state)? {
+        a_ust_in_bids += item.amount;
+        if let Some(proxied_bid) = item.proxied_bid.as_ref() {
+            // Waiting UST for liquidation
+            ust_in_active_bids += Uint128::try_from(proxied_bid.amount)?;
+            // Pending bLuna in Anchor
+            pending_b_luna += Uint128::try_from(proxied_bid.pending_liquidated_collateral)?;
+        }
+    }
+
+    let epoch_state_response: EpochStateResponse = deps.querier.query_wasm_smart(
+        state.anchor_market.to_string(),
+        &ExternalQueryMsg::EpochState {
+            block_height: Some(env.block.height),
+            distributed_interest: None,
+        },
+    )?;
+    // Fetch bLuna price from oracle
+    let price_response: PriceResponse = deps.querier.query_wasm_smart(
+        state.price_oracle.to_string(),
+        &ExternalQueryMsg::Price {
+            base: state.collateral_token.to_string(),
+            quote: "uusd".to_string(),
+        },
+    )?;
+
+    Ok(Nav {
+        idle_ust,
+        a_ust: a_ust_balance_response.balance,
+        a_ust_in_bids,
+        ust_in_active_bids,
+        pending_b_luna,
+        locked_b_luna: state.locked_b_luna,
+        unlocked_b_luna,
+        a_ust_exchange_rate: epoch_state_response.exchange_rate,
+        b_luna_price: price_response.rate,
+        b_luna_price_updated_at: price_response
+            .last_updated_base
+            .min(price_response.last_updated_quote),
+        reserved_ust: state.reserved_ust,
+        queued_ust: state.queued_ust,
+    })
+}
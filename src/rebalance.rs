@@ -0,0 +1,96 @@
+use cosmwasm_std::Uint128;
+use std::collections::BTreeMap;
+
+use crate::msg::{KujiraBidResponse, SlotAllocation};
+
+const MAX_BPS: u128 = 10_000;
+
+pub struct Retraction {
+    pub bid_idx: u64,
+    pub premium_slot: u8,
+    // None retracts the whole bid
+    pub amount: Option<Uint128>,
+    pub retracted: Uint128,
+}
+
+#[derive(Default)]
+pub struct RebalancePlan {
+    pub retractions: Vec<Retraction>,
+    pub submissions: Vec<(u8, Uint128)>,
+}
+
+impl RebalancePlan {
+    pub fn is_empty(&self) -> bool {
+        self.retractions.is_empty() && self.submissions.is_empty()
+    }
+}
+
+/// Retractions and submissions moving the bids towards the target allocation.
+/// Only bids still waiting in Kujira can be retracted.
+pub fn plan_rebalance(
+    bids: &[KujiraBidResponse],
+    idle_a_ust: Uint128,
+    target: &[SlotAllocation],
+    tolerance_bps: u16,
+) -> RebalancePlan {
+    let mut current: BTreeMap<u8, Uint128> = BTreeMap::new();
+    for bid in bids {
+        *current.entry(bid.premium_slot).or_default() += bid.amount;
+    }
+    let basis = idle_a_ust + current.values().copied().sum::<Uint128>();
+    let tolerance = basis.multiply_ratio(tolerance_bps as u128, MAX_BPS);
+    let target_of = |premium_slot: u8| {
+        target
+            .iter()
+            .find(|allocation| allocation.premium_slot == premium_slot)
+            .map(|allocation| basis.multiply_ratio(allocation.bps as u128, MAX_BPS))
+            .unwrap_or_default()
+    };
+
+    let mut plan = RebalancePlan::default();
+    let mut available = idle_a_ust;
+    // Pull the excess out of over-allocated slots first, newest bids first
+    for (premium_slot, amount) in current.iter() {
+        let target_amount = target_of(*premium_slot);
+        if *amount <= target_amount + tolerance {
+            continue;
+        }
+        let mut excess = *amount - target_amount;
+        for bid in bids
+            .iter()
+            .rev()
+            .filter(|bid| bid.premium_slot == *premium_slot && bid.proxied_bid.is_none())
+        {
+            if excess.is_zero() {
+                break;
+            }
+            let retracted = bid.amount.min(excess);
+            plan.retractions.push(Retraction {
+                bid_idx: bid.idx,
+                premium_slot: *premium_slot,
+                amount: Some(retracted).filter(|retracted| *retracted < bid.amount),
+                retracted,
+            });
+            excess -= retracted;
+            available += retracted;
+        }
+    }
+    // Then top up under-allocated slots from the idle and retracted aUST
+    for allocation in target {
+        let amount = current
+            .get(&allocation.premium_slot)
+            .copied()
+            .unwrap_or_default();
+        let target_amount = target_of(allocation.premium_slot);
+        if amount + tolerance >= target_amount {
+            continue;
+        }
+        let submitted = (target_amount - amount).min(available);
+        if submitted.is_zero() {
+            break;
+        }
+        plan.submissions.push((allocation.premium_slot, submitted));
+        available -= submitted;
+    }
+    plan
+}
@@ -0,0 +1,68 @@
+use cosmwasm_std::{Addr, Api, Env, StdResult, Storage, Uint128};
+use cw20_base::state::{BALANCES, TOKEN_INFO};
+
+use crate::state::{LAST_DEPOSIT, STATE};
+use crate::ContractError;
+
+pub fn total_share(storage: &dyn Storage) -> StdResult<Uint128> {
+    Ok(TOKEN_INFO.load(storage)?.total_supply)
+}
+
+// Shares priced against the NAV, escrowed shares are already paid by queued UST
+pub fn outstanding_share(storage: &dyn Storage) -> StdResult<Uint128> {
+    Ok(total_share(storage)?.checked_sub(STATE.load(storage)?.escrowed_share)?)
+}
+
+pub fn escrow_share(
+    storage: &mut dyn Storage,
+    owner: &Addr,
+    vault: &Addr,
+    amount: Uint128,
+) -> StdResult<()> {
+    BALANCES.update(storage, owner, |balance| -> StdResult<_> {
+        Ok(balance.unwrap_or_default().checked_sub(amount)?)
+    })?;
+    BALANCES.update(storage, vault, |balance| -> StdResult<_> {
+        Ok(balance.unwrap_or_default() + amount)
+    })?;
+    Ok(())
+}
+
+pub fn mint_share(storage: &mut dyn Storage, recipient: &Addr, amount: Uint128) -> StdResult<()> {
+    TOKEN_INFO.update(storage, |mut info| -> StdResult<_> {
+        info.total_supply += amount;
+        Ok(info)
+    })?;
+    BALANCES.update(storage, recipient, |balance| -> StdResult<_> {
+        Ok(balance.unwrap_or_default() + amount)
+    })?;
+    Ok(())
+}
+
+pub fn burn_share(storage: &mut dyn Storage, owner: &Addr, amount: Uint128) -> StdResult<()> {
+    BALANCES.update(storage, owner, |balance| -> StdResult<_> {
+        Ok(balance.unwrap_or_default().checked_sub(amount)?)
+    })?;
+    TOKEN_INFO.update(storage, |mut info| -> StdResult<_> {
+        info.total_supply = info.total_supply.checked_sub(amount)?;
+        Ok(info)
+    })?;
+    Ok(())
+}
+
+// Shares can't move while the owner is inside its withdraw lock, so transfers can't skip it
+pub fn check_transfer_lock(
+    storage: &dyn Storage,
+    api: &dyn Api,
+    env: &Env,
+    owner: &str,
+) -> Result<(), ContractError> {
+    let owner = api.addr_canonicalize(&owner.to_lowercase())?;
+    if let Some(timestamp) = LAST_DEPOSIT.may_load(storage, owner.as_slice())? {
+        let unlock_at = timestamp.plus_seconds(STATE.load(storage)?.withdraw_lock);
+        if unlock_at >= env.block.time {
+            return Err(ContractError::WithdrawLocked { unlock_at });
+        }
+    }
+    Ok(())
+}
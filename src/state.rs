@@ -1,17 +1,18 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use crate::msg::BidStrategy;
-use cosmwasm_std::{Addr, Timestamp, Uint128};
-use cw_storage_plus::{Item, Map, U32Key};
+use crate::msg::{BidStrategy, SlotAllocation, SwapOperation};
+use cosmwasm_std::{Addr, Decimal256, Timestamp, Uint128};
+use cw20::Expiration;
+use cw_storage_plus::{Item, Map, U32Key, U64Key};
+use std::fmt;
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct State {
     pub owner: Addr,
-    pub total_supply: Uint128,
     pub locked_b_luna: Uint128,
     pub swap_wallet: Addr,
-    pub paused: bool,
+    pub paused: PauseFlags,
     pub collateral_token: Addr,
     pub price_oracle: Addr,
     pub astroport_router: Addr,
@@ -21,6 +22,76 @@ pub struct State {
     pub lock_period: u64,
     pub withdraw_lock: u64,
     pub bid_strategy: BidStrategy,
+    pub b_luna_discount_bps: u16,
+    pub max_price_age: u64,
+    pub min_b_luna_price: Option<Decimal256>,
+    pub max_b_luna_price: Option<Decimal256>,
+    // UST owed to unfilled withdrawal requests
+    pub queued_ust: Uint128,
+    // UST set aside for filled withdrawal requests
+    pub reserved_ust: Uint128,
+    // Shares held by the vault for unfilled withdrawal requests
+    pub escrowed_share: Uint128,
+    pub next_withdrawal_id: u64,
+    // Requests fill in id order, so every request from this id on is unfilled
+    pub next_fill_id: u64,
+    pub fee_collector: Addr,
+    // Annual fee on total cap
+    pub management_fee_bps: u16,
+    // Fee on NAV per share gains above the high-water mark
+    pub performance_fee_bps: u16,
+    pub high_water_mark: Decimal256,
+    pub last_fee_collection: u64,
+    // Target distribution of bids over premium slots
+    pub target_allocation: Vec<SlotAllocation>,
+    pub rebalance_tolerance_bps: u16,
+    // Highest premium slot the vault bids on
+    pub max_premium_slot: u8,
+    // Delay before a queued config change can be executed
+    pub timelock_delay: u64,
+    // Shortfall below the oracle value accepted when swapping bLuna
+    pub swap_tolerance_bps: u16,
+    // Router route from collateral_token to uusd
+    pub swap_route: Vec<SwapOperation>,
+    // Direct bLuna-UST pair, the router is used if not set
+    pub astroport_pair: Option<Addr>,
+    // Largest bLuna amount sold by one swap, unlimited if not set
+    pub max_swap_amount: Option<Uint128>,
+    // Minimum seconds between two swaps
+    pub swap_cooldown: u64,
+    pub last_swap: u64,
+    // Set by EmergencyShutdown, only redemptions remain afterwards
+    pub shutdown: bool,
+}
+
+// Operations frozen independently during an incident
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, JsonSchema)]
+pub struct PauseFlags {
+    pub deposit: bool,
+    pub withdraw_ust: bool,
+    pub withdraw_b_luna: bool,
+    pub submit_bid: bool,
+    pub claim: bool,
+    pub unlock: bool,
+    pub swap: bool,
+}
+
+impl PauseFlags {
+    pub fn operations(&self) -> Vec<&'static str> {
+        [
+            (self.deposit, "deposit"),
+            (self.withdraw_ust, "withdraw_ust"),
+            (self.withdraw_b_luna, "withdraw_b_luna"),
+            (self.submit_bid, "submit_bid"),
+            (self.claim, "claim"),
+            (self.unlock, "unlock"),
+            (self.swap, "swap"),
+        ]
+        .iter()
+        .filter(|(paused, _)| *paused)
+        .map(|(_, operation)| *operation)
+        .collect()
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -30,11 +101,105 @@ pub struct TokenRecord {
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct WithdrawalRequest {
+    pub id: u64,
+    pub owner: Addr,
+    pub share: Uint128,
+    // UST value of the share at request time, the UST paid out once filled
+    pub amount: Uint128,
+    pub requested_at: Timestamp,
+    pub filled: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    // Grants and revokes roles, implies every other role
+    Admin,
+    // Submits and retracts bids
+    Bidder,
+    // Claims liquidations, unlocks and swaps collateral
+    Keeper,
+    // Pauses and unpauses the vault
+    Pauser,
+    // Updates config and strategy presets
+    ConfigManager,
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Role::Admin => "admin",
+            Role::Bidder => "bidder",
+            Role::Keeper => "keeper",
+            Role::Pauser => "pauser",
+            Role::ConfigManager => "config_manager",
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
 pub struct Permission {
-    pub submit_bid: bool,
+    pub roles: Vec<Role>,
+}
+
+impl Permission {
+    pub fn has_role(&self, role: Role) -> bool {
+        self.roles.contains(&Role::Admin) || self.roles.contains(&role)
+    }
+}
+
+// Owner proposed by the current owner, waiting for acceptance
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingOwner {
+    pub new_owner: Addr,
+    pub expiry: Option<Expiration>,
 }
 
-pub const BALANCES: Map<&[u8], Uint128> = Map::new("balance");
+// Config change waiting for the timelock delay
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingConfigChange {
+    pub swap_wallet: Option<Addr>,
+    pub lock_period: Option<u64>,
+    pub withdraw_lock: Option<u64>,
+    pub bid_strategy: Option<BidStrategy>,
+    pub timelock_delay: Option<u64>,
+    pub swap_route: Option<Vec<SwapOperation>>,
+    pub astroport_pair: Option<Addr>,
+    pub executable_at: Timestamp,
+}
+
+// Assets left for share redemptions after an emergency shutdown
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ShutdownBasket {
+    pub ust: Uint128,
+    pub a_ust: Uint128,
+    pub b_luna: Uint128,
+}
+
+// Balance snapshot taken before an external call, reconciled by its reply
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingSettlement {
+    // Amount handed to the external contract, or expected from it for claims
+    pub amount: Uint128,
+    // Balance of the received asset before the call
+    pub balance_before: Uint128,
+    // Least acceptable amount received, zero for none
+    pub minimum_receive: Uint128,
+}
+
+// Running totals of settled swaps, aUST redemptions and liquidation claims
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct SettlementStats {
+    pub swaps: u64,
+    pub swapped_b_luna: Uint128,
+    pub swap_proceeds: Uint128,
+    pub redemptions: u64,
+    pub redeemed_a_ust: Uint128,
+    pub redemption_proceeds: Uint128,
+    pub claims: u64,
+    pub claimed_b_luna: Uint128,
+}
 
 pub const LAST_DEPOSIT: Map<&[u8], Timestamp> = Map::new("last_deposit");
 
@@ -42,4 +207,23 @@ pub const PERMISSIONS: Map<&[u8], Permission> = Map::new("permission");
 
 pub const STATE: Item<State> = Item::new("state");
 
+pub const PENDING_OWNER: Item<PendingOwner> = Item::new("pending_owner");
+
+pub const PENDING_CONFIG_CHANGE: Item<PendingConfigChange> = Item::new("pending_config_change");
+
+pub const SHUTDOWN_BASKET: Item<ShutdownBasket> = Item::new("shutdown_basket");
+
+// Highest bid idx already retracted after a shutdown
+pub const SHUTDOWN_BID_CURSOR: Item<u64> = Item::new("shutdown_bid_cursor");
+
+// Keyed by reply id
+pub const PENDING_SETTLEMENTS: Map<U64Key, PendingSettlement> = Map::new("pending_settlements");
+
+pub const SETTLEMENT_STATS: Item<SettlementStats> = Item::new("settlement_stats");
+
 pub const CLAIM_LIST: Map<U32Key, TokenRecord> = Map::new("claim_list");
+
+// Named bid strategies selectable per bid
+pub const STRATEGY_PRESETS: Map<&str, BidStrategy> = Map::new("strategy_presets");
+
+pub const WITHDRAWAL_REQUESTS: Map<U64Key, WithdrawalRequest> = Map::new("withdrawal_requests");
@@ -0,0 +1,133 @@
+// Anchor money market: DepositStable mints aUST at the exchange rate, aUST sent back with
+// RedeemStable is burned for UST
+use cosmwasm_std::{
+    from_binary, to_binary, Addr, BankMsg, Binary, Coin, Decimal256, Deps, DepsMut, Empty, Env,
+    MessageInfo, Response, StdError, StdResult, Uint256, WasmMsg,
+};
+use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg};
+use cw_multi_test::{Contract, ContractWrapper};
+use cw_storage_plus::Item;
+use serde::{Deserialize, Serialize};
+
+use super::{div, mul};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct InstantiateMsg {
+    pub a_ust: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecuteMsg {
+    DepositStable {},
+    Receive(Cw20ReceiveMsg),
+    // Test knob, interest accrues by raising the rate
+    SetExchangeRate { rate: Decimal256 },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Cw20HookMsg {
+    RedeemStable {},
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryMsg {
+    EpochState {
+        block_height: Option<u64>,
+        distributed_interest: Option<Uint256>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct EpochStateResponse {
+    pub exchange_rate: Decimal256,
+    pub aterra_supply: Uint256,
+}
+
+const A_UST: Item<Addr> = Item::new("a_ust");
+const EXCHANGE_RATE: Item<Decimal256> = Item::new("exchange_rate");
+
+pub fn contract() -> Box<dyn Contract<Empty>> {
+    Box::new(ContractWrapper::new(execute, instantiate, query))
+}
+
+fn instantiate(
+    deps: DepsMut,
+    _env: Env,
+    _info: MessageInfo,
+    msg: InstantiateMsg,
+) -> StdResult<Response> {
+    A_UST.save(deps.storage, &deps.api.addr_validate(&msg.a_ust)?)?;
+    EXCHANGE_RATE.save(deps.storage, &Decimal256::one())?;
+    Ok(Response::new())
+}
+
+fn execute(deps: DepsMut, _env: Env, info: MessageInfo, msg: ExecuteMsg) -> StdResult<Response> {
+    match msg {
+        ExecuteMsg::DepositStable {} => {
+            let deposit = info
+                .funds
+                .iter()
+                .find(|coin| coin.denom == "uusd")
+                .map(|coin| coin.amount)
+                .unwrap_or_default();
+            let rate = EXCHANGE_RATE.load(deps.storage)?;
+            let a_ust = div(deposit, rate)?;
+            Ok(Response::new()
+                .add_message(WasmMsg::Execute {
+                    contract_addr: A_UST.load(deps.storage)?.to_string(),
+                    msg: to_binary(&Cw20ExecuteMsg::Mint {
+                        recipient: info.sender.to_string(),
+                        amount: a_ust,
+                    })?,
+                    funds: vec![],
+                })
+                .add_attribute("action", "deposit_stable")
+                .add_attribute("mint_amount", a_ust))
+        }
+        ExecuteMsg::Receive(receive) => {
+            let a_ust = A_UST.load(deps.storage)?;
+            if info.sender != a_ust {
+                return Err(StdError::generic_err("only aUST can be redeemed"));
+            }
+            match from_binary(&receive.msg)? {
+                Cw20HookMsg::RedeemStable {} => {
+                    let rate = EXCHANGE_RATE.load(deps.storage)?;
+                    let ust = mul(receive.amount, rate)?;
+                    Ok(Response::new()
+                        .add_message(WasmMsg::Execute {
+                            contract_addr: a_ust.to_string(),
+                            msg: to_binary(&Cw20ExecuteMsg::Burn {
+                                amount: receive.amount,
+                            })?,
+                            funds: vec![],
+                        })
+                        .add_message(BankMsg::Send {
+                            to_address: receive.sender,
+                            amount: vec![Coin {
+                                denom: "uusd".to_string(),
+                                amount: ust,
+                            }],
+                        })
+                        .add_attribute("action", "redeem_stable")
+                        .add_attribute("redeem_amount", ust))
+                }
+            }
+        }
+        ExecuteMsg::SetExchangeRate { rate } => {
+            EXCHANGE_RATE.save(deps.storage, &rate)?;
+            Ok(Response::new())
+        }
+    }
+}
+
+fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
+    match msg {
+        QueryMsg::EpochState { .. } => to_binary(&EpochStateResponse {
+            exchange_rate: EXCHANGE_RATE.load(deps.storage)?,
+            aterra_supply: Uint256::zero(),
+        }),
+    }
+}
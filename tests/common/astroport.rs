@@ -0,0 +1,314 @@
+// Astroport router and bLuna-UST pair, both buying the offered token at a fixed uusd rate out
+// of their own balance and burning it
+use cosmwasm_std::{
+    from_binary, to_binary, Addr, BankMsg, Binary, Coin, CosmosMsg, Decimal, Decimal256, Deps,
+    DepsMut, Empty, Env, Fraction, MessageInfo, Response, StdError, StdResult, Storage, Uint128,
+    WasmMsg,
+};
+use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg};
+use cw_multi_test::{Contract, ContractWrapper};
+use cw_storage_plus::Item;
+use serde::{Deserialize, Serialize};
+
+use super::mul;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct InstantiateMsg {
+    pub offer_token: String,
+    // uusd paid per offered token
+    pub rate: Decimal256,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum AssetInfo {
+    Token { contract_addr: Addr },
+    NativeToken { denom: String },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Asset {
+    pub info: AssetInfo,
+    pub amount: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct Venue {
+    offer_token: Addr,
+    rate: Decimal256,
+    // Share of the proceeds withheld after the minimum was checked, like a transfer tax
+    tax: Decimal256,
+}
+
+const VENUE: Item<Venue> = Item::new("venue");
+
+fn instantiate(
+    deps: DepsMut,
+    _env: Env,
+    _info: MessageInfo,
+    msg: InstantiateMsg,
+) -> StdResult<Response> {
+    VENUE.save(
+        deps.storage,
+        &Venue {
+            offer_token: deps.api.addr_validate(&msg.offer_token)?,
+            rate: msg.rate,
+            tax: Decimal256::zero(),
+        },
+    )?;
+    Ok(Response::new())
+}
+
+fn set_rate(storage: &mut dyn Storage, rate: Decimal256) -> StdResult<Response> {
+    VENUE.update(storage, |mut venue| -> StdResult<_> {
+        venue.rate = rate;
+        Ok(venue)
+    })?;
+    Ok(Response::new())
+}
+
+fn set_tax(storage: &mut dyn Storage, tax: Decimal256) -> StdResult<Response> {
+    VENUE.update(storage, |mut venue| -> StdResult<_> {
+        venue.tax = tax;
+        Ok(venue)
+    })?;
+    Ok(Response::new())
+}
+
+// Burns the received tokens and pays their uusd value, less the tax, unless the venue's
+// minimum check rejects it
+fn swap(
+    storage: &dyn Storage,
+    info: &MessageInfo,
+    receive: &Cw20ReceiveMsg,
+    to: Option<String>,
+    check: impl FnOnce(Uint128) -> StdResult<()>,
+) -> StdResult<Vec<CosmosMsg>> {
+    let venue = VENUE.load(storage)?;
+    if info.sender != venue.offer_token {
+        return Err(StdError::generic_err("unsupported offer token"));
+    }
+    let ust = mul(receive.amount, venue.rate)?;
+    check(ust)?;
+    let withheld = mul(ust, venue.tax)?;
+    Ok(vec![
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: venue.offer_token.to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Burn {
+                amount: receive.amount,
+            })?,
+            funds: vec![],
+        }),
+        CosmosMsg::Bank(BankMsg::Send {
+            to_address: to.unwrap_or_else(|| receive.sender.clone()),
+            amount: vec![Coin {
+                denom: "uusd".to_string(),
+                amount: ust - withheld,
+            }],
+        }),
+    ])
+}
+
+pub mod router {
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    #[serde(rename_all = "snake_case")]
+    pub enum ExecuteMsg {
+        Receive(Cw20ReceiveMsg),
+        SetRate { rate: Decimal256 },
+        SetTax { tax: Decimal256 },
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    #[serde(rename_all = "snake_case")]
+    pub enum SwapOperation {
+        NativeSwap {
+            offer_denom: String,
+            ask_denom: String,
+        },
+        AstroSwap {
+            offer_asset_info: AssetInfo,
+            ask_asset_info: AssetInfo,
+        },
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    #[serde(rename_all = "snake_case")]
+    pub enum Cw20HookMsg {
+        ExecuteSwapOperations {
+            operations: Vec<SwapOperation>,
+            minimum_receive: Option<Uint128>,
+            to: Option<String>,
+            max_spread: Option<Decimal>,
+        },
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    #[serde(rename_all = "snake_case")]
+    pub enum QueryMsg {
+        SimulateSwapOperations {
+            offer_amount: Uint128,
+            operations: Vec<SwapOperation>,
+        },
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    pub struct SimulateSwapOperationsResponse {
+        pub amount: Uint128,
+    }
+
+    pub fn contract() -> Box<dyn Contract<Empty>> {
+        Box::new(ContractWrapper::new(execute, instantiate, query))
+    }
+
+    fn execute(
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+        msg: ExecuteMsg,
+    ) -> StdResult<Response> {
+        match msg {
+            ExecuteMsg::Receive(receive) => match from_binary(&receive.msg)? {
+                Cw20HookMsg::ExecuteSwapOperations {
+                    operations,
+                    minimum_receive,
+                    to,
+                    ..
+                } => {
+                    if operations.is_empty() {
+                        return Err(StdError::generic_err("must provide operations"));
+                    }
+                    let messages =
+                        swap(
+                            deps.storage,
+                            &info,
+                            &receive,
+                            to,
+                            |ust| match minimum_receive {
+                                Some(minimum_receive) if ust < minimum_receive => {
+                                    Err(StdError::generic_err(
+                                        "assertion failed; minimum receive amount",
+                                    ))
+                                }
+                                _ => Ok(()),
+                            },
+                        )?;
+                    Ok(Response::new()
+                        .add_messages(messages)
+                        .add_attribute("action", "execute_swap_operations"))
+                }
+            },
+            ExecuteMsg::SetRate { rate } => set_rate(deps.storage, rate),
+            ExecuteMsg::SetTax { tax } => set_tax(deps.storage, tax),
+        }
+    }
+
+    fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
+        match msg {
+            QueryMsg::SimulateSwapOperations {
+                offer_amount,
+                operations,
+            } => {
+                if operations.is_empty() {
+                    return Err(StdError::generic_err("must provide operations"));
+                }
+                let venue = VENUE.load(deps.storage)?;
+                to_binary(&SimulateSwapOperationsResponse {
+                    amount: mul(offer_amount, venue.rate)?,
+                })
+            }
+        }
+    }
+}
+
+pub mod pair {
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    #[serde(rename_all = "snake_case")]
+    pub enum ExecuteMsg {
+        Receive(Cw20ReceiveMsg),
+        SetRate { rate: Decimal256 },
+        SetTax { tax: Decimal256 },
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    #[serde(rename_all = "snake_case")]
+    pub enum Cw20HookMsg {
+        Swap {
+            belief_price: Option<Decimal>,
+            max_spread: Option<Decimal>,
+            to: Option<String>,
+        },
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    #[serde(rename_all = "snake_case")]
+    pub enum QueryMsg {
+        Simulation { offer_asset: Asset },
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    pub struct SimulationResponse {
+        pub return_amount: Uint128,
+        pub spread_amount: Uint128,
+        pub commission_amount: Uint128,
+    }
+
+    pub fn contract() -> Box<dyn Contract<Empty>> {
+        Box::new(ContractWrapper::new(execute, instantiate, query))
+    }
+
+    fn execute(
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+        msg: ExecuteMsg,
+    ) -> StdResult<Response> {
+        match msg {
+            ExecuteMsg::Receive(receive) => match from_binary(&receive.msg)? {
+                Cw20HookMsg::Swap {
+                    belief_price,
+                    max_spread,
+                    to,
+                } => {
+                    let offer = receive.amount;
+                    let messages = swap(deps.storage, &info, &receive, to, |ust| {
+                        // Rejects returns deviating from the belief price by more than max_spread
+                        if let Some(belief_price) = belief_price {
+                            let expected = offer * belief_price.inv().unwrap();
+                            let spread = expected.saturating_sub(ust);
+                            if Decimal::from_ratio(spread, expected)
+                                > max_spread.unwrap_or_default()
+                            {
+                                return Err(StdError::generic_err(
+                                    "Operation exceeds max spread limit",
+                                ));
+                            }
+                        }
+                        Ok(())
+                    })?;
+                    Ok(Response::new()
+                        .add_messages(messages)
+                        .add_attribute("action", "swap"))
+                }
+            },
+            ExecuteMsg::SetRate { rate } => set_rate(deps.storage, rate),
+            ExecuteMsg::SetTax { tax } => set_tax(deps.storage, tax),
+        }
+    }
+
+    fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
+        match msg {
+            QueryMsg::Simulation { offer_asset } => {
+                let venue = VENUE.load(deps.storage)?;
+                to_binary(&SimulationResponse {
+                    return_amount: mul(offer_asset.amount, venue.rate)?,
+                    spread_amount: Uint128::zero(),
+                    commission_amount: Uint128::zero(),
+                })
+            }
+        }
+    }
+}
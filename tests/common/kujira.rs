@@ -0,0 +1,407 @@
+// Kujira aUST vault: bids wait as aUST until the suite activates them into an Anchor bid, which
+// the suite then liquidates. Claims mint the collateral, so the vault must be its minter.
+use std::convert::TryInto;
+
+use cosmwasm_std::{
+    from_binary, to_binary, Addr, BankMsg, Binary, Coin, CosmosMsg, Decimal256, Deps, DepsMut,
+    Empty, Env, MessageInfo, Order, QuerierWrapper, Response, StdError, StdResult, Uint128,
+    Uint256, WasmMsg,
+};
+use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg};
+use cw_multi_test::{Contract, ContractWrapper};
+use cw_storage_plus::{Bound, Item, Map, U64Key};
+use serde::{Deserialize, Serialize};
+
+use super::anchor;
+use super::{div, mul};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct InstantiateMsg {
+    pub a_ust: String,
+    pub anchor_market: String,
+    pub collateral_token: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct LoanAmount {
+    pub ltv: u8,
+    pub cumulative_value: Uint256,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Strategy {
+    pub activate_at: LoanAmount,
+    pub deactivate_at: LoanAmount,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecuteMsg {
+    Receive(Cw20ReceiveMsg),
+    RetractBid {
+        bid_idx: u64,
+        amount: Option<Uint128>,
+    },
+    ClaimLiquidations {
+        collateral_token: String,
+        bids_idx: Vec<u64>,
+    },
+    // Test knobs standing in for Anchor's liquidation queue
+    ActivateBid {
+        bid_idx: u64,
+    },
+    LiquidateBid {
+        bid_idx: u64,
+        ust: Uint128,
+        collateral: Uint128,
+    },
+    SetClaimFee {
+        fee: Decimal256,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Cw20HookMsg {
+    SubmitBid {
+        collateral_token: String,
+        premium_slot: u8,
+        strategy: Strategy,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryMsg {
+    BidsByUser {
+        collateral_token: String,
+        bidder: String,
+        start_after: Option<u64>,
+        limit: Option<u8>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BidsResponse {
+    pub bids: Vec<BidResponse>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BidResponse {
+    pub collateral_token: Addr,
+    pub premium_slot: u8,
+    pub bidder: Addr,
+    pub idx: u64,
+    pub bid_idx: Option<Uint128>,
+    pub strategy: Strategy,
+    pub amount: Uint128,
+    pub prev_exchange_rate: Decimal256,
+    pub proxied_bid: Option<AnchorBidResponse>,
+}
+
+// Anchor liquidation queue bid holding an activated Kujira bid
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AnchorBidResponse {
+    pub idx: Uint128,
+    pub collateral_token: String,
+    pub premium_slot: u8,
+    pub bidder: String,
+    pub amount: Uint256,
+    pub product_snapshot: Decimal256,
+    pub sum_snapshot: Decimal256,
+    pub pending_liquidated_collateral: Uint256,
+    pub wait_end: Option<u64>,
+    pub epoch_snapshot: Uint128,
+    pub scale_snapshot: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct Config {
+    a_ust: Addr,
+    anchor_market: Addr,
+    collateral_token: Addr,
+    // Share of claimed collateral kept as a fee
+    claim_fee: Decimal256,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct Bid {
+    bidder: Addr,
+    premium_slot: u8,
+    strategy: Strategy,
+    // aUST waiting in the vault
+    amount: Uint128,
+    // UST bid and pending collateral once the bid moved into Anchor
+    proxied: Option<(Uint128, Uint128)>,
+}
+
+const CONFIG: Item<Config> = Item::new("config");
+const NEXT_IDX: Item<u64> = Item::new("next_idx");
+const BIDS: Map<U64Key, Bid> = Map::new("bids");
+
+pub fn contract() -> Box<dyn Contract<Empty>> {
+    Box::new(ContractWrapper::new(execute, instantiate, query))
+}
+
+fn instantiate(
+    deps: DepsMut,
+    _env: Env,
+    _info: MessageInfo,
+    msg: InstantiateMsg,
+) -> StdResult<Response> {
+    CONFIG.save(
+        deps.storage,
+        &Config {
+            a_ust: deps.api.addr_validate(&msg.a_ust)?,
+            anchor_market: deps.api.addr_validate(&msg.anchor_market)?,
+            collateral_token: deps.api.addr_validate(&msg.collateral_token)?,
+            claim_fee: Decimal256::zero(),
+        },
+    )?;
+    NEXT_IDX.save(deps.storage, &1)?;
+    Ok(Response::new())
+}
+
+fn execute(deps: DepsMut, _env: Env, info: MessageInfo, msg: ExecuteMsg) -> StdResult<Response> {
+    let config = CONFIG.load(deps.storage)?;
+    match msg {
+        ExecuteMsg::Receive(receive) => {
+            if info.sender != config.a_ust {
+                return Err(StdError::generic_err("bids are made in aUST"));
+            }
+            match from_binary(&receive.msg)? {
+                Cw20HookMsg::SubmitBid {
+                    collateral_token,
+                    premium_slot,
+                    strategy,
+                } => {
+                    if collateral_token != config.collateral_token {
+                        return Err(StdError::generic_err("unsupported collateral"));
+                    }
+                    let idx = NEXT_IDX.load(deps.storage)?;
+                    NEXT_IDX.save(deps.storage, &(idx + 1))?;
+                    BIDS.save(
+                        deps.storage,
+                        U64Key::from(idx),
+                        &Bid {
+                            bidder: deps.api.addr_validate(&receive.sender)?,
+                            premium_slot,
+                            strategy,
+                            amount: receive.amount,
+                            proxied: None,
+                        },
+                    )?;
+                    Ok(Response::new()
+                        .add_attribute("action", "submit_bid")
+                        .add_attribute("bid_idx", idx.to_string()))
+                }
+            }
+        }
+        ExecuteMsg::RetractBid { bid_idx, amount } => {
+            let mut bid = BIDS.load(deps.storage, U64Key::from(bid_idx))?;
+            if bid.bidder != info.sender {
+                return Err(StdError::generic_err("not the bidder"));
+            }
+            let mut messages = vec![];
+            let refund = match amount {
+                // Partial retraction of a waiting bid
+                Some(amount) if amount < bid.amount => {
+                    if bid.proxied.is_some() {
+                        return Err(StdError::generic_err("bid is activated"));
+                    }
+                    bid.amount -= amount;
+                    BIDS.save(deps.storage, U64Key::from(bid_idx), &bid)?;
+                    amount
+                }
+                _ => {
+                    BIDS.remove(deps.storage, U64Key::from(bid_idx));
+                    let mut refund = bid.amount;
+                    // The UST left in Anchor comes back as aUST
+                    if let Some((ust, _)) = bid.proxied.filter(|(ust, _)| !ust.is_zero()) {
+                        let rate = exchange_rate(&deps.querier, &config)?;
+                        refund += div(ust, rate)?;
+                        messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+                            contract_addr: config.anchor_market.to_string(),
+                            msg: to_binary(&anchor::ExecuteMsg::DepositStable {})?,
+                            funds: vec![Coin {
+                                denom: "uusd".to_string(),
+                                amount: ust,
+                            }],
+                        }));
+                    }
+                    refund
+                }
+            };
+            // Anchor refuses to send an empty refund for a consumed bid
+            if refund.is_zero() {
+                return Err(StdError::generic_err("Cannot transfer empty coins amount"));
+            }
+            messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: config.a_ust.to_string(),
+                msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                    recipient: info.sender.to_string(),
+                    amount: refund,
+                })?,
+                funds: vec![],
+            }));
+            Ok(Response::new()
+                .add_messages(messages)
+                .add_attribute("action", "retract_bid")
+                .add_attribute("amount", refund))
+        }
+        ExecuteMsg::ClaimLiquidations {
+            collateral_token,
+            bids_idx,
+        } => {
+            if collateral_token != config.collateral_token {
+                return Err(StdError::generic_err("unsupported collateral"));
+            }
+            let mut claimed = Uint128::zero();
+            for idx in bids_idx {
+                let mut bid = BIDS.load(deps.storage, U64Key::from(idx))?;
+                if bid.bidder != info.sender {
+                    return Err(StdError::generic_err("not the bidder"));
+                }
+                if let Some((ust, pending)) = bid.proxied {
+                    claimed += pending;
+                    bid.proxied = Some((ust, Uint128::zero()));
+                    BIDS.save(deps.storage, U64Key::from(idx), &bid)?;
+                }
+            }
+            let amount = claimed - mul(claimed, config.claim_fee)?;
+            let mut response = Response::new()
+                .add_attribute("action", "claim_liquidations")
+                .add_attribute("amount", amount);
+            if !amount.is_zero() {
+                response = response.add_message(WasmMsg::Execute {
+                    contract_addr: config.collateral_token.to_string(),
+                    msg: to_binary(&Cw20ExecuteMsg::Mint {
+                        recipient: info.sender.to_string(),
+                        amount,
+                    })?,
+                    funds: vec![],
+                });
+            }
+            Ok(response)
+        }
+        ExecuteMsg::ActivateBid { bid_idx } => {
+            let mut bid = BIDS.load(deps.storage, U64Key::from(bid_idx))?;
+            let rate = exchange_rate(&deps.querier, &config)?;
+            let a_ust = bid.amount;
+            bid.amount = Uint128::zero();
+            bid.proxied = Some((mul(a_ust, rate)?, Uint128::zero()));
+            BIDS.save(deps.storage, U64Key::from(bid_idx), &bid)?;
+            Ok(Response::new().add_message(WasmMsg::Execute {
+                contract_addr: config.a_ust.to_string(),
+                msg: to_binary(&Cw20ExecuteMsg::Send {
+                    contract: config.anchor_market.to_string(),
+                    amount: a_ust,
+                    msg: to_binary(&anchor::Cw20HookMsg::RedeemStable {})?,
+                })?,
+                funds: vec![],
+            }))
+        }
+        ExecuteMsg::LiquidateBid {
+            bid_idx,
+            ust,
+            collateral,
+        } => {
+            let mut bid = BIDS.load(deps.storage, U64Key::from(bid_idx))?;
+            let (amount, pending) = bid
+                .proxied
+                .ok_or_else(|| StdError::generic_err("bid is not activated"))?;
+            bid.proxied = Some((amount.checked_sub(ust)?, pending + collateral));
+            BIDS.save(deps.storage, U64Key::from(bid_idx), &bid)?;
+            // The UST paid for the collateral leaves the vault
+            Ok(Response::new().add_message(BankMsg::Burn {
+                amount: vec![Coin {
+                    denom: "uusd".to_string(),
+                    amount: ust,
+                }],
+            }))
+        }
+        ExecuteMsg::SetClaimFee { fee } => {
+            CONFIG.save(
+                deps.storage,
+                &Config {
+                    claim_fee: fee,
+                    ..config
+                },
+            )?;
+            Ok(Response::new())
+        }
+    }
+}
+
+fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
+    match msg {
+        QueryMsg::BidsByUser {
+            collateral_token,
+            bidder,
+            start_after,
+            limit,
+        } => {
+            let config = CONFIG.load(deps.storage)?;
+            if collateral_token != config.collateral_token {
+                return Err(StdError::generic_err("unsupported collateral"));
+            }
+            let rate = exchange_rate(&deps.querier, &config)?;
+            let bids = BIDS
+                .range(
+                    deps.storage,
+                    start_after.map(Bound::exclusive_int),
+                    None,
+                    Order::Ascending,
+                )
+                .filter(|item| {
+                    item.as_ref()
+                        .map_or(true, |(_, bid)| bid.bidder.as_str() == bidder)
+                })
+                .take(limit.unwrap_or(10) as usize)
+                .map(|item| {
+                    let (key, bid) = item?;
+                    let idx = u64::from_be_bytes(key.as_slice().try_into().unwrap());
+                    Ok(bid_response(&env, &config, idx, &bid, rate))
+                })
+                .collect::<StdResult<_>>()?;
+            to_binary(&BidsResponse { bids })
+        }
+    }
+}
+
+fn bid_response(env: &Env, config: &Config, idx: u64, bid: &Bid, rate: Decimal256) -> BidResponse {
+    BidResponse {
+        collateral_token: config.collateral_token.clone(),
+        premium_slot: bid.premium_slot,
+        bidder: bid.bidder.clone(),
+        idx,
+        bid_idx: bid.proxied.map(|_| Uint128::from(idx)),
+        strategy: bid.strategy.clone(),
+        amount: bid.amount,
+        prev_exchange_rate: rate,
+        proxied_bid: bid.proxied.map(|(amount, pending)| AnchorBidResponse {
+            idx: Uint128::from(idx),
+            collateral_token: config.collateral_token.to_string(),
+            premium_slot: bid.premium_slot,
+            bidder: env.contract.address.to_string(),
+            amount: Uint256::from(amount),
+            product_snapshot: Decimal256::one(),
+            sum_snapshot: Decimal256::zero(),
+            pending_liquidated_collateral: Uint256::from(pending),
+            wait_end: None,
+            epoch_snapshot: Uint128::zero(),
+            scale_snapshot: Uint128::zero(),
+        }),
+    }
+}
+
+fn exchange_rate(querier: &QuerierWrapper, config: &Config) -> StdResult<Decimal256> {
+    let res: anchor::EpochStateResponse = querier.query_wasm_smart(
+        &config.anchor_market,
+        &anchor::QueryMsg::EpochState {
+            block_height: None,
+            distributed_interest: None,
+        },
+    )?;
+    Ok(res.exchange_rate)
+}
@@ -0,0 +1,596 @@
+// cw-multi-test chain running the vault against mock Anchor, Kujira, oracle and Astroport
+// contracts and cw20-base tokens
+#![allow(dead_code)]
+
+mod anchor;
+mod astroport;
+mod kujira;
+mod oracle;
+
+use std::convert::TryFrom;
+use std::str::FromStr;
+
+use anyhow::Error as AnyError;
+use cosmwasm_std::testing::{mock_env, MockApi};
+use cosmwasm_std::{
+    Addr, Api, Attribute, CanonicalAddr, Coin, Decimal256, Empty, Env, Fraction,
+    RecoverPubkeyError, StdError, StdResult, Timestamp, Uint128, Uint256, VerificationError,
+};
+use cw20::{BalanceResponse, Cw20ExecuteMsg, Cw20QueryMsg, MinterResponse};
+use cw_multi_test::{App, AppBuilder, AppResponse, Contract, ContractWrapper, Executor};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use terra_deposit_withdraw::contract::{execute, instantiate, query, reply};
+use terra_deposit_withdraw::msg::{
+    BidStrategy, ExecuteMsg, InstantiateMsg, KujiraBidsResponse, QueryMsg, SlotAllocation,
+};
+use terra_deposit_withdraw::state::{PauseFlags, Role};
+use terra_deposit_withdraw::ContractError;
+
+// Addresses cw-multi-test hands out in the order Suite::with_msg instantiates the contracts
+pub const A_UST: &str = "Contract #0";
+pub const B_LUNA: &str = "Contract #1";
+pub const OTHER_TOKEN: &str = "Contract #2";
+pub const ANCHOR: &str = "Contract #3";
+pub const KUJIRA: &str = "Contract #4";
+pub const ORACLE: &str = "Contract #5";
+pub const ROUTER: &str = "Contract #6";
+pub const PAIR: &str = "Contract #7";
+pub const VAULT: &str = "Contract #8";
+
+pub const OWNER: &str = "owner";
+pub const SWAP_WALLET: &str = "swap_wallet";
+pub const KEEPER: &str = "keeper";
+
+pub const LOCK_PERIOD: u64 = 14 * 24 * 60 * 60;
+pub const WITHDRAW_LOCK: u64 = 60 * 60;
+
+// uusd Anchor and the swap venues start with to pay out
+const RESERVES: u128 = 1_000_000_000_000;
+
+#[derive(Clone, Debug)]
+pub struct MockBid {
+    pub idx: u64,
+    pub premium_slot: u8,
+    pub strategy: BidStrategy,
+    // aUST waiting in Kujira
+    pub amount: u128,
+}
+
+// The vault lowercases addresses the way bech32 ones are, cw-multi-test names contracts
+// `Contract #n`, so those are mapped back before MockApi sees them
+struct ChainApi(MockApi);
+
+fn contract_case(human: &str) -> String {
+    match human.strip_prefix("contract #") {
+        Some(n) => format!("Contract #{}", n),
+        None => human.to_string(),
+    }
+}
+
+impl Api for ChainApi {
+    fn addr_validate(&self, human: &str) -> StdResult<Addr> {
+        self.0.addr_validate(&contract_case(human))
+    }
+
+    fn addr_canonicalize(&self, human: &str) -> StdResult<CanonicalAddr> {
+        self.0.addr_canonicalize(&contract_case(human))
+    }
+
+    fn addr_humanize(&self, canonical: &CanonicalAddr) -> StdResult<Addr> {
+        self.0.addr_humanize(canonical)
+    }
+
+    fn secp256k1_verify(
+        &self,
+        message_hash: &[u8],
+        signature: &[u8],
+        public_key: &[u8],
+    ) -> Result<bool, VerificationError> {
+        self.0.secp256k1_verify(message_hash, signature, public_key)
+    }
+
+    fn secp256k1_recover_pubkey(
+        &self,
+        message_hash: &[u8],
+        signature: &[u8],
+        recovery_param: u8,
+    ) -> Result<Vec<u8>, RecoverPubkeyError> {
+        self.0
+            .secp256k1_recover_pubkey(message_hash, signature, recovery_param)
+    }
+
+    fn ed25519_verify(
+        &self,
+        message: &[u8],
+        signature: &[u8],
+        public_key: &[u8],
+    ) -> Result<bool, VerificationError> {
+        self.0.ed25519_verify(message, signature, public_key)
+    }
+
+    fn ed25519_batch_verify(
+        &self,
+        messages: &[&[u8]],
+        signatures: &[&[u8]],
+        public_keys: &[&[u8]],
+    ) -> Result<bool, VerificationError> {
+        self.0
+            .ed25519_batch_verify(messages, signatures, public_keys)
+    }
+
+    fn debug(&self, message: &str) {
+        self.0.debug(message)
+    }
+}
+
+pub struct Suite {
+    pub app: App,
+}
+
+// UpdateConfig fields, all unchanged unless a test sets them
+#[derive(Clone, Debug, Default)]
+pub struct ConfigUpdate {
+    pub paused: Option<PauseFlags>,
+    pub b_luna_discount_bps: Option<u16>,
+    pub max_price_age: Option<u64>,
+    pub min_b_luna_price: Option<Decimal256>,
+    pub max_b_luna_price: Option<Decimal256>,
+    pub fee_collector: Option<Addr>,
+    pub management_fee_bps: Option<u16>,
+    pub performance_fee_bps: Option<u16>,
+    pub target_allocation: Option<Vec<SlotAllocation>>,
+    pub rebalance_tolerance_bps: Option<u16>,
+    pub max_premium_slot: Option<u8>,
+    pub swap_tolerance_bps: Option<u16>,
+    pub max_swap_amount: Option<Uint128>,
+    pub swap_cooldown: Option<u64>,
+}
+
+// UpdateConfig changing only the fields `customize` sets
+pub fn update_config(customize: impl FnOnce(&mut ConfigUpdate)) -> ExecuteMsg {
+    let mut update = ConfigUpdate::default();
+    customize(&mut update);
+    ExecuteMsg::UpdateConfig {
+        paused: update.paused,
+        b_luna_discount_bps: update.b_luna_discount_bps,
+        max_price_age: update.max_price_age,
+        min_b_luna_price: update.min_b_luna_price,
+        max_b_luna_price: update.max_b_luna_price,
+        fee_collector: update.fee_collector,
+        management_fee_bps: update.management_fee_bps,
+        performance_fee_bps: update.performance_fee_bps,
+        target_allocation: update.target_allocation,
+        rebalance_tolerance_bps: update.rebalance_tolerance_bps,
+        max_premium_slot: update.max_premium_slot,
+        swap_tolerance_bps: update.swap_tolerance_bps,
+        max_swap_amount: update.max_swap_amount,
+        swap_cooldown: update.swap_cooldown,
+    }
+}
+
+pub fn dec(value: &str) -> Decimal256 {
+    Decimal256::from_str(value).unwrap()
+}
+
+// Attributes `contract` emitted across the transaction, replies included
+pub fn attributes(res: &AppResponse, contract: &str) -> Vec<Attribute> {
+    res.events
+        .iter()
+        .filter(|event| event.ty == "wasm" && event.attributes[0].value == contract)
+        .flat_map(|event| event.attributes[1..].to_vec())
+        .collect()
+}
+
+// amount × rate, rounded down like the real contracts do
+fn mul(amount: Uint128, rate: Decimal256) -> StdResult<Uint128> {
+    Uint128::try_from(Uint256::from(amount) * rate)
+        .map_err(|err| StdError::generic_err(err.to_string()))
+}
+
+// amount ÷ rate, rounded down
+fn div(amount: Uint128, rate: Decimal256) -> StdResult<Uint128> {
+    let inv = rate
+        .inv()
+        .ok_or_else(|| StdError::generic_err("zero rate"))?;
+    mul(amount, inv)
+}
+
+// Vault errors come back as they were, anything a mock or token returned as a generic error
+fn contract_error(err: AnyError) -> ContractError {
+    match err.downcast::<ContractError>() {
+        Ok(err) => err,
+        Err(err) => match err.downcast::<StdError>() {
+            Ok(err) => ContractError::Std(err),
+            Err(err) => ContractError::Std(StdError::generic_err(err.to_string())),
+        },
+    }
+}
+
+// Instantiates a contract, checking it got the address the suite's constants expect
+fn deploy<T: Serialize>(app: &mut App, code_id: u64, msg: &T, label: &str, expected: &str) {
+    let address = app
+        .instantiate_contract(code_id, Addr::unchecked(OWNER), msg, &[], label, None)
+        .unwrap();
+    assert_eq!(address, expected);
+}
+
+fn vault() -> Box<dyn Contract<Empty>> {
+    Box::new(ContractWrapper::new(execute, instantiate, query).with_reply(reply))
+}
+
+fn cw20() -> Box<dyn Contract<Empty>> {
+    Box::new(ContractWrapper::new(
+        cw20_base::contract::execute,
+        cw20_base::contract::instantiate,
+        cw20_base::contract::query,
+    ))
+}
+
+impl Suite {
+    pub fn new() -> Self {
+        Self::with_msg(|_| {})
+    }
+
+    pub fn with_msg(customize: impl FnOnce(&mut InstantiateMsg)) -> Self {
+        let mut block = mock_env().block;
+        block.time = Timestamp::from_seconds(block.time.seconds());
+        let mut app = AppBuilder::new()
+            .with_api(ChainApi(MockApi::default()))
+            .with_block(block)
+            .build();
+        let cw20_id = app.store_code(cw20());
+        for (name, symbol, minter, address) in [
+            ("Anchor Terra USD", "aUST", ANCHOR, A_UST),
+            ("Bonded Luna", "bLuna", KUJIRA, B_LUNA),
+            ("Other Token", "OTHER", OWNER, OTHER_TOKEN),
+        ] {
+            deploy(
+                &mut app,
+                cw20_id,
+                &cw20_base::msg::InstantiateMsg {
+                    name: name.to_string(),
+                    symbol: symbol.to_string(),
+                    decimals: 6,
+                    initial_balances: vec![],
+                    mint: Some(MinterResponse {
+                        minter: minter.to_string(),
+                        cap: None,
+                    }),
+                    marketing: None,
+                },
+                symbol,
+                address,
+            );
+        }
+        let anchor_id = app.store_code(anchor::contract());
+        let kujira_id = app.store_code(kujira::contract());
+        let oracle_id = app.store_code(oracle::contract());
+        let router_id = app.store_code(astroport::router::contract());
+        let pair_id = app.store_code(astroport::pair::contract());
+        deploy(
+            &mut app,
+            anchor_id,
+            &anchor::InstantiateMsg {
+                a_ust: A_UST.to_string(),
+            },
+            "anchor",
+            ANCHOR,
+        );
+        deploy(
+            &mut app,
+            kujira_id,
+            &kujira::InstantiateMsg {
+                a_ust: A_UST.to_string(),
+                anchor_market: ANCHOR.to_string(),
+                collateral_token: B_LUNA.to_string(),
+            },
+            "kujira",
+            KUJIRA,
+        );
+        deploy(
+            &mut app,
+            oracle_id,
+            &oracle::InstantiateMsg {
+                asset: B_LUNA.to_string(),
+                price: dec("50"),
+            },
+            "oracle",
+            ORACLE,
+        );
+        for (code_id, label, address) in [(router_id, "router", ROUTER), (pair_id, "pair", PAIR)] {
+            deploy(
+                &mut app,
+                code_id,
+                &astroport::InstantiateMsg {
+                    offer_token: B_LUNA.to_string(),
+                    rate: dec("50"),
+                },
+                label,
+                address,
+            );
+        }
+        let mut suite = Suite { app };
+        for reserve in [ANCHOR, ROUTER, PAIR] {
+            suite.mint_native(reserve, RESERVES);
+        }
+
+        let mut msg = InstantiateMsg {
+            owner: Addr::unchecked(OWNER),
+            swap_wallet: Addr::unchecked(SWAP_WALLET),
+            collateral_token: Some(Addr::unchecked(B_LUNA)),
+            price_oracle: Some(Addr::unchecked(ORACLE)),
+            astroport_router: Some(Addr::unchecked(ROUTER)),
+            anchor_market: Some(Addr::unchecked(ANCHOR)),
+            a_ust: Some(Addr::unchecked(A_UST)),
+            kujira_a_ust_vault: Some(Addr::unchecked(KUJIRA)),
+            lock_period: Some(LOCK_PERIOD),
+            withdraw_lock: Some(WITHDRAW_LOCK),
+            bid_strategy: None,
+            b_luna_discount_bps: None,
+            max_price_age: None,
+            min_b_luna_price: None,
+            max_b_luna_price: None,
+            fee_collector: None,
+            management_fee_bps: None,
+            performance_fee_bps: None,
+            target_allocation: None,
+            rebalance_tolerance_bps: None,
+            max_premium_slot: None,
+            timelock_delay: None,
+            swap_tolerance_bps: None,
+            swap_route: None,
+            astroport_pair: None,
+            max_swap_amount: None,
+            swap_cooldown: None,
+            share_name: None,
+            share_symbol: None,
+        };
+        customize(&mut msg);
+        let admin = msg.owner.to_string();
+        let vault_id = suite.app.store_code(vault());
+        deploy(&mut suite.app, vault_id, &msg, "vault", VAULT);
+        suite
+            .execute(
+                &admin,
+                ExecuteMsg::GrantRole {
+                    address: Addr::unchecked(KEEPER),
+                    role: Role::Keeper,
+                },
+                &[],
+            )
+            .unwrap();
+        suite
+    }
+
+    pub fn env(&self) -> Env {
+        let mut env = mock_env();
+        env.block = self.app.block_info();
+        env.contract.address = Addr::unchecked(VAULT);
+        env
+    }
+
+    // Moves the block time forward, the oracle keeps feeding fresh prices
+    pub fn advance(&mut self, seconds: u64) {
+        self.advance_without_price(seconds);
+        self.run(ORACLE, &oracle::ExecuteMsg::Feed { price: None });
+    }
+
+    // Moves the block time forward while the oracle price goes stale
+    pub fn advance_without_price(&mut self, seconds: u64) {
+        self.app.update_block(|block| {
+            block.time = block.time.plus_seconds(seconds);
+            block.height += seconds / 5;
+        });
+    }
+
+    pub fn set_a_ust_rate(&mut self, rate: &str) {
+        self.run(
+            ANCHOR,
+            &anchor::ExecuteMsg::SetExchangeRate { rate: dec(rate) },
+        );
+    }
+
+    // Feeds the oracle price, the router follows it
+    pub fn set_b_luna_price(&mut self, price: &str) {
+        self.run(
+            ORACLE,
+            &oracle::ExecuteMsg::Feed {
+                price: Some(dec(price)),
+            },
+        );
+        self.set_router_rate(price);
+    }
+
+    // uusd the router pays per bLuna
+    pub fn set_router_rate(&mut self, rate: &str) {
+        self.run(
+            ROUTER,
+            &astroport::router::ExecuteMsg::SetRate { rate: dec(rate) },
+        );
+    }
+
+    // uusd the bLuna-UST pair pays per bLuna
+    pub fn set_pair_rate(&mut self, rate: &str) {
+        self.run(
+            PAIR,
+            &astroport::pair::ExecuteMsg::SetRate { rate: dec(rate) },
+        );
+    }
+
+    // Share of swap proceeds both venues withhold after checking their minimum
+    pub fn set_swap_tax(&mut self, tax: &str) {
+        self.run(
+            ROUTER,
+            &astroport::router::ExecuteMsg::SetTax { tax: dec(tax) },
+        );
+        self.run(PAIR, &astroport::pair::ExecuteMsg::SetTax { tax: dec(tax) });
+    }
+
+    // Share of claimed bLuna Kujira keeps as a fee
+    pub fn set_claim_fee(&mut self, fee: &str) {
+        self.run(KUJIRA, &kujira::ExecuteMsg::SetClaimFee { fee: dec(fee) });
+    }
+
+    pub fn mint_native(&mut self, address: &str, amount: u128) {
+        let address = Addr::unchecked(address);
+        let balance = self.native_balance(address.as_str());
+        self.app
+            .init_bank_balance(
+                &address,
+                vec![Coin {
+                    denom: "uusd".to_string(),
+                    amount: Uint128::from(balance + amount),
+                }],
+            )
+            .unwrap();
+    }
+
+    pub fn mint_token(&mut self, token: &str, address: &str, amount: u128) {
+        let minter: Option<MinterResponse> = self
+            .app
+            .wrap()
+            .query_wasm_smart(token, &Cw20QueryMsg::Minter {})
+            .unwrap();
+        self.app
+            .execute_contract(
+                Addr::unchecked(minter.unwrap().minter),
+                Addr::unchecked(token),
+                &Cw20ExecuteMsg::Mint {
+                    recipient: address.to_string(),
+                    amount: Uint128::from(amount),
+                },
+                &[],
+            )
+            .unwrap();
+    }
+
+    pub fn native_balance(&self, address: &str) -> u128 {
+        self.app
+            .wrap()
+            .query_balance(address, "uusd")
+            .unwrap()
+            .amount
+            .u128()
+    }
+
+    pub fn token_balance(&self, token: &str, address: &str) -> u128 {
+        let res: BalanceResponse = self
+            .app
+            .wrap()
+            .query_wasm_smart(
+                token,
+                &Cw20QueryMsg::Balance {
+                    address: address.to_string(),
+                },
+            )
+            .unwrap();
+        res.balance.u128()
+    }
+
+    // The vault's bids as Kujira reports them
+    pub fn bids(&self) -> Vec<MockBid> {
+        let mut bids = vec![];
+        loop {
+            let res: KujiraBidsResponse = self
+                .app
+                .wrap()
+                .query_wasm_smart(
+                    KUJIRA,
+                    &kujira::QueryMsg::BidsByUser {
+                        collateral_token: B_LUNA.to_string(),
+                        bidder: VAULT.to_string(),
+                        start_after: bids.last().map(|bid: &MockBid| bid.idx),
+                        limit: None,
+                    },
+                )
+                .unwrap();
+            if res.bids.is_empty() {
+                return bids;
+            }
+            bids.extend(res.bids.into_iter().map(|bid| MockBid {
+                idx: bid.idx,
+                premium_slot: bid.premium_slot,
+                strategy: bid.strategy,
+                amount: bid.amount.u128(),
+            }));
+        }
+    }
+
+    // Kujira moves the aUST of a bid into an Anchor bid
+    pub fn activate_bid(&mut self, idx: u64) {
+        self.run(KUJIRA, &kujira::ExecuteMsg::ActivateBid { bid_idx: idx });
+    }
+
+    // Anchor liquidates collateral against an activated bid
+    pub fn liquidate_bid(&mut self, idx: u64, ust: u128, b_luna: u128) {
+        self.run(
+            KUJIRA,
+            &kujira::ExecuteMsg::LiquidateBid {
+                bid_idx: idx,
+                ust: Uint128::from(ust),
+                collateral: Uint128::from(b_luna),
+            },
+        );
+    }
+
+    pub fn query<T: DeserializeOwned>(&self, msg: QueryMsg) -> T {
+        self.app.wrap().query_wasm_smart(VAULT, &msg).unwrap()
+    }
+
+    pub fn deposit(&mut self, sender: &str, amount: u128) -> Result<AppResponse, ContractError> {
+        self.mint_native(sender, amount);
+        self.execute(
+            sender,
+            ExecuteMsg::Deposit {
+                min_shares_out: None,
+            },
+            &[Coin {
+                denom: "uusd".to_string(),
+                amount: Uint128::from(amount),
+            }],
+        )
+    }
+
+    // Sends tokens held by sender to the vault with a receive hook
+    pub fn send_token(
+        &mut self,
+        token: &str,
+        sender: &str,
+        amount: u128,
+        msg: cosmwasm_std::Binary,
+    ) -> Result<AppResponse, ContractError> {
+        self.app
+            .execute_contract(
+                Addr::unchecked(sender),
+                Addr::unchecked(token),
+                &Cw20ExecuteMsg::Send {
+                    contract: VAULT.to_string(),
+                    amount: Uint128::from(amount),
+                    msg,
+                },
+                &[],
+            )
+            .map_err(contract_error)
+    }
+
+    // Executes a vault message and every message it emits, reverting all of it on error
+    pub fn execute(
+        &mut self,
+        sender: &str,
+        msg: ExecuteMsg,
+        funds: &[Coin],
+    ) -> Result<AppResponse, ContractError> {
+        self.app
+            .execute_contract(Addr::unchecked(sender), Addr::unchecked(VAULT), &msg, funds)
+            .map_err(contract_error)
+    }
+
+    // Drives a mock contract, which only the suite does
+    fn run<T: Serialize + std::fmt::Debug>(&mut self, contract: &str, msg: &T) {
+        self.app
+            .execute_contract(Addr::unchecked(OWNER), Addr::unchecked(contract), msg, &[])
+            .unwrap();
+    }
+}
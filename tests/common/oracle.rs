@@ -0,0 +1,96 @@
+// Price oracle quoting one asset in uusd, fed by the suite
+use cosmwasm_std::{
+    to_binary, Binary, Decimal256, Deps, DepsMut, Empty, Env, MessageInfo, Response, StdError,
+    StdResult,
+};
+use cw_multi_test::{Contract, ContractWrapper};
+use cw_storage_plus::Item;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct InstantiateMsg {
+    pub asset: String,
+    pub price: Decimal256,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecuteMsg {
+    // Refreshes the price at the current block, replacing it when set
+    Feed { price: Option<Decimal256> },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryMsg {
+    Price { base: String, quote: String },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PriceResponse {
+    pub rate: Decimal256,
+    pub last_updated_base: u64,
+    pub last_updated_quote: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct Feed {
+    asset: String,
+    price: Decimal256,
+    updated_at: u64,
+}
+
+const FEED: Item<Feed> = Item::new("feed");
+
+pub fn contract() -> Box<dyn Contract<Empty>> {
+    Box::new(ContractWrapper::new(execute, instantiate, query))
+}
+
+fn instantiate(
+    deps: DepsMut,
+    env: Env,
+    _info: MessageInfo,
+    msg: InstantiateMsg,
+) -> StdResult<Response> {
+    FEED.save(
+        deps.storage,
+        &Feed {
+            asset: msg.asset,
+            price: msg.price,
+            updated_at: env.block.time.seconds(),
+        },
+    )?;
+    Ok(Response::new())
+}
+
+fn execute(deps: DepsMut, env: Env, _info: MessageInfo, msg: ExecuteMsg) -> StdResult<Response> {
+    match msg {
+        ExecuteMsg::Feed { price } => {
+            FEED.update(deps.storage, |mut feed| -> StdResult<_> {
+                feed.price = price.unwrap_or(feed.price);
+                feed.updated_at = env.block.time.seconds();
+                Ok(feed)
+            })?;
+            Ok(Response::new())
+        }
+    }
+}
+
+fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
+    match msg {
+        QueryMsg::Price { base, quote } => {
+            let feed = FEED.load(deps.storage)?;
+            if base != feed.asset || quote != "uusd" {
+                return Err(StdError::generic_err(format!(
+                    "no price for {}/{}",
+                    base, quote
+                )));
+            }
+            to_binary(&PriceResponse {
+                rate: feed.price,
+                last_updated_base: feed.updated_at,
+                last_updated_quote: feed.updated_at,
+            })
+        }
+    }
+}
@@ -0,0 +1,132 @@
+mod common;
+
+use cosmwasm_std::{Addr, Decimal256, Uint128};
+use cw20::BalanceResponse;
+use std::str::FromStr;
+
+use common::*;
+use terra_deposit_withdraw::fees::AccruedFees;
+use terra_deposit_withdraw::msg::{ExecuteMsg, HighWaterMarkResponse, QueryMsg};
+use terra_deposit_withdraw::ContractError;
+
+const COLLECTOR: &str = "fee_collector";
+const YEAR: u64 = 365 * 24 * 60 * 60;
+
+fn share_of(suite: &Suite, address: &str) -> u128 {
+    let res: BalanceResponse = suite.query(QueryMsg::Balance {
+        address: address.to_string(),
+    });
+    res.balance.u128()
+}
+
+fn fee_suite() -> Suite {
+    Suite::with_msg(|msg| {
+        msg.fee_collector = Some(Addr::unchecked(COLLECTOR));
+        msg.management_fee_bps = Some(200);
+        msg.performance_fee_bps = Some(2_000);
+    })
+}
+
+#[test]
+fn management_fee_accrues_over_time() {
+    let mut suite = fee_suite();
+    suite.deposit("alice", 1_000_000).unwrap();
+    suite.advance(YEAR);
+
+    // 2% of the total cap, paid in shares after dilution
+    let fees: AccruedFees = suite.query(QueryMsg::AccruedFees {});
+    assert_eq!(fees.management_fee, Uint128::from(20_000u128));
+    assert_eq!(fees.performance_fee, Uint128::zero());
+    assert_eq!(fees.share, Uint128::from(20_408u128));
+
+    // Anyone can collect
+    suite
+        .execute(KEEPER, ExecuteMsg::CollectFees {}, &[])
+        .unwrap();
+    assert_eq!(share_of(&suite, COLLECTOR), 20_408);
+    let fees: AccruedFees = suite.query(QueryMsg::AccruedFees {});
+    assert_eq!(fees.share, Uint128::zero());
+    let res: HighWaterMarkResponse = suite.query(QueryMsg::HighWaterMark {});
+    assert_eq!(res.high_water_mark, Decimal256::one());
+}
+
+#[test]
+fn performance_fee_above_high_water_mark() {
+    let mut suite = fee_suite();
+    suite.deposit("alice", 1_000_000).unwrap();
+    suite.advance(YEAR);
+    suite
+        .execute(KEEPER, ExecuteMsg::CollectFees {}, &[])
+        .unwrap();
+
+    // NAV per share grows to 1.47, 20% of the gain goes to the collector on the next deposit
+    suite.set_a_ust_rate("1.5");
+    let fees: AccruedFees = suite.query(QueryMsg::AccruedFees {});
+    assert_eq!(fees.performance_fee, Uint128::from(95_918u128));
+    suite.deposit("bob", 1_500_000).unwrap();
+    assert_eq!(share_of(&suite, COLLECTOR), 20_408 + 69_707);
+    assert_eq!(share_of(&suite, "bob"), 1_090_115);
+
+    let res: HighWaterMarkResponse = suite.query(QueryMsg::HighWaterMark {});
+    assert_eq!(
+        res.high_water_mark,
+        Decimal256::from_str("1.376001614508561023").unwrap()
+    );
+    // No performance fee until the mark is beaten again
+    suite.set_a_ust_rate("1.4");
+    let fees: AccruedFees = suite.query(QueryMsg::AccruedFees {});
+    assert_eq!(fees.performance_fee, Uint128::zero());
+}
+
+#[test]
+fn fee_rates_and_accrual_are_bounded() {
+    let mut suite = fee_suite();
+    let err = suite
+        .execute(
+            OWNER,
+            update_config(|msg| msg.management_fee_bps = Some(501)),
+            &[],
+        )
+        .unwrap_err();
+    assert!(matches!(err, ContractError::BpsAboveMax { max: 500, .. }));
+    let err = suite
+        .execute(
+            OWNER,
+            update_config(|msg| msg.performance_fee_bps = Some(3_001)),
+            &[],
+        )
+        .unwrap_err();
+    assert!(matches!(err, ContractError::BpsAboveMax { max: 3_000, .. }));
+    suite
+        .execute(
+            OWNER,
+            update_config(|msg| msg.management_fee_bps = Some(500)),
+            &[],
+        )
+        .unwrap();
+
+    // Late collection charges the whole period
+    suite.deposit("alice", 1_000_000).unwrap();
+    suite.advance(4 * YEAR);
+    let fees: AccruedFees = suite.query(QueryMsg::AccruedFees {});
+    assert_eq!(fees.management_fee, Uint128::from(200_000u128));
+
+    // But never more than half of the total cap at once
+    suite.advance(26 * YEAR);
+    let fees: AccruedFees = suite.query(QueryMsg::AccruedFees {});
+    assert_eq!(fees.management_fee, Uint128::from(500_000u128));
+    assert_eq!(fees.share, Uint128::from(1_000_000u128));
+
+    // Settling at the old rates needs a trusted price like any other collection
+    let err = suite
+        .execute(
+            OWNER,
+            update_config(|msg| {
+                msg.max_b_luna_price = Some(Decimal256::from_str("0.01").unwrap());
+                msg.management_fee_bps = Some(100);
+            }),
+            &[],
+        )
+        .unwrap_err();
+    assert!(matches!(err, ContractError::PriceOutOfBounds { .. }));
+}
@@ -0,0 +1,2131 @@
+mod common;
+
+use cosmwasm_std::{to_binary, Addr, Coin, Decimal256, Uint128, Uint256};
+use cw20::{BalanceResponse, Expiration, TokenInfoResponse};
+use std::str::FromStr;
+
+use common::*;
+use terra_deposit_withdraw::msg::AssetInfo::{self, NativeToken, Token};
+use terra_deposit_withdraw::msg::{
+    BidStrategy, ConfigResponse, CumulativeLoanAmount, ExecuteMsg, InfoResponse, NextSwapResponse,
+    PendingConfigChangeResponse, PendingOwnerResponse, PermissionResponse, PermissionsResponse,
+    QueryMsg, ReceiveMsg, ShutdownBasketResponse, SimulateSwapResponse, SlotAllocation,
+    StrategyPreset, StrategyPresetsResponse, SwapOperation, SwapVenue, TotalCapResponse,
+    UnlockableResponse, WithdrawalRequestsResponse,
+};
+use terra_deposit_withdraw::nav::Nav;
+use terra_deposit_withdraw::state::{PauseFlags, Role, SettlementStats, ShutdownBasket};
+use terra_deposit_withdraw::ContractError;
+
+const DAY: u64 = 24 * 60 * 60;
+const PAUSE_DEPOSIT: PauseFlags = PauseFlags {
+    deposit: true,
+    withdraw_ust: false,
+    withdraw_b_luna: false,
+    submit_bid: false,
+    claim: false,
+    unlock: false,
+    swap: false,
+};
+
+fn share_of(suite: &Suite, address: &str) -> u128 {
+    let res: BalanceResponse = suite.query(QueryMsg::Balance {
+        address: address.to_string(),
+    });
+    res.balance.u128()
+}
+
+fn swap_all() -> ExecuteMsg {
+    ExecuteMsg::Swap {
+        amount: None,
+        minimum_receive: None,
+        venue: None,
+    }
+}
+
+fn total_cap(suite: &Suite) -> u128 {
+    let res: TotalCapResponse = suite.query(QueryMsg::TotalCap {});
+    res.total_cap.u128()
+}
+
+#[test]
+fn deposit_and_withdraw_ust() {
+    let mut suite = Suite::new();
+    suite.deposit("alice", 1_000_000).unwrap();
+    assert_eq!(share_of(&suite, "alice"), 1_000_000);
+    // Deposited UST goes straight to Anchor
+    assert_eq!(suite.native_balance(VAULT), 0);
+    assert_eq!(suite.token_balance(A_UST, VAULT), 1_000_000);
+
+    let err = suite
+        .execute(
+            "alice",
+            ExecuteMsg::WithdrawUst {
+                share: Uint128::from(1_000_000u128),
+                min_amount_out: None,
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert!(matches!(err, ContractError::WithdrawLocked { .. }));
+
+    suite.advance(WITHDRAW_LOCK + 1);
+    suite.set_a_ust_rate("1.05");
+    suite
+        .execute(
+            "alice",
+            ExecuteMsg::WithdrawUst {
+                share: Uint128::from(1_000_000u128),
+                min_amount_out: None,
+            },
+            &[],
+        )
+        .unwrap();
+    assert_eq!(suite.native_balance("alice"), 1_050_000);
+    assert_eq!(share_of(&suite, "alice"), 0);
+    assert_eq!(suite.token_balance(A_UST, VAULT), 0);
+    let info: InfoResponse = suite.query(QueryMsg::GetInfo {});
+    assert_eq!(info.total_supply, Uint128::zero());
+}
+
+#[test]
+fn slippage_bounds() {
+    let mut suite = Suite::new();
+    suite.deposit("alice", 1_000_000).unwrap();
+    suite.set_a_ust_rate("1.25");
+
+    // 1_250_000 UST now buys 1_000_000 shares
+    suite.mint_native("bob", 1_250_000);
+    let funds = [Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(1_250_000u128),
+    }];
+    let err = suite
+        .execute(
+            "bob",
+            ExecuteMsg::Deposit {
+                min_shares_out: Some(Uint128::from(1_000_001u128)),
+            },
+            &funds,
+        )
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::SlippageExceeded { minimum, actual }
+            if minimum == Uint128::from(1_000_001u128) && actual == Uint128::from(1_000_000u128)
+    ));
+    suite
+        .execute(
+            "bob",
+            ExecuteMsg::Deposit {
+                min_shares_out: Some(Uint128::from(1_000_000u128)),
+            },
+            &funds,
+        )
+        .unwrap();
+    assert_eq!(share_of(&suite, "bob"), 1_000_000);
+
+    suite.advance(WITHDRAW_LOCK + 1);
+    let err = suite
+        .execute(
+            "alice",
+            ExecuteMsg::WithdrawUst {
+                share: Uint128::from(1_000_000u128),
+                min_amount_out: Some(Uint128::from(1_250_001u128)),
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert!(matches!(err, ContractError::SlippageExceeded { .. }));
+    assert_eq!(share_of(&suite, "alice"), 1_000_000);
+
+    let err = suite
+        .execute(
+            "alice",
+            ExecuteMsg::WithdrawBLuna {
+                share: Uint128::from(1_000_000u128),
+                min_amount_out: Some(Uint128::from(1_000_000_000_000u128)),
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert!(matches!(err, ContractError::SlippageExceeded { .. }));
+
+    suite
+        .execute(
+            "alice",
+            ExecuteMsg::WithdrawUst {
+                share: Uint128::from(1_000_000u128),
+                min_amount_out: Some(Uint128::from(1_250_000u128)),
+            },
+            &[],
+        )
+        .unwrap();
+    assert_eq!(suite.native_balance("alice"), 1_250_000);
+}
+
+#[test]
+fn withdrawal_queue() {
+    let mut suite = Suite::new();
+    suite.deposit("alice", 1_000_000).unwrap();
+    suite.deposit("bob", 1_000_000).unwrap();
+    suite.advance(WITHDRAW_LOCK + 1);
+
+    suite
+        .execute(
+            "alice",
+            ExecuteMsg::RequestWithdrawal {
+                share: Uint128::from(1_000_000u128),
+            },
+            &[],
+        )
+        .unwrap();
+    // Shares are escrowed by the vault and the UST value is owed
+    assert_eq!(share_of(&suite, "alice"), 0);
+    assert_eq!(share_of(&suite, VAULT), 1_000_000);
+    assert_eq!(total_cap(&suite), 1_000_000);
+    let res: WithdrawalRequestsResponse = suite.query(QueryMsg::WithdrawalRequestsByOwner {
+        owner: "alice".to_string(),
+        start_after: None,
+        limit: None,
+    });
+    assert_eq!(res.requests.len(), 1);
+    let id = res.requests[0].id;
+    assert_eq!(res.requests[0].amount, Uint128::from(1_000_000u128));
+    assert!(!res.requests[0].filled);
+
+    // Nothing to fill with while all UST sits in Anchor
+    suite
+        .execute(KEEPER, ExecuteMsg::ProcessWithdrawals { limit: None }, &[])
+        .unwrap();
+    let err = suite
+        .execute("alice", ExecuteMsg::ClaimWithdrawal { id }, &[])
+        .unwrap_err();
+    assert!(matches!(err, ContractError::WithdrawalNotFilled { .. }));
+
+    // New UST stays in the vault while the queue is owed
+    suite.deposit("carol", 1_000_000).unwrap();
+    assert_eq!(share_of(&suite, "carol"), 1_000_000);
+    assert_eq!(suite.native_balance(VAULT), 1_000_000);
+    suite
+        .execute(KEEPER, ExecuteMsg::ProcessWithdrawals { limit: None }, &[])
+        .unwrap();
+    let res: WithdrawalRequestsResponse = suite.query(QueryMsg::WithdrawalRequests {
+        start_after: None,
+        limit: None,
+    });
+    assert!(res.requests[0].filled);
+    assert_eq!(share_of(&suite, VAULT), 0);
+    assert_eq!(total_cap(&suite), 2_000_000);
+    let token_info: TokenInfoResponse = suite.query(QueryMsg::TokenInfo {});
+    assert_eq!(token_info.total_supply, Uint128::from(2_000_000u128));
+
+    let err = suite
+        .execute("bob", ExecuteMsg::ClaimWithdrawal { id }, &[])
+        .unwrap_err();
+    assert!(matches!(err, ContractError::NotRequester { address, .. } if address == "bob"));
+    suite
+        .execute("alice", ExecuteMsg::ClaimWithdrawal { id }, &[])
+        .unwrap();
+    assert_eq!(suite.native_balance("alice"), 1_000_000);
+    assert_eq!(suite.native_balance(VAULT), 0);
+    let res: WithdrawalRequestsResponse = suite.query(QueryMsg::WithdrawalRequests {
+        start_after: None,
+        limit: None,
+    });
+    assert!(res.requests.is_empty());
+}
+
+#[test]
+fn withdrawal_queue_resumes_after_filled_requests() {
+    let mut suite = Suite::new();
+    suite.deposit("alice", 1_000_000).unwrap();
+    suite.advance(WITHDRAW_LOCK + 1);
+    for _ in 0..3 {
+        suite
+            .execute(
+                "alice",
+                ExecuteMsg::RequestWithdrawal {
+                    share: Uint128::from(100_000u128),
+                },
+                &[],
+            )
+            .unwrap();
+    }
+    suite.deposit("bob", 300_000).unwrap();
+
+    // Each pass starts at the first unfilled request, filled ones left unclaimed are skipped
+    let filled = |suite: &Suite| -> Vec<bool> {
+        let res: WithdrawalRequestsResponse = suite.query(QueryMsg::WithdrawalRequests {
+            start_after: None,
+            limit: None,
+        });
+        res.requests.iter().map(|request| request.filled).collect()
+    };
+    for expected in [
+        [true, false, false],
+        [true, true, false],
+        [true, true, true],
+    ] {
+        let res = suite
+            .execute(
+                KEEPER,
+                ExecuteMsg::ProcessWithdrawals { limit: Some(1) },
+                &[],
+            )
+            .unwrap();
+        assert_eq!(filled(&suite), expected);
+        assert!(attributes(&res, VAULT)
+            .iter()
+            .any(|attr| attr.key == "filled"));
+    }
+    let res = suite
+        .execute(
+            KEEPER,
+            ExecuteMsg::ProcessWithdrawals { limit: Some(1) },
+            &[],
+        )
+        .unwrap();
+    assert!(!attributes(&res, VAULT)
+        .iter()
+        .any(|attr| attr.key == "filled"));
+
+    // A request queued after the cursor moved is still picked up
+    suite
+        .execute(
+            "alice",
+            ExecuteMsg::RequestWithdrawal {
+                share: Uint128::from(100_000u128),
+            },
+            &[],
+        )
+        .unwrap();
+    suite.deposit("bob", 100_000).unwrap();
+    suite
+        .execute(
+            KEEPER,
+            ExecuteMsg::ProcessWithdrawals { limit: Some(1) },
+            &[],
+        )
+        .unwrap();
+    assert_eq!(filled(&suite), [true, true, true, true]);
+}
+
+#[test]
+fn withdrawal_queue_skips_and_cancels_requests() {
+    let mut suite = Suite::new();
+    suite.deposit("alice", 1_000_000).unwrap();
+    suite.advance(WITHDRAW_LOCK + 1);
+    let request = |share: u128| ExecuteMsg::RequestWithdrawal {
+        share: Uint128::from(share),
+    };
+    suite.execute("alice", request(500_000), &[]).unwrap();
+    suite.execute("alice", request(100_000), &[]).unwrap();
+    let requests = |suite: &Suite| -> Vec<(u64, bool)> {
+        let res: WithdrawalRequestsResponse = suite.query(QueryMsg::WithdrawalRequests {
+            start_after: None,
+            limit: None,
+        });
+        res.requests
+            .iter()
+            .map(|request| (request.id, request.filled))
+            .collect()
+    };
+
+    // The large request doesn't block the small one behind it
+    suite.deposit("bob", 200_000).unwrap();
+    suite
+        .execute(KEEPER, ExecuteMsg::ProcessWithdrawals { limit: None }, &[])
+        .unwrap();
+    assert_eq!(requests(&suite), [(1, false), (2, true)]);
+
+    let cancel = |id: u64| ExecuteMsg::CancelWithdrawal { id };
+    let err = suite.execute("bob", cancel(1), &[]).unwrap_err();
+    assert!(matches!(err, ContractError::NotRequester { id: 1, .. }));
+    let err = suite.execute("alice", cancel(2), &[]).unwrap_err();
+    assert!(matches!(err, ContractError::WithdrawalFilled { id: 2 }));
+    let err = suite.execute("alice", cancel(3), &[]).unwrap_err();
+    assert!(matches!(err, ContractError::WithdrawalNotFound { id: 3 }));
+
+    // Cancelling returns the escrowed shares and the queued UST
+    suite.execute("alice", cancel(1), &[]).unwrap();
+    assert_eq!(share_of(&suite, "alice"), 900_000);
+    assert_eq!(share_of(&suite, VAULT), 0);
+    let nav: Nav = suite.query(QueryMsg::Nav {});
+    assert_eq!(nav.queued_ust, Uint128::zero());
+    assert_eq!(total_cap(&suite), 1_100_000);
+    assert_eq!(requests(&suite), [(2, true)]);
+
+    // The cursor moves past the cancelled request
+    suite.execute("alice", request(100_000), &[]).unwrap();
+    suite.deposit("bob", 100_000).unwrap();
+    suite
+        .execute(KEEPER, ExecuteMsg::ProcessWithdrawals { limit: None }, &[])
+        .unwrap();
+    assert_eq!(requests(&suite), [(2, true), (3, true)]);
+}
+
+#[test]
+fn withdrawal_queue_is_written_down_after_losses() {
+    let mut suite = Suite::new();
+    suite.deposit("alice", 1_000_000).unwrap();
+    suite.deposit("bob", 1_000_000).unwrap();
+    suite.advance(WITHDRAW_LOCK + 1);
+    for owner in ["alice", "bob"] {
+        suite
+            .execute(
+                owner,
+                ExecuteMsg::RequestWithdrawal {
+                    share: Uint128::from(1_000_000u128),
+                },
+                &[],
+            )
+            .unwrap();
+    }
+
+    // The vault is now worth 0.4 UST for the 2 UST queued
+    suite.set_a_ust_rate("0.1");
+    suite.mint_native(VAULT, 200_000);
+    assert_eq!(total_cap(&suite), 0);
+    suite
+        .execute(KEEPER, ExecuteMsg::ProcessWithdrawals { limit: None }, &[])
+        .unwrap();
+    let res: WithdrawalRequestsResponse = suite.query(QueryMsg::WithdrawalRequests {
+        start_after: None,
+        limit: None,
+    });
+    assert!(res.requests[0].filled);
+    assert_eq!(res.requests[0].amount, Uint128::from(200_000u128));
+    assert!(!res.requests[1].filled);
+    let nav: Nav = suite.query(QueryMsg::Nav {});
+    assert_eq!(nav.queued_ust, Uint128::from(1_000_000u128));
+    assert_eq!(nav.gross_cap().unwrap(), Uint128::from(200_000u128));
+    assert_eq!(total_cap(&suite), 0);
+
+    suite
+        .execute(
+            "alice",
+            ExecuteMsg::ClaimWithdrawal {
+                id: res.requests[0].id,
+            },
+            &[],
+        )
+        .unwrap();
+    assert_eq!(suite.native_balance("alice"), 200_000);
+}
+
+#[test]
+fn share_price_across_users() {
+    let mut suite = Suite::new();
+    suite.deposit("alice", 1_000_000).unwrap();
+    suite.set_a_ust_rate("1.25");
+    assert_eq!(total_cap(&suite), 1_250_000);
+
+    // Bob pays the grown share price
+    suite.deposit("bob", 1_250_000).unwrap();
+    assert_eq!(share_of(&suite, "bob"), 1_000_000);
+    assert_eq!(total_cap(&suite), 2_500_000);
+    let token_info: TokenInfoResponse = suite.query(QueryMsg::TokenInfo {});
+    assert_eq!(token_info.total_supply, Uint128::from(2_000_000u128));
+
+    suite.advance(WITHDRAW_LOCK + 1);
+    suite.set_a_ust_rate("1.5");
+    for user in ["alice", "bob"] {
+        suite
+            .execute(
+                user,
+                ExecuteMsg::WithdrawUst {
+                    share: Uint128::from(1_000_000u128),
+                    min_amount_out: None,
+                },
+                &[],
+            )
+            .unwrap();
+        assert_eq!(suite.native_balance(user), 1_500_000);
+    }
+    assert_eq!(total_cap(&suite), 0);
+}
+
+#[test]
+fn liquidation_lifecycle() {
+    let mut suite = Suite::new();
+    suite.deposit("alice", 10_000_000).unwrap();
+
+    let err = suite
+        .execute(
+            "alice",
+            ExecuteMsg::SubmitBid {
+                amount: Uint128::from(8_000_000u128),
+                premium_slot: 5,
+                strategy: None,
+                preset: None,
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert!(matches!(err, ContractError::MissingRole { .. }));
+    suite
+        .execute(
+            OWNER,
+            ExecuteMsg::SubmitBid {
+                amount: Uint128::from(8_000_000u128),
+                premium_slot: 5,
+                strategy: None,
+                preset: None,
+            },
+            &[],
+        )
+        .unwrap();
+    assert_eq!(suite.token_balance(A_UST, VAULT), 2_000_000);
+    assert_eq!(suite.token_balance(A_UST, KUJIRA), 8_000_000);
+    assert_eq!(total_cap(&suite), 10_000_000);
+
+    // 5 UST buys 0.11 bLuna worth 5.5 UST
+    let idx = suite.bids()[0].idx;
+    suite.activate_bid(idx);
+    suite.liquidate_bid(idx, 5_000_000, 110_000);
+    let nav: Nav = suite.query(QueryMsg::Nav {});
+    assert_eq!(nav.a_ust, Uint128::from(2_000_000u128));
+    assert_eq!(nav.a_ust_in_bids, Uint128::zero());
+    assert_eq!(nav.ust_in_active_bids, Uint128::from(3_000_000u128));
+    assert_eq!(nav.pending_b_luna, Uint128::from(110_000u128));
+    assert_eq!(nav.total_cap().unwrap().u128(), 10_500_000);
+    assert_eq!(total_cap(&suite), 10_500_000);
+
+    suite
+        .execute(KEEPER, ExecuteMsg::ClaimLiquidation {}, &[])
+        .unwrap();
+    assert_eq!(suite.token_balance(B_LUNA, VAULT), 110_000);
+    let info: InfoResponse = suite.query(QueryMsg::GetInfo {});
+    assert_eq!(info.locked_b_luna, Uint128::from(110_000u128));
+    assert_eq!(total_cap(&suite), 10_500_000);
+
+    // Locked bLuna can't be swapped nor unlocked early
+    let err = suite.execute(KEEPER, swap_all(), &[]).unwrap_err();
+    assert!(matches!(err, ContractError::NothingToSwap {}));
+    let err = suite
+        .execute(KEEPER, ExecuteMsg::Unlock {}, &[])
+        .unwrap_err();
+    assert!(matches!(err, ContractError::NothingToUnlock {}));
+    let res: UnlockableResponse = suite.query(QueryMsg::Unlockable {});
+    assert!(!res.unlockable);
+
+    suite.advance(LOCK_PERIOD);
+    // Only the 5 UST of aUST and the active bid can be paid out right away
+    let err = suite
+        .execute(
+            "alice",
+            ExecuteMsg::WithdrawUst {
+                share: Uint128::from(10_000_000u128),
+                min_amount_out: Some(Uint128::from(10_500_000u128)),
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::SlippageExceeded { actual, .. } if actual == Uint128::from(5_000_000u128)
+    ));
+    let res: UnlockableResponse = suite.query(QueryMsg::Unlockable {});
+    assert!(res.unlockable);
+    suite.execute(KEEPER, ExecuteMsg::Unlock {}, &[]).unwrap();
+    suite.execute(KEEPER, swap_all(), &[]).unwrap();
+    assert_eq!(suite.token_balance(B_LUNA, VAULT), 0);
+    assert_eq!(suite.native_balance(VAULT), 5_500_000);
+    assert_eq!(total_cap(&suite), 10_500_000);
+
+    // Withdrawal retracts the remaining bid and redeems aUST
+    suite
+        .execute(
+            "alice",
+            ExecuteMsg::WithdrawUst {
+                share: Uint128::from(10_000_000u128),
+                min_amount_out: None,
+            },
+            &[],
+        )
+        .unwrap();
+    assert_eq!(suite.native_balance("alice"), 10_500_000);
+    assert!(suite.bids().is_empty());
+    assert_eq!(total_cap(&suite), 0);
+}
+
+#[test]
+fn withdraw_ust_queues_the_shortfall() {
+    let mut suite = Suite::new();
+    suite.deposit("alice", 10_000_000).unwrap();
+    suite
+        .execute(
+            OWNER,
+            ExecuteMsg::SubmitBid {
+                amount: Uint128::from(8_000_000u128),
+                premium_slot: 5,
+                strategy: None,
+                preset: None,
+            },
+            &[],
+        )
+        .unwrap();
+    let idx = suite.bids()[0].idx;
+    suite.activate_bid(idx);
+    suite.liquidate_bid(idx, 5_000_000, 110_000);
+    suite
+        .execute(KEEPER, ExecuteMsg::ClaimLiquidation {}, &[])
+        .unwrap();
+    suite.advance(WITHDRAW_LOCK + 1);
+
+    // 5 UST is paid right away, the 5.5 UST locked as bLuna is queued
+    let res = suite
+        .execute(
+            "alice",
+            ExecuteMsg::WithdrawUst {
+                share: Uint128::from(10_000_000u128),
+                min_amount_out: Some(Uint128::from(5_000_000u128)),
+            },
+            &[],
+        )
+        .unwrap();
+    assert!(attributes(&res, VAULT)
+        .iter()
+        .any(|attr| attr.key == "queued" && attr.value == "5500000"));
+    assert_eq!(suite.native_balance("alice"), 5_000_000);
+    assert_eq!(suite.token_balance(B_LUNA, SWAP_WALLET), 0);
+    assert_eq!(share_of(&suite, "alice"), 0);
+    assert_eq!(share_of(&suite, VAULT), 5_238_095);
+    let res: WithdrawalRequestsResponse = suite.query(QueryMsg::WithdrawalRequestsByOwner {
+        owner: "alice".to_string(),
+        start_after: None,
+        limit: None,
+    });
+    let id = res.requests[0].id;
+    assert_eq!(res.requests[0].amount, Uint128::from(5_500_000u128));
+    assert_eq!(res.requests[0].share, Uint128::from(5_238_095u128));
+
+    // The swapped bLuna fills the request
+    suite.advance(LOCK_PERIOD);
+    suite.execute(KEEPER, ExecuteMsg::Unlock {}, &[]).unwrap();
+    suite.execute(KEEPER, swap_all(), &[]).unwrap();
+    suite
+        .execute(KEEPER, ExecuteMsg::ProcessWithdrawals { limit: None }, &[])
+        .unwrap();
+    suite
+        .execute("alice", ExecuteMsg::ClaimWithdrawal { id }, &[])
+        .unwrap();
+    assert_eq!(suite.native_balance("alice"), 10_500_000);
+    let token_info: TokenInfoResponse = suite.query(QueryMsg::TokenInfo {});
+    assert_eq!(token_info.total_supply, Uint128::zero());
+}
+
+#[test]
+fn bid_allocation_and_retraction() {
+    let mut suite = Suite::new();
+    suite.deposit("alice", 10_000_000).unwrap();
+
+    let allocations = vec![
+        (3, Uint128::from(2_000_000u128)),
+        (5, Uint128::from(3_000_000u128)),
+    ];
+    let err = suite
+        .execute(
+            "alice",
+            ExecuteMsg::SubmitBids {
+                allocations: allocations.clone(),
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert!(matches!(err, ContractError::MissingRole { .. }));
+    let res = suite
+        .execute(OWNER, ExecuteMsg::SubmitBids { allocations }, &[])
+        .unwrap();
+    assert_eq!(
+        attributes(&res, KUJIRA)
+            .iter()
+            .filter(|attr| attr.key == "action" && attr.value == "submit_bid")
+            .count(),
+        2
+    );
+    assert_eq!(
+        attributes(&res, VAULT)
+            .iter()
+            .filter(|attr| attr.key == "premium_slot")
+            .count(),
+        2
+    );
+    let bids = suite.bids();
+    assert_eq!(
+        bids.iter()
+            .map(|bid| (bid.premium_slot, bid.amount))
+            .collect::<Vec<_>>(),
+        vec![(3, 2_000_000), (5, 3_000_000)]
+    );
+    assert_eq!(suite.token_balance(A_UST, VAULT), 5_000_000);
+
+    // Move part of the slot 5 bid back into the vault
+    let err = suite
+        .execute(
+            OWNER,
+            ExecuteMsg::RetractBid {
+                bid_idx: bids[1].idx,
+                amount: Some(Uint128::from(3_000_001u128)),
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert!(matches!(err, ContractError::InvalidRetractAmount { .. }));
+    suite
+        .execute(
+            OWNER,
+            ExecuteMsg::RetractBid {
+                bid_idx: bids[1].idx,
+                amount: Some(Uint128::from(1_000_000u128)),
+            },
+            &[],
+        )
+        .unwrap();
+    assert_eq!(suite.bids()[1].amount, 2_000_000);
+    assert_eq!(suite.token_balance(A_UST, VAULT), 6_000_000);
+
+    // Retract the slot 3 bid entirely
+    suite
+        .execute(
+            OWNER,
+            ExecuteMsg::RetractBid {
+                bid_idx: bids[0].idx,
+                amount: None,
+            },
+            &[],
+        )
+        .unwrap();
+    assert_eq!(suite.bids().len(), 1);
+    assert_eq!(suite.token_balance(A_UST, VAULT), 8_000_000);
+    assert_eq!(total_cap(&suite), 10_000_000);
+
+    let err = suite
+        .execute(
+            OWNER,
+            ExecuteMsg::RetractBid {
+                bid_idx: bids[0].idx,
+                amount: None,
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert!(matches!(err, ContractError::BidNotFound { .. }));
+}
+
+fn slot_amounts(suite: &Suite) -> Vec<(u8, u128)> {
+    let mut amounts = std::collections::BTreeMap::new();
+    for bid in suite.bids() {
+        *amounts.entry(bid.premium_slot).or_insert(0) += bid.amount;
+    }
+    amounts.into_iter().collect()
+}
+
+fn set_target_allocation(suite: &mut Suite, target_allocation: Vec<(u8, u16)>) {
+    suite
+        .execute(
+            OWNER,
+            update_config(|msg| {
+                msg.target_allocation = Some(
+                    target_allocation
+                        .into_iter()
+                        .map(|(premium_slot, bps)| SlotAllocation { premium_slot, bps })
+                        .collect(),
+                );
+                msg.rebalance_tolerance_bps = Some(100);
+            }),
+            &[],
+        )
+        .unwrap();
+}
+
+fn strategy(activate_ltv: u8, deactivate_ltv: u8) -> BidStrategy {
+    BidStrategy {
+        activate_at: CumulativeLoanAmount {
+            ltv: activate_ltv,
+            cumulative_value: Uint256::from(1_000_000_000u128),
+        },
+        deactivate_at: CumulativeLoanAmount {
+            ltv: deactivate_ltv,
+            cumulative_value: Uint256::from(100_000_000u128),
+        },
+    }
+}
+
+#[test]
+fn bid_strategy_presets() {
+    let mut suite = Suite::new();
+    suite.deposit("alice", 10_000_000).unwrap();
+
+    let mut inverted_value = strategy(95, 90);
+    inverted_value.deactivate_at.cumulative_value = Uint256::from(2_000_000_000u128);
+    for invalid in [
+        strategy(101, 90),
+        strategy(90, 0),
+        strategy(90, 95),
+        inverted_value,
+    ] {
+        let err = suite
+            .execute(
+                OWNER,
+                ExecuteMsg::SetStrategyPreset {
+                    name: "aggressive".to_string(),
+                    strategy: invalid,
+                },
+                &[],
+            )
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::InvalidLtv { ltv: 101 | 0 }
+                | ContractError::ActivateLtvBelowDeactivate {
+                    activate: 90,
+                    deactivate: 95
+                }
+                | ContractError::ActivateValueBelowDeactivate { .. }
+        ));
+    }
+    let err = suite
+        .execute(
+            "alice",
+            ExecuteMsg::SetStrategyPreset {
+                name: "aggressive".to_string(),
+                strategy: strategy(95, 90),
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert!(matches!(err, ContractError::MissingRole { .. }));
+    suite
+        .execute(
+            OWNER,
+            ExecuteMsg::SetStrategyPreset {
+                name: "aggressive".to_string(),
+                strategy: strategy(95, 90),
+            },
+            &[],
+        )
+        .unwrap();
+    let res: StrategyPresetsResponse = suite.query(QueryMsg::StrategyPresets {
+        start_after: None,
+        limit: None,
+    });
+    assert_eq!(
+        res.presets,
+        vec![StrategyPreset {
+            name: "aggressive".to_string(),
+            strategy: strategy(95, 90),
+        }]
+    );
+
+    let submit = |strategy: Option<BidStrategy>, preset: Option<&str>| ExecuteMsg::SubmitBid {
+        amount: Uint128::from(1_000_000u128),
+        premium_slot: 10,
+        strategy,
+        preset: preset.map(str::to_string),
+    };
+    suite
+        .execute(OWNER, submit(None, Some("aggressive")), &[])
+        .unwrap();
+    suite
+        .execute(OWNER, submit(Some(strategy(80, 70)), None), &[])
+        .unwrap();
+    suite.execute(OWNER, submit(None, None), &[]).unwrap();
+    let config: ConfigResponse = suite.query(QueryMsg::Config {});
+    assert_eq!(
+        suite
+            .bids()
+            .into_iter()
+            .map(|bid| bid.strategy)
+            .collect::<Vec<_>>(),
+        vec![strategy(95, 90), strategy(80, 70), config.bid_strategy]
+    );
+
+    let err = suite
+        .execute(OWNER, submit(Some(strategy(80, 90)), None), &[])
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::ActivateLtvBelowDeactivate {
+            activate: 80,
+            deactivate: 90
+        }
+    ));
+    for msg in [
+        submit(Some(strategy(80, 70)), Some("aggressive")),
+        submit(None, Some("unknown")),
+    ] {
+        let err = suite.execute(OWNER, msg, &[]).unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::StrategyAndPreset {} | ContractError::UnknownPreset { .. }
+        ));
+    }
+
+    suite
+        .execute(
+            OWNER,
+            ExecuteMsg::RemoveStrategyPreset {
+                name: "aggressive".to_string(),
+            },
+            &[],
+        )
+        .unwrap();
+    let err = suite
+        .execute(OWNER, submit(None, Some("aggressive")), &[])
+        .unwrap_err();
+    assert!(matches!(err, ContractError::UnknownPreset { .. }));
+}
+
+#[test]
+fn premium_slot_bounds() {
+    let mut suite = Suite::with_msg(|msg| msg.max_premium_slot = Some(20));
+    suite.deposit("alice", 10_000_000).unwrap();
+
+    let submit = |premium_slot: u8| ExecuteMsg::SubmitBid {
+        amount: Uint128::from(1_000_000u128),
+        premium_slot,
+        strategy: None,
+        preset: None,
+    };
+    suite.execute(OWNER, submit(20), &[]).unwrap();
+    let err = suite.execute(OWNER, submit(21), &[]).unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::InvalidPremiumSlot {
+            premium_slot: 21,
+            max_premium_slot: 20
+        }
+    ));
+    let err = suite
+        .execute(
+            OWNER,
+            ExecuteMsg::SubmitBids {
+                allocations: vec![
+                    (5, Uint128::from(1_000_000u128)),
+                    (25, Uint128::from(1_000_000u128)),
+                ],
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::InvalidPremiumSlot {
+            premium_slot: 25,
+            ..
+        }
+    ));
+    assert_eq!(slot_amounts(&suite), vec![(20, 1_000_000)]);
+
+    // The max slot cannot drop below the target allocation
+    set_target_allocation(&mut suite, vec![(5, 3_000), (15, 7_000)]);
+    let update =
+        |max_premium_slot: u8| update_config(|msg| msg.max_premium_slot = Some(max_premium_slot));
+    let err = suite.execute(OWNER, update(10), &[]).unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::InvalidPremiumSlot {
+            premium_slot: 15,
+            max_premium_slot: 10
+        }
+    ));
+    suite.execute(OWNER, update(25), &[]).unwrap();
+    suite.execute(OWNER, submit(25), &[]).unwrap();
+    let config: ConfigResponse = suite.query(QueryMsg::Config {});
+    assert_eq!(config.max_premium_slot, 25);
+}
+
+#[test]
+fn rebalance_to_target_allocation() {
+    let mut suite = Suite::new();
+    suite.deposit("alice", 10_000_000).unwrap();
+    let err = suite
+        .execute(KEEPER, ExecuteMsg::Rebalance {}, &[])
+        .unwrap_err();
+    assert!(matches!(err, ContractError::NoTargetAllocation {}));
+
+    set_target_allocation(&mut suite, vec![(5, 3_000), (10, 7_000)]);
+    suite
+        .execute(KEEPER, ExecuteMsg::Rebalance {}, &[])
+        .unwrap();
+    assert_eq!(slot_amounts(&suite), vec![(5, 3_000_000), (10, 7_000_000)]);
+    assert_eq!(suite.token_balance(A_UST, VAULT), 0);
+    // Nothing to do inside the tolerance band
+    let err = suite
+        .execute(KEEPER, ExecuteMsg::Rebalance {}, &[])
+        .unwrap_err();
+    assert!(matches!(err, ContractError::AlreadyBalanced {}));
+
+    // Under-allocated slots are topped up from idle aUST
+    let idx = suite.bids()[1].idx;
+    suite
+        .execute(
+            OWNER,
+            ExecuteMsg::RetractBid {
+                bid_idx: idx,
+                amount: Some(Uint128::from(2_000_000u128)),
+            },
+            &[],
+        )
+        .unwrap();
+    suite
+        .execute(KEEPER, ExecuteMsg::Rebalance {}, &[])
+        .unwrap();
+    assert_eq!(slot_amounts(&suite), vec![(5, 3_000_000), (10, 7_000_000)]);
+    assert_eq!(suite.bids().len(), 3);
+
+    // Over-allocated slots give up their newest bids first
+    set_target_allocation(&mut suite, vec![(5, 5_000), (10, 5_000)]);
+    suite
+        .execute(KEEPER, ExecuteMsg::Rebalance {}, &[])
+        .unwrap();
+    assert_eq!(slot_amounts(&suite), vec![(5, 5_000_000), (10, 5_000_000)]);
+    assert_eq!(suite.bids().len(), 3);
+    assert_eq!(suite.token_balance(A_UST, VAULT), 0);
+    assert_eq!(total_cap(&suite), 10_000_000);
+}
+
+#[test]
+fn withdraw_b_luna_after_unlock() {
+    let mut suite = Suite::new();
+    suite.deposit("alice", 5_000_000).unwrap();
+    suite.deposit("bob", 5_000_000).unwrap();
+    suite
+        .execute(
+            OWNER,
+            ExecuteMsg::SubmitBid {
+                amount: Uint128::from(10_000_000u128),
+                premium_slot: 3,
+                strategy: None,
+                preset: None,
+            },
+            &[],
+        )
+        .unwrap();
+    let idx = suite.bids()[0].idx;
+    suite.activate_bid(idx);
+    suite.liquidate_bid(idx, 10_000_000, 220_000);
+    suite
+        .execute(KEEPER, ExecuteMsg::ClaimLiquidation {}, &[])
+        .unwrap();
+
+    suite.advance(WITHDRAW_LOCK + 1);
+    let err = suite
+        .execute(
+            "alice",
+            ExecuteMsg::WithdrawBLuna {
+                share: Uint128::from(5_000_000u128),
+                min_amount_out: None,
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::InsufficientUnlockedCollateral { available, requested }
+            if available.is_zero() && requested == Uint128::from(110_000u128)
+    ));
+
+    suite.advance(LOCK_PERIOD);
+    suite.execute(KEEPER, ExecuteMsg::Unlock {}, &[]).unwrap();
+    suite
+        .execute(
+            "alice",
+            ExecuteMsg::WithdrawBLuna {
+                share: Uint128::from(5_000_000u128),
+                min_amount_out: None,
+            },
+            &[],
+        )
+        .unwrap();
+    assert_eq!(suite.token_balance(B_LUNA, "alice"), 110_000);
+    assert_eq!(share_of(&suite, "bob"), 5_000_000);
+    assert_eq!(total_cap(&suite), 5_500_000);
+}
+
+#[test]
+fn deposit_a_ust_and_b_luna() {
+    let mut suite = Suite::new();
+    suite.deposit("alice", 1_000_000).unwrap();
+    suite.set_a_ust_rate("1.25");
+
+    // aUST is valued at the Anchor exchange rate and stays in the vault
+    suite.mint_token(A_UST, "bob", 800_000);
+    suite
+        .send_token(
+            A_UST,
+            "bob",
+            800_000,
+            to_binary(&ReceiveMsg::Deposit {
+                min_shares_out: None,
+            })
+            .unwrap(),
+        )
+        .unwrap();
+    assert_eq!(share_of(&suite, "bob"), 800_000);
+    assert_eq!(suite.token_balance(A_UST, VAULT), 1_800_000);
+    assert_eq!(suite.native_balance(VAULT), 0);
+
+    // bLuna is valued at the oracle price less the discount
+    suite
+        .execute(
+            OWNER,
+            update_config(|msg| msg.b_luna_discount_bps = Some(200)),
+            &[],
+        )
+        .unwrap();
+    suite.mint_token(B_LUNA, "carol", 20_000);
+    suite
+        .send_token(
+            B_LUNA,
+            "carol",
+            20_000,
+            to_binary(&ReceiveMsg::Deposit {
+                min_shares_out: None,
+            })
+            .unwrap(),
+        )
+        .unwrap();
+    // 20_000 * 50 * 0.98 = 980_000 UST at 1.25 UST per share
+    assert_eq!(share_of(&suite, "carol"), 784_000);
+    let nav: Nav = suite.query(QueryMsg::Nav {});
+    assert_eq!(nav.unlocked_b_luna, Uint128::from(20_000u128));
+    assert_eq!(nav.locked_b_luna, Uint128::zero());
+
+    // Deposits start the withdraw lock
+    let err = suite
+        .execute(
+            "carol",
+            ExecuteMsg::WithdrawBLuna {
+                share: Uint128::from(784_000u128),
+                min_amount_out: None,
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert!(matches!(err, ContractError::WithdrawLocked { .. }));
+
+    // Only aUST and bLuna are accepted
+    suite.mint_token(OTHER_TOKEN, "dave", 1_000);
+    let err = suite
+        .send_token(
+            OTHER_TOKEN,
+            "dave",
+            1_000,
+            to_binary(&ReceiveMsg::Deposit {
+                min_shares_out: None,
+            })
+            .unwrap(),
+        )
+        .unwrap_err();
+    assert!(matches!(err, ContractError::UnsupportedToken { .. }));
+}
+
+#[test]
+fn share_transfer_respects_withdraw_lock() {
+    let mut suite = Suite::new();
+    suite.deposit("alice", 1_000_000).unwrap();
+    let transfer = |recipient: &str, amount: u128| ExecuteMsg::Transfer {
+        recipient: recipient.to_string(),
+        amount: Uint128::from(amount),
+    };
+    let withdraw = |share: u128| ExecuteMsg::WithdrawUst {
+        share: Uint128::from(share),
+        min_amount_out: None,
+    };
+
+    // Shares can't leave an account inside its withdraw lock
+    let err = suite
+        .execute("alice", transfer("bob", 400_000), &[])
+        .unwrap_err();
+    assert!(matches!(err, ContractError::WithdrawLocked { .. }));
+    let err = suite
+        .execute(
+            "alice",
+            ExecuteMsg::Send {
+                contract: "bob".to_string(),
+                amount: Uint128::from(400_000u128),
+                msg: to_binary(&0u8).unwrap(),
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert!(matches!(err, ContractError::WithdrawLocked { .. }));
+
+    suite.advance(WITHDRAW_LOCK + 1);
+    suite
+        .execute("alice", transfer("bob", 400_000), &[])
+        .unwrap();
+    assert_eq!(share_of(&suite, "alice"), 600_000);
+    assert_eq!(share_of(&suite, "bob"), 400_000);
+
+    // Dust from a fresh depositor can't push the recipient's lock out
+    suite.deposit("mallory", 1_000).unwrap();
+    let err = suite
+        .execute("mallory", transfer("bob", 1), &[])
+        .unwrap_err();
+    assert!(matches!(err, ContractError::WithdrawLocked { .. }));
+    suite.advance(WITHDRAW_LOCK + 1);
+    suite.deposit("alice", 1_000).unwrap();
+    suite.execute("mallory", transfer("bob", 1), &[]).unwrap();
+    suite.execute("bob", withdraw(400_001), &[]).unwrap();
+    assert_eq!(suite.native_balance("bob"), 400_001);
+    let err = suite.execute("alice", withdraw(600_000), &[]).unwrap_err();
+    assert!(matches!(err, ContractError::WithdrawLocked { .. }));
+}
+
+#[test]
+fn oracle_price_checks() {
+    let mut suite = Suite::with_msg(|msg| msg.max_price_age = Some(600));
+    suite.deposit("alice", 1_000_000).unwrap();
+    suite.advance(WITHDRAW_LOCK + 1);
+
+    // The oracle stops updating
+    suite.advance_without_price(601);
+    let err = suite.deposit("bob", 1_000_000).unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::StalePrice { max_age: 600, .. }
+    ));
+    let err = suite
+        .execute(
+            "alice",
+            ExecuteMsg::WithdrawUst {
+                share: Uint128::from(1_000_000u128),
+                min_amount_out: None,
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert!(matches!(err, ContractError::StalePrice { .. }));
+
+    suite.advance(1);
+    suite
+        .execute(
+            OWNER,
+            update_config(|msg| {
+                msg.min_b_luna_price = Some(Decimal256::from_str("10").unwrap());
+                msg.max_b_luna_price = Some(Decimal256::from_str("100").unwrap());
+            }),
+            &[],
+        )
+        .unwrap();
+    let config: ConfigResponse = suite.query(QueryMsg::Config {});
+    assert_eq!(config.max_price_age, 600);
+    assert_eq!(
+        config.min_b_luna_price,
+        Some(Decimal256::from_str("10").unwrap())
+    );
+
+    suite.set_b_luna_price("5");
+    let err = suite.deposit("bob", 1_000_000).unwrap_err();
+    assert!(matches!(err, ContractError::PriceOutOfBounds { .. }));
+    suite.set_b_luna_price("150");
+    let err = suite
+        .execute(
+            "alice",
+            ExecuteMsg::WithdrawBLuna {
+                share: Uint128::from(1_000_000u128),
+                min_amount_out: None,
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert!(matches!(err, ContractError::PriceOutOfBounds { .. }));
+
+    suite.set_b_luna_price("50");
+    suite.deposit("bob", 1_000_000).unwrap();
+}
+
+#[test]
+fn pause_flags_freeze_operations() {
+    let mut suite = Suite::with_msg(|msg| msg.owner = Addr::unchecked("admin"));
+    suite.deposit("bob", 1_000_000).unwrap();
+    suite.advance(WITHDRAW_LOCK + 1);
+    suite
+        .execute("admin", update_paused(PAUSE_DEPOSIT), &[])
+        .unwrap();
+    let status: PauseFlags = suite.query(QueryMsg::PauseStatus {});
+    assert_eq!(status, PAUSE_DEPOSIT);
+    let err = suite.deposit("alice", 1_000_000).unwrap_err();
+    assert!(matches!(err, ContractError::Paused { operation } if operation == "deposit"));
+
+    // Other flows keep working until their own flag is set
+    let withdraw = ExecuteMsg::WithdrawUst {
+        share: Uint128::from(100_000u128),
+        min_amount_out: None,
+    };
+    suite.execute("bob", withdraw.clone(), &[]).unwrap();
+    suite
+        .execute(
+            "admin",
+            update_paused(PauseFlags {
+                withdraw_ust: true,
+                submit_bid: true,
+                swap: true,
+                ..PAUSE_DEPOSIT
+            }),
+            &[],
+        )
+        .unwrap();
+    let err = suite.execute("bob", withdraw, &[]).unwrap_err();
+    assert!(matches!(err, ContractError::Paused { operation } if operation == "withdraw_ust"));
+    let err = suite
+        .execute(
+            "bob",
+            ExecuteMsg::RequestWithdrawal {
+                share: Uint128::from(100_000u128),
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert!(matches!(err, ContractError::Paused { operation } if operation == "withdraw_ust"));
+    let err = suite
+        .execute(
+            "admin",
+            ExecuteMsg::SubmitBid {
+                amount: Uint128::from(100_000u128),
+                premium_slot: 5,
+                strategy: None,
+                preset: None,
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert!(matches!(err, ContractError::Paused { operation } if operation == "submit_bid"));
+    let retract = ExecuteMsg::RetractBid {
+        bid_idx: 1,
+        amount: None,
+    };
+    let err = suite.execute("admin", retract, &[]).unwrap_err();
+    assert!(matches!(err, ContractError::Paused { operation } if operation == "submit_bid"));
+    let err = suite.execute(KEEPER, swap_all(), &[]).unwrap_err();
+    assert!(matches!(err, ContractError::Paused { operation } if operation == "swap"));
+
+    let res = suite
+        .execute("admin", update_paused(PauseFlags::default()), &[])
+        .unwrap();
+    assert!(attributes(&res, VAULT).contains(&cosmwasm_std::attr("paused", "none")));
+    suite.deposit("alice", 1_000_000).unwrap();
+}
+
+fn update_paused(paused: PauseFlags) -> ExecuteMsg {
+    update_config(|msg| msg.paused = Some(paused))
+}
+
+#[test]
+fn role_based_access() {
+    let mut suite = Suite::new();
+    let grant = |address: &str, role: Role| ExecuteMsg::GrantRole {
+        address: Addr::unchecked(address),
+        role,
+    };
+
+    // Keepers run the liquidation chores
+    let err = suite.execute("alice", swap_all(), &[]).unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::MissingRole {
+            role: Role::Keeper,
+            ..
+        }
+    ));
+    let err = suite.execute(KEEPER, swap_all(), &[]).unwrap_err();
+    assert!(matches!(err, ContractError::NothingToSwap {}));
+
+    // Only admins grant roles
+    let err = suite
+        .execute("alice", grant("alice", Role::Bidder), &[])
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::MissingRole {
+            role: Role::Admin,
+            ..
+        }
+    ));
+    let res = suite
+        .execute(OWNER, grant("alice", Role::Bidder), &[])
+        .unwrap();
+    let event = res
+        .events
+        .iter()
+        .find(|event| event.ty == "wasm-grant_role")
+        .unwrap();
+    assert_eq!(event.attributes[1].value, "alice");
+    assert_eq!(event.attributes[2].value, "bidder");
+    let err = suite
+        .execute(OWNER, grant("alice", Role::Bidder), &[])
+        .unwrap_err();
+    assert!(matches!(err, ContractError::PermissionUnchanged {}));
+
+    suite.deposit("bob", 1_000_000).unwrap();
+    suite
+        .execute(
+            "alice",
+            ExecuteMsg::SubmitBid {
+                amount: Uint128::from(1_000_000u128),
+                premium_slot: 5,
+                strategy: None,
+                preset: None,
+            },
+            &[],
+        )
+        .unwrap();
+
+    // Pausing and config changes are separate roles
+    suite
+        .execute(OWNER, grant("pauser", Role::Pauser), &[])
+        .unwrap();
+    let pause_and_tune = update_config(|msg| {
+        msg.paused = Some(PAUSE_DEPOSIT);
+        msg.rebalance_tolerance_bps = Some(50);
+    });
+    let err = suite.execute("pauser", pause_and_tune, &[]).unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::MissingRole {
+            role: Role::ConfigManager,
+            ..
+        }
+    ));
+    suite
+        .execute("pauser", update_paused(PAUSE_DEPOSIT), &[])
+        .unwrap();
+    let config: ConfigResponse = suite.query(QueryMsg::Config {});
+    assert!(config.paused.deposit);
+    suite
+        .execute(OWNER, grant("manager", Role::ConfigManager), &[])
+        .unwrap();
+    let err = suite
+        .execute("manager", update_paused(PauseFlags::default()), &[])
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::MissingRole {
+            role: Role::Pauser,
+            ..
+        }
+    ));
+    suite
+        .execute(
+            "manager",
+            ExecuteMsg::SetStrategyPreset {
+                name: "aggressive".to_string(),
+                strategy: strategy(95, 90),
+            },
+            &[],
+        )
+        .unwrap();
+
+    let res: PermissionsResponse = suite.query(QueryMsg::ListPermissions {
+        start_after: None,
+        limit: Some(2),
+    });
+    assert_eq!(res.permissions.len(), 2);
+    let next: PermissionsResponse = suite.query(QueryMsg::ListPermissions {
+        start_after: Some(res.permissions[1].address.clone()),
+        limit: None,
+    });
+    let mut permissions = res
+        .permissions
+        .into_iter()
+        .chain(next.permissions)
+        .map(|permission| (permission.address, permission.roles))
+        .collect::<Vec<_>>();
+    permissions.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(
+        permissions,
+        vec![
+            ("alice".to_string(), vec![Role::Bidder]),
+            (KEEPER.to_string(), vec![Role::Keeper]),
+            ("manager".to_string(), vec![Role::ConfigManager]),
+            (OWNER.to_string(), vec![Role::Admin]),
+            ("pauser".to_string(), vec![Role::Pauser]),
+        ]
+    );
+
+    let res = suite
+        .execute(
+            OWNER,
+            ExecuteMsg::RevokeRole {
+                address: Addr::unchecked("alice"),
+                role: Role::Bidder,
+            },
+            &[],
+        )
+        .unwrap();
+    assert!(res
+        .events
+        .iter()
+        .any(|event| event.ty == "wasm-revoke_role"));
+    let res: PermissionResponse = suite.query(QueryMsg::Permission {
+        address: "alice".to_string(),
+    });
+    assert!(res.permission.roles.is_empty());
+    let err = suite
+        .execute(
+            OWNER,
+            ExecuteMsg::RevokeRole {
+                address: Addr::unchecked(OWNER),
+                role: Role::Admin,
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert!(matches!(err, ContractError::OwnerAdminRequired {}));
+}
+
+#[test]
+fn two_step_ownership_transfer() {
+    let mut suite = Suite::new();
+    let propose = |suite: &Suite, new_owner: &str, seconds: u64| ExecuteMsg::ProposeOwner {
+        new_owner: Addr::unchecked(new_owner),
+        expiry: Some(Expiration::AtTime(
+            suite.env().block.time.plus_seconds(seconds),
+        )),
+    };
+
+    // Even admins cannot transfer ownership
+    suite
+        .execute(
+            OWNER,
+            ExecuteMsg::GrantRole {
+                address: Addr::unchecked("alice"),
+                role: Role::Admin,
+            },
+            &[],
+        )
+        .unwrap();
+    let msg = propose(&suite, "alice", 100);
+    let err = suite.execute("alice", msg, &[]).unwrap_err();
+    assert!(matches!(err, ContractError::OwnerRequired { address } if address == "alice"));
+    let err = suite
+        .execute("alice", ExecuteMsg::CancelOwnershipProposal {}, &[])
+        .unwrap_err();
+    assert!(matches!(err, ContractError::OwnerRequired { .. }));
+    let err = suite
+        .execute("alice", ExecuteMsg::AcceptOwnership {}, &[])
+        .unwrap_err();
+    assert!(matches!(err, ContractError::NoPendingOwner {}));
+    let msg = propose(&suite, OWNER, 100);
+    let err = suite.execute(OWNER, msg, &[]).unwrap_err();
+    assert!(matches!(err, ContractError::OwnerUnchanged {}));
+
+    // A typo can be cancelled before anyone accepts it
+    let msg = propose(&suite, "alcie", 100);
+    suite.execute(OWNER, msg, &[]).unwrap();
+    let res: PendingOwnerResponse = suite.query(QueryMsg::PendingOwner {});
+    assert_eq!(res.new_owner, Some("alcie".to_string()));
+    suite
+        .execute(OWNER, ExecuteMsg::CancelOwnershipProposal {}, &[])
+        .unwrap();
+    let res: PendingOwnerResponse = suite.query(QueryMsg::PendingOwner {});
+    assert_eq!(res.new_owner, None);
+
+    // Proposals expire
+    let msg = propose(&suite, "alice", 100);
+    suite.execute(OWNER, msg, &[]).unwrap();
+    suite.advance(101);
+    let err = suite
+        .execute("alice", ExecuteMsg::AcceptOwnership {}, &[])
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::OwnershipProposalExpired { .. }
+    ));
+
+    let msg = propose(&suite, "alice", 100);
+    suite.execute(OWNER, msg, &[]).unwrap();
+    let err = suite
+        .execute("bob", ExecuteMsg::AcceptOwnership {}, &[])
+        .unwrap_err();
+    assert!(matches!(err, ContractError::NotPendingOwner { address } if address == "bob"));
+    suite
+        .execute("alice", ExecuteMsg::AcceptOwnership {}, &[])
+        .unwrap();
+    let config: ConfigResponse = suite.query(QueryMsg::Config {});
+    assert_eq!(config.owner, "alice");
+    let res: PendingOwnerResponse = suite.query(QueryMsg::PendingOwner {});
+    assert_eq!(res.new_owner, None);
+
+    // The admin role moved with the ownership
+    for (address, roles) in [("alice", vec![Role::Admin]), (OWNER, vec![])] {
+        let res: PermissionResponse = suite.query(QueryMsg::Permission {
+            address: address.to_string(),
+        });
+        assert_eq!(res.permission.roles, roles);
+    }
+}
+
+#[test]
+fn timelocked_config_change() {
+    let mut suite = Suite::with_msg(|msg| msg.timelock_delay = Some(DAY));
+    let queue = |swap_wallet: &str| ExecuteMsg::QueueConfigChange {
+        swap_wallet: Some(Addr::unchecked(swap_wallet)),
+        lock_period: None,
+        withdraw_lock: Some(2 * WITHDRAW_LOCK),
+        bid_strategy: None,
+        timelock_delay: None,
+        swap_route: None,
+        astroport_pair: None,
+    };
+
+    let err = suite.execute("alice", queue("alice"), &[]).unwrap_err();
+    assert!(matches!(err, ContractError::MissingRole { .. }));
+    let err = suite
+        .execute(
+            OWNER,
+            ExecuteMsg::QueueConfigChange {
+                swap_wallet: None,
+                lock_period: None,
+                withdraw_lock: None,
+                bid_strategy: None,
+                timelock_delay: None,
+                swap_route: None,
+                astroport_pair: None,
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert!(matches!(err, ContractError::NoConfigChange {}));
+
+    // Depositors see the change coming
+    suite.execute(OWNER, queue("mallory"), &[]).unwrap();
+    let res: PendingConfigChangeResponse = suite.query(QueryMsg::PendingConfigChange {});
+    let pending = res.pending.unwrap();
+    assert_eq!(pending.swap_wallet, Some(Addr::unchecked("mallory")));
+    assert_eq!(
+        pending.executable_at,
+        suite.env().block.time.plus_seconds(DAY)
+    );
+    suite
+        .execute(OWNER, ExecuteMsg::CancelConfigChange {}, &[])
+        .unwrap();
+    let err = suite
+        .execute(OWNER, ExecuteMsg::ExecuteConfigChange {}, &[])
+        .unwrap_err();
+    assert!(matches!(err, ContractError::NoPendingConfigChange {}));
+
+    suite.execute(OWNER, queue("new_wallet"), &[]).unwrap();
+    suite.advance(DAY - 1);
+    let err = suite
+        .execute(OWNER, ExecuteMsg::ExecuteConfigChange {}, &[])
+        .unwrap_err();
+    assert!(matches!(err, ContractError::TimelockNotExpired { .. }));
+    suite.advance(1);
+    suite
+        .execute(OWNER, ExecuteMsg::ExecuteConfigChange {}, &[])
+        .unwrap();
+    let config: ConfigResponse = suite.query(QueryMsg::Config {});
+    assert_eq!(config.swap_wallet, "new_wallet");
+    assert_eq!(config.withdraw_lock, 2 * WITHDRAW_LOCK);
+    let res: PendingConfigChangeResponse = suite.query(QueryMsg::PendingConfigChange {});
+    assert_eq!(res.pending, None);
+
+    // Pausing stays instant
+    suite
+        .execute(OWNER, update_paused(PAUSE_DEPOSIT), &[])
+        .unwrap();
+    let config: ConfigResponse = suite.query(QueryMsg::Config {});
+    assert!(config.paused.deposit);
+}
+
+#[test]
+fn emergency_shutdown_redeems_pro_rata_basket() {
+    let mut suite = Suite::new();
+    suite.deposit("alice", 10_000_000).unwrap();
+    suite.deposit("bob", 10_000_000).unwrap();
+    for (amount, premium_slot) in [(8_000_000u128, 5), (4_000_000u128, 6), (2_000_000u128, 7)] {
+        suite
+            .execute(
+                OWNER,
+                ExecuteMsg::SubmitBid {
+                    amount: Uint128::from(amount),
+                    premium_slot,
+                    strategy: None,
+                    preset: None,
+                },
+                &[],
+            )
+            .unwrap();
+    }
+    // 154_000 bLuna claimed and locked, 22_000 still pending in Anchor
+    let idx = suite.bids()[0].idx;
+    suite.activate_bid(idx);
+    suite.liquidate_bid(idx, 5_000_000, 110_000);
+    // The third bid is fully consumed and has nothing left to retract
+    let consumed = suite.bids()[2].idx;
+    suite.activate_bid(consumed);
+    suite.liquidate_bid(consumed, 2_000_000, 44_000);
+    suite
+        .execute(KEEPER, ExecuteMsg::ClaimLiquidation {}, &[])
+        .unwrap();
+    suite.liquidate_bid(idx, 1_000_000, 22_000);
+    suite.advance(WITHDRAW_LOCK + 1);
+    suite
+        .execute(
+            "bob",
+            ExecuteMsg::RequestWithdrawal {
+                share: Uint128::from(2_000_000u128),
+            },
+            &[],
+        )
+        .unwrap();
+
+    let err = suite
+        .execute("alice", ExecuteMsg::EmergencyShutdown {}, &[])
+        .unwrap_err();
+    assert!(matches!(err, ContractError::OwnerRequired { .. }));
+    let retract = |limit: u32| ExecuteMsg::RetractShutdownBids { limit: Some(limit) };
+    let err = suite.execute(KEEPER, retract(2), &[]).unwrap_err();
+    assert!(matches!(err, ContractError::NotShutDown {}));
+    let err = suite
+        .execute(KEEPER, ExecuteMsg::FinalizeShutdown {}, &[])
+        .unwrap_err();
+    assert!(matches!(err, ContractError::NotShutDown {}));
+    let redeem = |share: u128| ExecuteMsg::RedeemShutdownShare {
+        share: Uint128::from(share),
+    };
+    let err = suite.execute("alice", redeem(1), &[]).unwrap_err();
+    assert!(matches!(err, ContractError::NotShutDown {}));
+
+    suite
+        .execute(OWNER, ExecuteMsg::EmergencyShutdown {}, &[])
+        .unwrap();
+    assert_eq!(suite.bids().len(), 3);
+    let err = suite
+        .execute(KEEPER, ExecuteMsg::FinalizeShutdown {}, &[])
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::ShutdownBidsRemaining { cursor: 0 }
+    ));
+
+    // Anyone winds the bids down in batches, the last one freezes the basket
+    let res = suite.execute(KEEPER, retract(2), &[]).unwrap();
+    assert!(attributes(&res, VAULT).contains(&cosmwasm_std::attr("done", "false")));
+    assert_eq!(suite.bids().len(), 1);
+    let res: ShutdownBasketResponse = suite.query(QueryMsg::ShutdownBasket {});
+    assert_eq!(res.basket, None);
+    let err = suite
+        .execute(KEEPER, ExecuteMsg::FinalizeShutdown {}, &[])
+        .unwrap_err();
+    assert!(matches!(err, ContractError::ShutdownBidsRemaining { .. }));
+    let res = suite.execute(KEEPER, retract(2), &[]).unwrap();
+    assert!(attributes(&res, VAULT).contains(&cosmwasm_std::attr("done", "true")));
+    let bids = suite.bids();
+    assert_eq!(bids.len(), 1);
+    assert_eq!(bids[0].idx, consumed);
+    let err = suite.execute(KEEPER, retract(2), &[]).unwrap_err();
+    assert!(matches!(err, ContractError::ShutDown {}));
+    let res: ShutdownBasketResponse = suite.query(QueryMsg::ShutdownBasket {});
+    assert_eq!(
+        res.basket,
+        Some(ShutdownBasket {
+            ust: Uint128::zero(),
+            a_ust: Uint128::from(12_000_000u128),
+            b_luna: Uint128::from(176_000u128),
+        })
+    );
+    assert_eq!(res.total_share, Uint128::from(20_000_000u128));
+    // The unfilled request is released back to bob
+    assert_eq!(share_of(&suite, "bob"), 10_000_000);
+    let res: WithdrawalRequestsResponse = suite.query(QueryMsg::WithdrawalRequests {
+        start_after: None,
+        limit: None,
+    });
+    assert!(res.requests.is_empty());
+    let config: ConfigResponse = suite.query(QueryMsg::Config {});
+    assert!(config.shutdown);
+
+    let err = suite.deposit("carol", 1_000_000).unwrap_err();
+    assert!(matches!(err, ContractError::ShutDown {}));
+    let err = suite
+        .execute(OWNER, ExecuteMsg::EmergencyShutdown {}, &[])
+        .unwrap_err();
+    assert!(matches!(err, ContractError::ShutDown {}));
+    let err = suite.execute(KEEPER, swap_all(), &[]).unwrap_err();
+    assert!(matches!(err, ContractError::ShutDown {}));
+
+    // Locked bLuna is paid out right away, without any oracle price
+    suite.set_b_luna_price("0");
+    suite.execute("alice", redeem(10_000_000), &[]).unwrap();
+    assert_eq!(suite.token_balance(A_UST, "alice"), 6_000_000);
+    assert_eq!(suite.token_balance(B_LUNA, "alice"), 88_000);
+    suite.execute("bob", redeem(5_000_000), &[]).unwrap();
+    assert_eq!(suite.token_balance(A_UST, "bob"), 3_000_000);
+    assert_eq!(suite.token_balance(B_LUNA, "bob"), 44_000);
+    let res: ShutdownBasketResponse = suite.query(QueryMsg::ShutdownBasket {});
+    assert_eq!(res.basket.unwrap().a_ust, Uint128::from(3_000_000u128));
+    assert_eq!(res.total_share, Uint128::from(5_000_000u128));
+}
+
+// 110_000 bLuna liquidated, claimed and unlocked
+#[test]
+fn emergency_shutdown_lifts_pauses() {
+    let mut suite = Suite::new();
+    suite.deposit("alice", 1_000_000).unwrap();
+    suite.advance(WITHDRAW_LOCK + 1);
+    suite
+        .execute(
+            "alice",
+            ExecuteMsg::RequestWithdrawal {
+                share: Uint128::from(1_000_000u128),
+            },
+            &[],
+        )
+        .unwrap();
+    suite.deposit("bob", 1_000_000).unwrap();
+    suite
+        .execute(KEEPER, ExecuteMsg::ProcessWithdrawals { limit: None }, &[])
+        .unwrap();
+    let res: WithdrawalRequestsResponse = suite.query(QueryMsg::WithdrawalRequests {
+        start_after: None,
+        limit: None,
+    });
+    let id = res.requests[0].id;
+    assert!(res.requests[0].filled);
+
+    let paused = PauseFlags {
+        withdraw_ust: true,
+        ..PauseFlags::default()
+    };
+    suite.execute(OWNER, update_paused(paused), &[]).unwrap();
+    let err = suite
+        .execute("alice", ExecuteMsg::ClaimWithdrawal { id }, &[])
+        .unwrap_err();
+    assert!(matches!(err, ContractError::Paused { .. }));
+
+    // The pause can't be lifted after shutdown, so shutdown lifts it
+    suite
+        .execute(OWNER, ExecuteMsg::EmergencyShutdown {}, &[])
+        .unwrap();
+    let status: PauseFlags = suite.query(QueryMsg::PauseStatus {});
+    assert_eq!(status, PauseFlags::default());
+    suite
+        .execute("alice", ExecuteMsg::ClaimWithdrawal { id }, &[])
+        .unwrap();
+    assert_eq!(suite.native_balance("alice"), 1_000_000);
+
+    // With no bids and no requests left to release the basket still freezes
+    suite
+        .execute(KEEPER, ExecuteMsg::RetractShutdownBids { limit: None }, &[])
+        .unwrap();
+    let res: ShutdownBasketResponse = suite.query(QueryMsg::ShutdownBasket {});
+    assert!(res.basket.is_some());
+}
+
+fn suite_with_unlocked_b_luna() -> Suite {
+    let mut suite = Suite::new();
+    suite.deposit("alice", 10_000_000).unwrap();
+    suite
+        .execute(
+            OWNER,
+            ExecuteMsg::SubmitBid {
+                amount: Uint128::from(8_000_000u128),
+                premium_slot: 5,
+                strategy: None,
+                preset: None,
+            },
+            &[],
+        )
+        .unwrap();
+    let idx = suite.bids()[0].idx;
+    suite.activate_bid(idx);
+    suite.liquidate_bid(idx, 5_000_000, 110_000);
+    suite
+        .execute(KEEPER, ExecuteMsg::ClaimLiquidation {}, &[])
+        .unwrap();
+    suite.advance(LOCK_PERIOD);
+    suite.execute(KEEPER, ExecuteMsg::Unlock {}, &[]).unwrap();
+    suite
+}
+
+#[test]
+fn swap_minimum_follows_oracle_price() {
+    let mut suite = suite_with_unlocked_b_luna();
+
+    let swap = |amount: Option<u128>, minimum_receive: Option<u128>| ExecuteMsg::Swap {
+        amount: amount.map(Uint128::from),
+        minimum_receive: minimum_receive.map(Uint128::from),
+        venue: None,
+    };
+    let err = suite.execute(KEEPER, swap(Some(0), None), &[]).unwrap_err();
+    assert!(matches!(err, ContractError::ZeroAmount {}));
+    let err = suite
+        .execute(KEEPER, swap(Some(110_001), None), &[])
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::InsufficientUnlockedCollateral { available, .. }
+            if available == Uint128::from(110_000u128)
+    ));
+
+    // The default 1% tolerance asks at least 49.5 uusd per bLuna
+    suite.set_router_rate("49");
+    let err = suite.execute(KEEPER, swap_all(), &[]).unwrap_err();
+    assert!(matches!(err, ContractError::Std(_)));
+    suite.set_router_rate("49.6");
+    let res = suite
+        .execute(KEEPER, swap(Some(50_000), None), &[])
+        .unwrap();
+    assert!(attributes(&res, VAULT).contains(&cosmwasm_std::attr("minimum_receive", "2475000")));
+    assert_eq!(suite.native_balance(VAULT), 2_480_000);
+    // Callers can only ask for more
+    let err = suite
+        .execute(KEEPER, swap(Some(10_000), Some(496_001)), &[])
+        .unwrap_err();
+    assert!(matches!(err, ContractError::Std(_)));
+    suite
+        .execute(KEEPER, swap(Some(10_000), Some(1)), &[])
+        .unwrap();
+    assert_eq!(suite.native_balance(VAULT), 2_976_000);
+
+    let update = |swap_tolerance_bps: u16| {
+        update_config(|msg| msg.swap_tolerance_bps = Some(swap_tolerance_bps))
+    };
+    let err = suite.execute(OWNER, update(1_001), &[]).unwrap_err();
+    assert!(matches!(err, ContractError::BpsAboveMax { max: 1_000, .. }));
+    suite.execute(OWNER, update(300), &[]).unwrap();
+    let config: ConfigResponse = suite.query(QueryMsg::Config {});
+    assert_eq!(config.swap_tolerance_bps, 300);
+    suite.set_router_rate("48.6");
+    suite.execute(KEEPER, swap_all(), &[]).unwrap();
+    assert_eq!(suite.token_balance(B_LUNA, VAULT), 0);
+    assert_eq!(suite.native_balance(VAULT), 2_976_000 + 2_430_000);
+}
+
+#[test]
+fn swap_routes_and_venues() {
+    let mut suite = suite_with_unlocked_b_luna();
+    let b_luna = Token {
+        contract_addr: Addr::unchecked(B_LUNA),
+    };
+    let uusd = NativeToken {
+        denom: "uusd".to_string(),
+    };
+    let uluna = NativeToken {
+        denom: "uluna".to_string(),
+    };
+    let queue = |swap_route: Vec<SwapOperation>| ExecuteMsg::QueueConfigChange {
+        swap_wallet: None,
+        lock_period: None,
+        withdraw_lock: None,
+        bid_strategy: None,
+        timelock_delay: None,
+        swap_route: Some(swap_route),
+        astroport_pair: Some(Addr::unchecked(PAIR)),
+    };
+    let astro_swap = |offer: &AssetInfo, ask: &AssetInfo| SwapOperation::AstroSwap {
+        offer_asset_info: offer.clone(),
+        ask_asset_info: ask.clone(),
+    };
+    for route in [
+        vec![],
+        vec![astro_swap(&uluna, &uusd)],
+        vec![astro_swap(&b_luna, &uluna)],
+        vec![astro_swap(&b_luna, &uluna), astro_swap(&b_luna, &uusd)],
+    ] {
+        let err = suite.execute(OWNER, queue(route), &[]).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidSwapRoute { .. }));
+    }
+
+    // The default route goes through uluna
+    let config: ConfigResponse = suite.query(QueryMsg::Config {});
+    assert_eq!(config.swap_route.len(), 2);
+    assert_eq!(config.astroport_pair, None);
+    let simulate = |venue: Option<SwapVenue>| QueryMsg::SimulateSwap {
+        amount: None,
+        venue,
+    };
+    let res: SimulateSwapResponse = suite.query(simulate(None));
+    assert_eq!(res.amount, Uint128::from(110_000u128));
+    assert_eq!(res.return_amount, Uint128::from(5_500_000u128));
+    assert_eq!(res.minimum_receive, Uint128::from(5_445_000u128));
+    let swap = |amount: u128, venue: SwapVenue| ExecuteMsg::Swap {
+        amount: Some(Uint128::from(amount)),
+        minimum_receive: None,
+        venue: Some(venue),
+    };
+    let err = suite
+        .execute(KEEPER, swap(50_000, SwapVenue::AstroportPair), &[])
+        .unwrap_err();
+    assert!(matches!(err, ContractError::NoAstroportPair {}));
+
+    suite
+        .execute(OWNER, queue(vec![astro_swap(&b_luna, &uusd)]), &[])
+        .unwrap();
+    suite.advance(2 * DAY);
+    suite
+        .execute(OWNER, ExecuteMsg::ExecuteConfigChange {}, &[])
+        .unwrap();
+    let config: ConfigResponse = suite.query(QueryMsg::Config {});
+    assert_eq!(config.swap_route, vec![astro_swap(&b_luna, &uusd)]);
+    assert_eq!(config.astroport_pair, Some(PAIR.to_string()));
+
+    // The pair enforces the oracle minimum through the belief price
+    suite.set_pair_rate("49.6");
+    let res: SimulateSwapResponse = suite.query(simulate(Some(SwapVenue::AstroportPair)));
+    assert_eq!(res.return_amount, Uint128::from(5_456_000u128));
+    suite
+        .execute(KEEPER, swap(50_000, SwapVenue::AstroportPair), &[])
+        .unwrap();
+    assert_eq!(suite.native_balance(VAULT), 2_480_000);
+    suite.set_pair_rate("49");
+    let err = suite
+        .execute(KEEPER, swap(10_000, SwapVenue::AstroportPair), &[])
+        .unwrap_err();
+    assert!(matches!(err, ContractError::Std(_)));
+    suite
+        .execute(KEEPER, swap(60_000, SwapVenue::Router), &[])
+        .unwrap();
+    assert_eq!(suite.token_balance(B_LUNA, VAULT), 0);
+    assert_eq!(suite.native_balance(VAULT), 2_480_000 + 3_000_000);
+}
+
+#[test]
+fn swaps_are_capped_and_spaced() {
+    let mut suite = suite_with_unlocked_b_luna();
+    let update = |max_swap_amount: u128, swap_cooldown: u64| {
+        update_config(|msg| {
+            msg.max_swap_amount = Some(Uint128::from(max_swap_amount));
+            msg.swap_cooldown = Some(swap_cooldown);
+        })
+    };
+    suite.execute(OWNER, update(40_000, 600), &[]).unwrap();
+    let config: ConfigResponse = suite.query(QueryMsg::Config {});
+    assert_eq!(config.max_swap_amount, Some(Uint128::from(40_000u128)));
+    assert_eq!(config.swap_cooldown, 600);
+    let now = suite.env().block.time;
+    let res: NextSwapResponse = suite.query(QueryMsg::NextSwap {});
+    assert!(res.next_swap_at <= now);
+    assert_eq!(res.max_amount, Uint128::from(40_000u128));
+
+    let err = suite
+        .execute(
+            KEEPER,
+            ExecuteMsg::Swap {
+                amount: Some(Uint128::from(50_000u128)),
+                minimum_receive: None,
+                venue: None,
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert!(matches!(err, ContractError::SwapAmountExceedsMax { .. }));
+
+    // Unwind 110_000 bLuna in capped chunks, one per cooldown
+    suite.execute(KEEPER, swap_all(), &[]).unwrap();
+    assert_eq!(suite.token_balance(B_LUNA, VAULT), 70_000);
+    let err = suite.execute(KEEPER, swap_all(), &[]).unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::SwapCooldown { next_swap_at } if next_swap_at == now.plus_seconds(600)
+    ));
+    let res: NextSwapResponse = suite.query(QueryMsg::NextSwap {});
+    assert_eq!(res.next_swap_at, now.plus_seconds(600));
+    suite.advance(600);
+    suite.execute(KEEPER, swap_all(), &[]).unwrap();
+    suite.advance(600);
+    let res: NextSwapResponse = suite.query(QueryMsg::NextSwap {});
+    assert_eq!(res.max_amount, Uint128::from(30_000u128));
+    suite.execute(KEEPER, swap_all(), &[]).unwrap();
+    assert_eq!(suite.token_balance(B_LUNA, VAULT), 0);
+    assert_eq!(suite.native_balance(VAULT), 5_500_000);
+
+    // Zero removes the cap
+    suite.execute(OWNER, update(0, 0), &[]).unwrap();
+    let config: ConfigResponse = suite.query(QueryMsg::Config {});
+    assert_eq!(config.max_swap_amount, None);
+}
+
+#[test]
+fn settlements_reconcile_received_amounts() {
+    let mut suite = suite_with_unlocked_b_luna();
+    let stats: SettlementStats = suite.query(QueryMsg::SettlementStats {});
+    assert_eq!(stats.claims, 1);
+    assert_eq!(stats.claimed_b_luna, Uint128::from(110_000u128));
+
+    // A venue paying out less than its own check let through is reverted by the reply
+    suite.set_swap_tax("0.02");
+    let err = suite.execute(KEEPER, swap_all(), &[]).unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::SlippageExceeded { minimum, actual }
+            if minimum == Uint128::from(5_445_000u128) && actual == Uint128::from(5_390_000u128)
+    ));
+    assert_eq!(suite.token_balance(B_LUNA, VAULT), 110_000);
+    assert_eq!(suite.native_balance(VAULT), 0);
+    let stats: SettlementStats = suite.query(QueryMsg::SettlementStats {});
+    assert_eq!(stats.swaps, 0);
+
+    suite.set_swap_tax("0.005");
+    suite.execute(KEEPER, swap_all(), &[]).unwrap();
+    let stats: SettlementStats = suite.query(QueryMsg::SettlementStats {});
+    assert_eq!(stats.swaps, 1);
+    assert_eq!(stats.swapped_b_luna, Uint128::from(110_000u128));
+    assert_eq!(stats.swap_proceeds, Uint128::from(5_472_500u128));
+
+    suite.advance(WITHDRAW_LOCK + 1);
+    let share = share_of(&suite, "alice");
+    suite
+        .execute(
+            "alice",
+            ExecuteMsg::WithdrawUst {
+                share: Uint128::from(share),
+                min_amount_out: None,
+            },
+            &[],
+        )
+        .unwrap();
+    let stats: SettlementStats = suite.query(QueryMsg::SettlementStats {});
+    assert_eq!(stats.redemptions, 1);
+    assert_eq!(stats.redeemed_a_ust, Uint128::from(5_000_000u128));
+    assert_eq!(stats.redemption_proceeds, Uint128::from(5_000_000u128));
+    assert_eq!(suite.native_balance("alice"), 10_472_500);
+}
+
+#[test]
+fn claims_lock_received_b_luna() {
+    let mut suite = Suite::new();
+    suite.deposit("alice", 10_000_000).unwrap();
+    suite
+        .execute(
+            OWNER,
+            ExecuteMsg::SubmitBid {
+                amount: Uint128::from(8_000_000u128),
+                premium_slot: 5,
+                strategy: None,
+                preset: None,
+            },
+            &[],
+        )
+        .unwrap();
+    let idx = suite.bids()[0].idx;
+    suite.activate_bid(idx);
+    suite.liquidate_bid(idx, 5_000_000, 110_000);
+
+    // Only the bLuna that arrives is locked
+    suite.set_claim_fee("0.01");
+    let res = suite
+        .execute(KEEPER, ExecuteMsg::ClaimLiquidation {}, &[])
+        .unwrap();
+    assert!(attributes(&res, VAULT).contains(&cosmwasm_std::attr("amount", "110000")));
+    assert_eq!(suite.token_balance(B_LUNA, VAULT), 108_900);
+    let nav: Nav = suite.query(QueryMsg::Nav {});
+    assert_eq!(nav.locked_b_luna, Uint128::from(108_900u128));
+    assert_eq!(nav.unlocked_b_luna, Uint128::zero());
+    let stats: SettlementStats = suite.query(QueryMsg::SettlementStats {});
+    assert_eq!(stats.claimed_b_luna, Uint128::from(108_900u128));
+
+    suite.advance(LOCK_PERIOD);
+    suite.execute(KEEPER, ExecuteMsg::Unlock {}, &[]).unwrap();
+    let nav: Nav = suite.query(QueryMsg::Nav {});
+    assert_eq!(nav.locked_b_luna, Uint128::zero());
+    assert_eq!(nav.unlocked_b_luna, Uint128::from(108_900u128));
+    suite.execute(KEEPER, swap_all(), &[]).unwrap();
+    assert_eq!(suite.token_balance(B_LUNA, VAULT), 0);
+}
@@ -0,0 +1,177 @@
+use cosmwasm_std::testing::{mock_dependencies, mock_env};
+use cosmwasm_std::{from_binary, Addr, Api, Storage, Uint128, Uint256};
+use cw2::{get_contract_version, set_contract_version};
+use cw20::{BalanceResponse, TokenInfoResponse};
+use cw_storage_plus::Map;
+
+use terra_deposit_withdraw::contract::{migrate, query};
+use terra_deposit_withdraw::msg::{
+    BidStrategy, CumulativeLoanAmount, MigrateMsg, PermissionsResponse, QueryMsg,
+};
+use terra_deposit_withdraw::state::{Role, STATE};
+use terra_deposit_withdraw::ContractError;
+
+const CONTRACT_NAME: &str = "crates.io:terra-deposit-withdraw";
+
+// State as stored by v0.1.0
+const STATE_V0_1_0: &str = r#"{
+    "owner": "owner",
+    "total_supply": "3000000",
+    "locked_b_luna": "120",
+    "swap_wallet": "swap_wallet",
+    "paused": true,
+    "collateral_token": "b_luna",
+    "price_oracle": "oracle",
+    "astroport_router": "router",
+    "anchor_market": "anchor",
+    "a_ust": "a_ust",
+    "kujira_a_ust_vault": "kujira",
+    "lock_period": 1209600,
+    "withdraw_lock": 3600,
+    "bid_strategy": {
+        "activate_at": { "ltv": 99, "cumulative_value": "1000000000000" },
+        "deactivate_at": { "ltv": 98, "cumulative_value": "100000000000" }
+    }
+}"#;
+
+const BALANCES_V0_1_0: Map<&[u8], Uint128> = Map::new("balance");
+
+// Permissions as stored by v0.1.0
+const PERMISSIONS_V0_1_0: [(&str, &str); 3] = [
+    ("owner", r#"{"submit_bid":true}"#),
+    ("bidder", r#"{"submit_bid":true}"#),
+    ("revoked", r#"{"submit_bid":false}"#),
+];
+
+#[test]
+fn migrate_v0_1_0_state() {
+    let mut deps = mock_dependencies(&[]);
+    set_contract_version(&mut deps.storage, CONTRACT_NAME, "0.1.0").unwrap();
+    deps.storage.set(b"state", STATE_V0_1_0.as_bytes());
+    for (address, balance) in [("alice", 1_000_000u128), ("bob", 2_000_000u128)] {
+        let key = deps.api.addr_canonicalize(address).unwrap();
+        BALANCES_V0_1_0
+            .save(&mut deps.storage, key.as_slice(), &Uint128::from(balance))
+            .unwrap();
+    }
+
+    for (address, permission) in PERMISSIONS_V0_1_0 {
+        let key = deps.api.addr_canonicalize(address).unwrap();
+        deps.storage.set(
+            &Map::<&[u8], ()>::new("permission").key(key.as_slice()),
+            permission.as_bytes(),
+        );
+    }
+
+    let res = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap();
+    assert_eq!(res.attributes[1].value, "0.1.0");
+
+    let version = get_contract_version(&deps.storage).unwrap();
+    assert_eq!(version.contract, CONTRACT_NAME);
+    assert_eq!(version.version, env!("CARGO_PKG_VERSION"));
+
+    let state = STATE.load(&deps.storage).unwrap();
+    assert_eq!(state.owner, Addr::unchecked("owner"));
+    assert_eq!(state.locked_b_luna, Uint128::from(120u128));
+    assert!(state.paused.deposit);
+    assert!(!state.paused.withdraw_ust);
+    assert_eq!(state.kujira_a_ust_vault, Addr::unchecked("kujira"));
+    assert_eq!(state.lock_period, 1209600);
+    assert_eq!(
+        state.bid_strategy,
+        BidStrategy {
+            activate_at: CumulativeLoanAmount {
+                ltv: 99,
+                cumulative_value: Uint256::from(1_000_000_000_000u128),
+            },
+            deactivate_at: CumulativeLoanAmount {
+                ltv: 98,
+                cumulative_value: Uint256::from(100_000_000_000u128),
+            },
+        }
+    );
+    assert_eq!(state.b_luna_discount_bps, 0);
+    assert_eq!(state.max_price_age, 3600);
+    assert_eq!(state.max_premium_slot, 30);
+    assert_eq!(state.timelock_delay, 2 * 24 * 60 * 60);
+    assert_eq!(state.swap_tolerance_bps, 100);
+    assert_eq!(state.swap_route.len(), 2);
+    assert_eq!(state.astroport_pair, None);
+    assert_eq!(state.max_swap_amount, None);
+    assert_eq!(state.swap_cooldown, 0);
+    assert_eq!(state.next_withdrawal_id, 1);
+    assert_eq!(state.next_fill_id, 1);
+    assert!(!state.shutdown);
+    assert_eq!(state.min_b_luna_price, None);
+    assert_eq!(state.fee_collector, Addr::unchecked("owner"));
+    assert_eq!(state.management_fee_bps, 0);
+
+    let res: PermissionsResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::ListPermissions {
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let mut permissions = res
+        .permissions
+        .into_iter()
+        .map(|permission| (permission.address, permission.roles))
+        .collect::<Vec<_>>();
+    permissions.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(
+        permissions,
+        vec![
+            ("bidder".to_string(), vec![Role::Bidder]),
+            ("owner".to_string(), vec![Role::Admin]),
+        ]
+    );
+
+    let token_info: TokenInfoResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::TokenInfo {}).unwrap()).unwrap();
+    assert_eq!(token_info.total_supply, Uint128::from(3_000_000u128));
+    assert_eq!(token_info.decimals, 6);
+
+    for (address, balance) in [("alice", 1_000_000u128), ("bob", 2_000_000u128)] {
+        let res: BalanceResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::Balance {
+                    address: address.to_string(),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(res.balance, Uint128::from(balance));
+        let key = deps.api.addr_canonicalize(address).unwrap();
+        assert_eq!(
+            BALANCES_V0_1_0
+                .may_load(&deps.storage, key.as_slice())
+                .unwrap(),
+            None
+        );
+    }
+}
+
+#[test]
+fn migrate_refuses_downgrade() {
+    let mut deps = mock_dependencies(&[]);
+    set_contract_version(&mut deps.storage, CONTRACT_NAME, "99.0.0").unwrap();
+    let err = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap_err();
+    assert!(matches!(err, ContractError::Downgrade { .. }));
+}
+
+#[test]
+fn migrate_refuses_other_contract() {
+    let mut deps = mock_dependencies(&[]);
+    set_contract_version(&mut deps.storage, "crates.io:cw20-base", "0.1.0").unwrap();
+    let err = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap_err();
+    assert!(matches!(err, ContractError::InvalidContract { .. }));
+}
@@ -0,0 +1,141 @@
+mod common;
+
+use cosmwasm_std::{Decimal256, Uint128};
+use cw20::BalanceResponse;
+use std::str::FromStr;
+
+use common::*;
+use terra_deposit_withdraw::msg::{ExecuteMsg, QueryMsg, TotalCapResponse};
+use terra_deposit_withdraw::nav::Nav;
+
+fn share_of(suite: &Suite, address: &str) -> u128 {
+    let res: BalanceResponse = suite.query(QueryMsg::Balance {
+        address: address.to_string(),
+    });
+    res.balance.u128()
+}
+
+fn total_cap(suite: &Suite) -> u128 {
+    let res: TotalCapResponse = suite.query(QueryMsg::TotalCap {});
+    res.total_cap.u128()
+}
+
+fn submit_bid(suite: &mut Suite, amount: u128, premium_slot: u8) {
+    suite
+        .execute(
+            OWNER,
+            ExecuteMsg::SubmitBid {
+                amount: Uint128::from(amount),
+                premium_slot,
+                strategy: None,
+                preset: None,
+            },
+            &[],
+        )
+        .unwrap();
+}
+
+// Spreads the vault over every place its value can sit in
+fn spread_suite() -> Suite {
+    let mut suite = Suite::new();
+    suite.deposit("alice", 10_000_000).unwrap();
+    suite.deposit("bob", 2_000_000).unwrap();
+    suite.advance(WITHDRAW_LOCK + 1);
+    for (owner, share) in [("bob", 300_000u128), ("alice", 1_000_000)] {
+        suite
+            .execute(
+                owner,
+                ExecuteMsg::RequestWithdrawal {
+                    share: Uint128::from(share),
+                },
+                &[],
+            )
+            .unwrap();
+    }
+    // Carol's UST fills bob's request and leaves the rest idle
+    suite.deposit("carol", 500_000).unwrap();
+    suite
+        .execute(KEEPER, ExecuteMsg::ProcessWithdrawals { limit: None }, &[])
+        .unwrap();
+
+    submit_bid(&mut suite, 4_000_000, 5);
+    submit_bid(&mut suite, 2_000_000, 6);
+    let idx = suite.bids()[0].idx;
+    suite.activate_bid(idx);
+    suite.liquidate_bid(idx, 1_000_000, 22_000);
+    suite
+        .execute(KEEPER, ExecuteMsg::ClaimLiquidation {}, &[])
+        .unwrap();
+    suite.liquidate_bid(idx, 1_000_000, 20_000);
+    suite.mint_token(B_LUNA, VAULT, 10_000);
+
+    suite.set_a_ust_rate("1.1");
+    suite.set_b_luna_price("40");
+    suite
+}
+
+#[test]
+fn nav_breakdown() {
+    let suite = spread_suite();
+    let nav: Nav = suite.query(QueryMsg::Nav {});
+    assert_eq!(
+        nav,
+        Nav {
+            idle_ust: Uint128::from(200_000u128),
+            a_ust: Uint128::from(6_000_000u128),
+            a_ust_in_bids: Uint128::from(2_000_000u128),
+            ust_in_active_bids: Uint128::from(2_000_000u128),
+            pending_b_luna: Uint128::from(20_000u128),
+            locked_b_luna: Uint128::from(22_000u128),
+            unlocked_b_luna: Uint128::from(10_000u128),
+            a_ust_exchange_rate: Decimal256::from_str("1.1").unwrap(),
+            b_luna_price: Decimal256::from_str("40").unwrap(),
+            b_luna_price_updated_at: suite.env().block.time.seconds(),
+            reserved_ust: Uint128::from(300_000u128),
+            queued_ust: Uint128::from(1_000_000u128),
+        }
+    );
+    // Reserved UST stays in the vault until claimed but isn't part of the NAV
+    assert_eq!(suite.native_balance(VAULT), 500_000);
+
+    // 52_000 bLuna at 40 + 8_000_000 aUST at 1.1 + 2_200_000 UST, less the 1_000_000 UST queued
+    assert_eq!(nav.total_b_luna(), Uint128::from(52_000u128));
+    assert_eq!(nav.total_a_ust(), Uint128::from(8_000_000u128));
+    assert_eq!(nav.total_ust(), Uint128::from(2_200_000u128));
+    assert_eq!(nav.free_ust(), Uint128::zero());
+    assert_eq!(nav.gross_cap().unwrap(), Uint128::from(13_080_000u128));
+    assert_eq!(nav.total_cap().unwrap(), Uint128::from(12_080_000u128));
+    assert_eq!(total_cap(&suite), 12_080_000);
+}
+
+#[test]
+fn share_price_follows_total_cap() {
+    let mut suite = spread_suite();
+    // Alice's escrowed shares are owed by the queue, bob's filled request burned his
+    assert_eq!(share_of(&suite, VAULT), 1_000_000);
+    assert_eq!(share_of(&suite, "alice"), 9_000_000);
+    assert_eq!(share_of(&suite, "bob"), 1_700_000);
+    assert_eq!(share_of(&suite, "carol"), 500_000);
+    let outstanding = 11_200_000u128;
+
+    // 1_208_000 UST is a tenth of the total cap
+    suite.deposit("dave", 1_208_000).unwrap();
+    assert_eq!(share_of(&suite, "dave"), outstanding / 10);
+    // The 408_000 UST beyond the queue goes to Anchor, rounding down to whole aUST
+    assert_eq!(total_cap(&suite), 13_287_999);
+
+    // And withdraws at the same price
+    suite.advance(WITHDRAW_LOCK + 1);
+    suite
+        .execute(
+            "dave",
+            ExecuteMsg::WithdrawUst {
+                share: Uint128::from(outstanding / 10),
+                min_amount_out: Some(Uint128::from(1_207_999u128)),
+            },
+            &[],
+        )
+        .unwrap();
+    assert_eq!(suite.native_balance("dave"), 1_207_999);
+    assert_eq!(total_cap(&suite), 12_080_000);
+}
@@ -0,0 +1,366 @@
+mod common;
+
+use cosmwasm_std::{
+    to_binary, Addr, Binary, Deps, DepsMut, Empty, Env, MessageInfo, Response, StdError, StdResult,
+    Uint128,
+};
+use cw20::{
+    AllAccountsResponse, AllAllowancesResponse, AllowanceResponse, BalanceResponse, Cw20ReceiveMsg,
+    Expiration, TokenInfoResponse,
+};
+use cw_multi_test::{ContractWrapper, Executor};
+use cw_storage_plus::Item;
+use serde::{Deserialize, Serialize};
+
+use common::*;
+use terra_deposit_withdraw::msg::{ExecuteMsg, QueryMsg, TotalCapResponse};
+use terra_deposit_withdraw::ContractError;
+
+const RECEIVER: &str = "Contract #9";
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+enum ReceiverMsg {
+    Receive(Cw20ReceiveMsg),
+}
+
+// Token and hook of every share send the receiver got
+const RECEIVED: Item<Vec<(Addr, Cw20ReceiveMsg)>> = Item::new("received");
+
+fn receiver_execute(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    msg: ReceiverMsg,
+) -> StdResult<Response> {
+    let ReceiverMsg::Receive(receive) = msg;
+    let mut received = RECEIVED.may_load(deps.storage)?.unwrap_or_default();
+    received.push((info.sender, receive));
+    RECEIVED.save(deps.storage, &received)?;
+    Ok(Response::new())
+}
+
+fn receiver_instantiate(
+    _deps: DepsMut,
+    _env: Env,
+    _info: MessageInfo,
+    _msg: Empty,
+) -> StdResult<Response> {
+    Ok(Response::new())
+}
+
+fn receiver_query(deps: Deps, _env: Env, _msg: Empty) -> StdResult<Binary> {
+    to_binary(&RECEIVED.may_load(deps.storage)?.unwrap_or_default())
+}
+
+// A contract accepting share sends, deployed after the vault
+fn deploy_receiver(suite: &mut Suite) {
+    let code_id = suite.app.store_code(Box::new(ContractWrapper::new(
+        receiver_execute,
+        receiver_instantiate,
+        receiver_query,
+    )));
+    let address = suite
+        .app
+        .instantiate_contract(
+            code_id,
+            Addr::unchecked(OWNER),
+            &Empty {},
+            &[],
+            "receiver",
+            None,
+        )
+        .unwrap();
+    assert_eq!(address, RECEIVER);
+}
+
+fn received(suite: &Suite) -> Vec<(Addr, Cw20ReceiveMsg)> {
+    suite
+        .app
+        .wrap()
+        .query_wasm_smart(RECEIVER, &Empty {})
+        .unwrap()
+}
+
+fn share_of(suite: &Suite, address: &str) -> u128 {
+    let res: BalanceResponse = suite.query(QueryMsg::Balance {
+        address: address.to_string(),
+    });
+    res.balance.u128()
+}
+
+fn total_supply(suite: &Suite) -> u128 {
+    let res: TokenInfoResponse = suite.query(QueryMsg::TokenInfo {});
+    res.total_supply.u128()
+}
+
+fn allowance(suite: &Suite, owner: &str, spender: &str) -> AllowanceResponse {
+    suite.query(QueryMsg::Allowance {
+        owner: owner.to_string(),
+        spender: spender.to_string(),
+    })
+}
+
+fn increase_allowance(spender: &str, amount: u128, expires: Option<Expiration>) -> ExecuteMsg {
+    ExecuteMsg::IncreaseAllowance {
+        spender: spender.to_string(),
+        amount: Uint128::from(amount),
+        expires,
+    }
+}
+
+fn transfer_from(owner: &str, recipient: &str, amount: u128) -> ExecuteMsg {
+    ExecuteMsg::TransferFrom {
+        owner: owner.to_string(),
+        recipient: recipient.to_string(),
+        amount: Uint128::from(amount),
+    }
+}
+
+#[test]
+fn shares_are_minted_and_burned_with_deposits() {
+    let mut suite = Suite::with_msg(|msg| {
+        msg.share_name = Some("Vault Share".to_string());
+        msg.share_symbol = Some("vSHARE".to_string());
+    });
+    let res: TokenInfoResponse = suite.query(QueryMsg::TokenInfo {});
+    assert_eq!(
+        res,
+        TokenInfoResponse {
+            name: "Vault Share".to_string(),
+            symbol: "vSHARE".to_string(),
+            decimals: 6,
+            total_supply: Uint128::zero(),
+        }
+    );
+
+    suite.deposit("bob", 500_000).unwrap();
+    suite.deposit("alice", 1_000_000).unwrap();
+    assert_eq!(share_of(&suite, "alice"), 1_000_000);
+    assert_eq!(share_of(&suite, "bob"), 500_000);
+    assert_eq!(total_supply(&suite), 1_500_000);
+    let res: AllAccountsResponse = suite.query(QueryMsg::AllAccounts {
+        start_after: None,
+        limit: None,
+    });
+    assert_eq!(res.accounts, ["alice", "bob"]);
+    let res: AllAccountsResponse = suite.query(QueryMsg::AllAccounts {
+        start_after: Some("alice".to_string()),
+        limit: Some(1),
+    });
+    assert_eq!(res.accounts, ["bob"]);
+
+    // Withdrawals burn the redeemed shares
+    suite.advance(WITHDRAW_LOCK + 1);
+    suite
+        .execute(
+            "bob",
+            ExecuteMsg::WithdrawUst {
+                share: Uint128::from(200_000u128),
+                min_amount_out: None,
+            },
+            &[],
+        )
+        .unwrap();
+    assert_eq!(share_of(&suite, "bob"), 300_000);
+    assert_eq!(total_supply(&suite), 1_300_000);
+
+    // Burning shares leaves their value to the other holders
+    suite
+        .execute(
+            "alice",
+            ExecuteMsg::Burn {
+                amount: Uint128::from(100_000u128),
+            },
+            &[],
+        )
+        .unwrap();
+    assert_eq!(share_of(&suite, "alice"), 900_000);
+    assert_eq!(total_supply(&suite), 1_200_000);
+    let res: TotalCapResponse = suite.query(QueryMsg::TotalCap {});
+    assert_eq!(res.total_cap, Uint128::from(1_300_000u128));
+    suite
+        .execute(
+            "bob",
+            ExecuteMsg::WithdrawUst {
+                share: Uint128::from(300_000u128),
+                min_amount_out: None,
+            },
+            &[],
+        )
+        .unwrap();
+    assert_eq!(suite.native_balance("bob"), 200_000 + 325_000);
+}
+
+#[test]
+fn allowances_and_transfers_from() {
+    let mut suite = Suite::new();
+    suite.deposit("alice", 1_000_000).unwrap();
+    suite.advance(WITHDRAW_LOCK + 1);
+    let height = suite.env().block.height;
+
+    suite
+        .execute("alice", increase_allowance("bob", 300_000, None), &[])
+        .unwrap();
+    suite
+        .execute(
+            "alice",
+            increase_allowance("carol", 100_000, Some(Expiration::AtHeight(height + 10))),
+            &[],
+        )
+        .unwrap();
+    suite
+        .execute(
+            "alice",
+            ExecuteMsg::DecreaseAllowance {
+                spender: "bob".to_string(),
+                amount: Uint128::from(50_000u128),
+                expires: None,
+            },
+            &[],
+        )
+        .unwrap();
+    assert_eq!(
+        allowance(&suite, "alice", "bob"),
+        AllowanceResponse {
+            allowance: Uint128::from(250_000u128),
+            expires: Expiration::Never {},
+        }
+    );
+    let res: AllAllowancesResponse = suite.query(QueryMsg::AllAllowances {
+        owner: "alice".to_string(),
+        start_after: None,
+        limit: None,
+    });
+    let allowances: Vec<_> = res
+        .allowances
+        .iter()
+        .map(|info| (info.spender.as_str(), info.allowance.u128()))
+        .collect();
+    assert_eq!(allowances, [("bob", 250_000), ("carol", 100_000)]);
+
+    // Spenders move shares up to their allowance
+    suite
+        .execute("bob", transfer_from("alice", "dave", 200_000), &[])
+        .unwrap();
+    assert_eq!(share_of(&suite, "alice"), 800_000);
+    assert_eq!(share_of(&suite, "dave"), 200_000);
+    assert_eq!(allowance(&suite, "alice", "bob").allowance.u128(), 50_000);
+    let err = suite
+        .execute("bob", transfer_from("alice", "dave", 50_001), &[])
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::Cw20(cw20_base::ContractError::Std(StdError::Overflow { .. }))
+    ));
+    let err = suite
+        .execute("dave", transfer_from("alice", "dave", 1), &[])
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::Cw20(cw20_base::ContractError::NoAllowance {})
+    ));
+
+    // Burning from an allowance burns the owner's shares
+    suite
+        .execute(
+            "bob",
+            ExecuteMsg::BurnFrom {
+                owner: "alice".to_string(),
+                amount: Uint128::from(50_000u128),
+            },
+            &[],
+        )
+        .unwrap();
+    assert_eq!(share_of(&suite, "alice"), 750_000);
+    assert_eq!(total_supply(&suite), 950_000);
+    assert_eq!(allowance(&suite, "alice", "bob").allowance, Uint128::zero());
+
+    // Allowances expire
+    suite.advance(60);
+    let err = suite
+        .execute(
+            "carol",
+            ExecuteMsg::BurnFrom {
+                owner: "alice".to_string(),
+                amount: Uint128::from(1u128),
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::Cw20(cw20_base::ContractError::Expired {})
+    ));
+
+    // Spenders can't move shares out of the owner's withdraw lock either
+    suite
+        .execute("alice", increase_allowance("bob", 100_000, None), &[])
+        .unwrap();
+    suite.deposit("alice", 1_000).unwrap();
+    let err = suite
+        .execute("bob", transfer_from("alice", "dave", 100_000), &[])
+        .unwrap_err();
+    assert!(matches!(err, ContractError::WithdrawLocked { .. }));
+}
+
+#[test]
+fn shares_are_sent_with_a_receive_hook() {
+    let mut suite = Suite::new();
+    deploy_receiver(&mut suite);
+    suite.deposit("alice", 1_000_000).unwrap();
+    suite.advance(WITHDRAW_LOCK + 1);
+
+    suite
+        .execute(
+            "alice",
+            ExecuteMsg::Send {
+                contract: RECEIVER.to_string(),
+                amount: Uint128::from(100_000u128),
+                msg: to_binary("hook").unwrap(),
+            },
+            &[],
+        )
+        .unwrap();
+    suite
+        .execute("alice", increase_allowance("bob", 50_000, None), &[])
+        .unwrap();
+    suite
+        .execute(
+            "bob",
+            ExecuteMsg::SendFrom {
+                owner: "alice".to_string(),
+                contract: RECEIVER.to_string(),
+                amount: Uint128::from(50_000u128),
+                msg: to_binary("hook from").unwrap(),
+            },
+            &[],
+        )
+        .unwrap();
+    assert_eq!(share_of(&suite, "alice"), 850_000);
+    assert_eq!(share_of(&suite, RECEIVER), 150_000);
+    assert_eq!(allowance(&suite, "alice", "bob").allowance, Uint128::zero());
+
+    // The hook comes from the vault and names whoever sent the shares
+    assert_eq!(
+        received(&suite),
+        [
+            (
+                Addr::unchecked(VAULT),
+                Cw20ReceiveMsg {
+                    sender: "alice".to_string(),
+                    amount: Uint128::from(100_000u128),
+                    msg: to_binary("hook").unwrap(),
+                }
+            ),
+            (
+                Addr::unchecked(VAULT),
+                Cw20ReceiveMsg {
+                    sender: "bob".to_string(),
+                    amount: Uint128::from(50_000u128),
+                    msg: to_binary("hook from").unwrap(),
+                }
+            ),
+        ]
+    );
+}
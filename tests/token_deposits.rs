@@ -0,0 +1,168 @@
+mod common;
+
+use cosmwasm_std::{attr, to_binary, Binary, Uint128};
+use cw20::{BalanceResponse, Cw20ReceiveMsg};
+
+use common::*;
+use terra_deposit_withdraw::msg::{ExecuteMsg, QueryMsg, ReceiveMsg, TimestampResponse};
+use terra_deposit_withdraw::nav::Nav;
+use terra_deposit_withdraw::ContractError;
+
+fn share_of(suite: &Suite, address: &str) -> u128 {
+    let res: BalanceResponse = suite.query(QueryMsg::Balance {
+        address: address.to_string(),
+    });
+    res.balance.u128()
+}
+
+fn deposit_msg(min_shares_out: Option<u128>) -> Binary {
+    to_binary(&ReceiveMsg::Deposit {
+        min_shares_out: min_shares_out.map(Uint128::from),
+    })
+    .unwrap()
+}
+
+#[test]
+fn a_ust_deposits_mint_at_the_exchange_rate() {
+    let mut suite = Suite::new();
+    suite.deposit("alice", 1_000_000).unwrap();
+    suite.set_a_ust_rate("1.25");
+    suite.mint_token(A_UST, "bob", 800_000);
+
+    // 800_000 aUST is worth 1_000_000 UST, which buys 800_000 shares at 1.25 UST each
+    let err = suite
+        .send_token(A_UST, "bob", 800_000, deposit_msg(Some(800_001)))
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::SlippageExceeded { minimum, actual }
+            if minimum == Uint128::from(800_001u128) && actual == Uint128::from(800_000u128)
+    ));
+    assert_eq!(suite.token_balance(A_UST, "bob"), 800_000);
+    let res = suite
+        .send_token(A_UST, "bob", 800_000, deposit_msg(Some(800_000)))
+        .unwrap();
+    let vault_attributes = attributes(&res, VAULT);
+    assert!(vault_attributes.contains(&attr("action", "deposit_a_ust")));
+    assert!(vault_attributes.contains(&attr("value", "1000000")));
+    assert!(vault_attributes.contains(&attr("share", "800000")));
+    assert_eq!(share_of(&suite, "bob"), 800_000);
+
+    // The aUST is kept as is, nothing goes through Anchor
+    assert!(attributes(&res, ANCHOR).is_empty());
+    assert_eq!(suite.token_balance(A_UST, VAULT), 1_800_000);
+    assert_eq!(suite.native_balance(VAULT), 0);
+
+    // aUST deposits start the withdraw lock
+    let withdraw = ExecuteMsg::WithdrawUst {
+        share: Uint128::from(800_000u128),
+        min_amount_out: None,
+    };
+    let err = suite.execute("bob", withdraw.clone(), &[]).unwrap_err();
+    assert!(matches!(err, ContractError::WithdrawLocked { .. }));
+    suite.advance(WITHDRAW_LOCK + 1);
+    suite.execute("bob", withdraw, &[]).unwrap();
+    assert_eq!(suite.native_balance("bob"), 1_000_000);
+}
+
+#[test]
+fn only_the_configured_a_ust_is_accepted() {
+    let mut suite = Suite::new();
+    suite.deposit("alice", 1_000_000).unwrap();
+
+    // The hook has to come from the aUST contract itself, not just name it
+    let err = suite
+        .execute(
+            "mallory",
+            ExecuteMsg::Receive(Cw20ReceiveMsg {
+                sender: A_UST.to_string(),
+                amount: Uint128::from(1_000_000u128),
+                msg: deposit_msg(None),
+            }),
+            &[],
+        )
+        .unwrap_err();
+    assert!(matches!(err, ContractError::UnsupportedToken { token } if token == "mallory"));
+    suite.mint_token(OTHER_TOKEN, "mallory", 1_000_000);
+    let err = suite
+        .send_token(OTHER_TOKEN, "mallory", 1_000_000, deposit_msg(None))
+        .unwrap_err();
+    assert!(matches!(err, ContractError::UnsupportedToken { token } if token == OTHER_TOKEN));
+    assert_eq!(share_of(&suite, "mallory"), 0);
+    assert_eq!(suite.token_balance(OTHER_TOKEN, "mallory"), 1_000_000);
+}
+
+#[test]
+fn b_luna_deposits_mint_at_the_discounted_oracle_price() {
+    let mut suite = Suite::with_msg(|msg| msg.max_price_age = Some(600));
+    suite.deposit("alice", 1_000_000).unwrap();
+
+    let err = suite
+        .execute(
+            OWNER,
+            update_config(|msg| msg.b_luna_discount_bps = Some(10_001)),
+            &[],
+        )
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::InvalidBps { name, bps: 10_001 } if name == "b_luna_discount_bps"
+    ));
+    suite
+        .execute(
+            OWNER,
+            update_config(|msg| msg.b_luna_discount_bps = Some(500)),
+            &[],
+        )
+        .unwrap();
+    suite.mint_token(B_LUNA, "carol", 20_000);
+
+    // A stale price can't value the deposit
+    suite.advance_without_price(601);
+    let err = suite
+        .send_token(B_LUNA, "carol", 20_000, deposit_msg(None))
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::StalePrice { max_age: 600, .. }
+    ));
+    assert_eq!(suite.token_balance(B_LUNA, "carol"), 20_000);
+
+    // 20_000 bLuna at 40 UST less 5% is worth 760_000 UST
+    suite.set_b_luna_price("40");
+    let err = suite
+        .send_token(B_LUNA, "carol", 20_000, deposit_msg(Some(760_001)))
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::SlippageExceeded { minimum, actual }
+            if minimum == Uint128::from(760_001u128) && actual == Uint128::from(760_000u128)
+    ));
+    let res = suite
+        .send_token(B_LUNA, "carol", 20_000, deposit_msg(Some(760_000)))
+        .unwrap();
+    let vault_attributes = attributes(&res, VAULT);
+    assert!(vault_attributes.contains(&attr("action", "deposit_b_luna")));
+    assert!(vault_attributes.contains(&attr("value", "760000")));
+    assert_eq!(share_of(&suite, "carol"), 760_000);
+    let nav: Nav = suite.query(QueryMsg::Nav {});
+    assert_eq!(nav.unlocked_b_luna, Uint128::from(20_000u128));
+    assert_eq!(nav.locked_b_luna, Uint128::zero());
+
+    // bLuna deposits start the withdraw lock like UST ones
+    let res: TimestampResponse = suite.query(QueryMsg::LastDepositTimestamp {
+        address: "carol".to_string(),
+    });
+    assert_eq!(res.timestamp, suite.env().block.time);
+    let withdraw = ExecuteMsg::WithdrawBLuna {
+        share: Uint128::from(760_000u128),
+        min_amount_out: None,
+    };
+    let err = suite.execute("carol", withdraw.clone(), &[]).unwrap_err();
+    assert!(matches!(err, ContractError::WithdrawLocked { .. }));
+    suite.advance(WITHDRAW_LOCK + 1);
+
+    // 760_000 of the 1_760_000 shares, the vault being worth 45_000 bLuna at 40
+    suite.execute("carol", withdraw, &[]).unwrap();
+    assert_eq!(suite.token_balance(B_LUNA, "carol"), 19_431);
+}